@@ -0,0 +1,54 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use engula_client::{List, Universe};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("queue").await?;
+    let co = db.create_collection("queue").await?;
+
+    let producer = {
+        let co = co.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            co.mutate::<()>("jobs", List::rpush([1, 2, 3])).await
+        })
+    };
+
+    let jobs: Vec<i64> = co
+        .mutate("jobs", List::blpop(1, Duration::from_secs(1)))
+        .await?;
+    println!("jobs.blpop(1) = {:?}", jobs);
+    producer.await??;
+
+    let jobs: Vec<i64> = co.mutate("jobs", List::rpoplpush("processing")).await?;
+    println!("jobs.rpoplpush(processing) = {:?}", jobs);
+    let jobs: Vec<i64> = co.get("jobs").await?;
+    println!("jobs = {:?}", jobs);
+    let processing: Vec<i64> = co.get("processing").await?;
+    println!("processing = {:?}", processing);
+
+    let empty: Option<Vec<i64>> = co
+        .mutate("empty", List::brpop(1, Duration::from_millis(100)))
+        .await?;
+    println!("empty.brpop(1) = {:?}", empty);
+
+    Ok(())
+}
@@ -12,20 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use engula_apis::v1::{engula_client::EngulaClient, *};
-use tonic::transport::Channel;
+use engula_cooperator::apis::{self as coapis, cooperator_client::CooperatorClient};
+use tonic::transport::{Channel, Endpoint};
 
 use crate::{Error, Result};
 
+/// The response of a database request executed in best-effort mode.
+pub struct BestEffortResponse {
+    /// The values of objects, where failed objects have empty values.
+    pub response: DatabaseResponse,
+    /// The errors of failed objects, keyed by the index of the collection
+    /// request, the index of the expression and the object id.
+    pub errors: HashMap<(usize, usize, Vec<u8>), Error>,
+}
+
 #[derive(Clone)]
 pub struct Client {
     client: EngulaClient<Channel>,
+    cooperator: CooperatorClient<Channel>,
 }
 
 impl Client {
     pub async fn connect(url: impl Into<String>) -> Result<Self> {
-        let client = EngulaClient::connect(url.into()).await?;
-        Ok(Self { client })
+        let endpoint = Endpoint::from_shared(url.into())
+            .map_err(|e| Error::invalid_argument(e.to_string()))?;
+        let channel = endpoint.connect().await?;
+        Ok(Self {
+            client: EngulaClient::new(channel.clone()),
+            cooperator: CooperatorClient::new(channel),
+        })
     }
 
     pub async fn batch(&self, req: BatchRequest) -> Result<BatchResponse> {
@@ -58,4 +76,75 @@ impl Client {
             .and_then(|x| x.response)
             .ok_or_else(|| Error::internal("missing universe response"))
     }
+
+    /// Executes a database request and returns the versions of objects read
+    /// by select expressions.
+    pub async fn database_with_versions(
+        &self,
+        req: DatabaseRequest,
+    ) -> Result<(DatabaseResponse, Vec<coapis::ObjectVersion>)> {
+        let req = coapis::BatchRequest {
+            databases: vec![req],
+            best_effort: false,
+        };
+        let mut res = self.cooperator.clone().batch(req).await?.into_inner();
+        let database = res
+            .databases
+            .pop()
+            .ok_or_else(|| Error::internal("missing database response"))?;
+        Ok((database, res.versions))
+    }
+
+    /// Executes a database request in best-effort mode.
+    ///
+    /// Failed objects get empty values in the response, and their errors are
+    /// returned separately.
+    pub async fn database_best_effort(&self, req: DatabaseRequest) -> Result<BestEffortResponse> {
+        // The ids of objects in each expression, to key the errors.
+        let ids: Vec<Vec<Vec<Vec<u8>>>> = req
+            .requests
+            .iter()
+            .map(|r| r.exprs.iter().map(|e| e.batch.clone()).collect())
+            .collect();
+        let req = coapis::BatchRequest {
+            databases: vec![req],
+            best_effort: true,
+        };
+        let mut res = self.cooperator.clone().batch(req).await?.into_inner();
+        let response = res
+            .databases
+            .pop()
+            .ok_or_else(|| Error::internal("missing database response"))?;
+        let mut errors = HashMap::new();
+        for err in res.errors {
+            let (collection, expr) = (err.collection as usize, err.expr as usize);
+            let id = ids
+                .get(collection)
+                .and_then(|x| x.get(expr))
+                .and_then(|x| x.get(err.object as usize))
+                .ok_or_else(|| Error::internal("invalid object error"))?;
+            let code = tonic::Code::from_i32(err.code);
+            let status = tonic::Status::new(code, err.message);
+            errors.insert((collection, expr, id.clone()), status.into());
+        }
+        Ok(BestEffortResponse { response, errors })
+    }
+
+    pub async fn set_quota(&self, req: coapis::SetQuotaRequest) -> Result<()> {
+        self.cooperator.clone().set_quota(req).await?;
+        Ok(())
+    }
+
+    pub async fn scan(&self, req: coapis::ScanRequest) -> Result<coapis::ScanResponse> {
+        let res = self.cooperator.clone().scan(req).await?;
+        Ok(res.into_inner())
+    }
+
+    pub async fn watch(
+        &self,
+        req: coapis::WatchRequest,
+    ) -> Result<tonic::Streaming<coapis::WatchResponse>> {
+        let res = self.cooperator.clone().watch(req).await?;
+        Ok(res.into_inner())
+    }
 }
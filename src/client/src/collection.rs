@@ -13,8 +13,12 @@
 // limitations under the License.
 
 use engula_apis::v1::*;
+use engula_cooperator::apis::{
+    ScanRequest, ScanResponse, SetQuotaRequest, WatchRequest, WatchResponse,
+};
+use futures::{Stream, StreamExt};
 
-use crate::{Any, Client, CollectionTxn, Error, Result};
+use crate::{Any, Client, CollectionTxn, Error, Quota, Result};
 
 #[derive(Clone)]
 pub struct Collection {
@@ -63,6 +67,28 @@ impl Collection {
         self.select(id, Any::get()).await
     }
 
+    /// Returns the object and its version.
+    ///
+    /// A zero version means that the object doesn't exist, which can be used
+    /// with [`Self::set_if_version`].
+    pub async fn get_with_version<T: TryFrom<Value>>(
+        &self,
+        id: impl Into<Vec<u8>>,
+    ) -> Result<(T, u64)> {
+        let expr = ObjectExpr {
+            batch: vec![id.into()],
+            select: Some(Any::get().into()),
+            ..Default::default()
+        };
+        let req = self.request(expr);
+        let (res, mut versions) = self.client.database_with_versions(req).await?;
+        let version = versions
+            .pop()
+            .ok_or_else(|| Error::internal("missing object version"))?;
+        let value = take_value(res)?;
+        Ok((value, version.version))
+    }
+
     pub async fn set(&self, id: impl Into<Vec<u8>>, value: impl Into<Value>) -> Result<()> {
         self.mutate(id, Any::set(value)).await
     }
@@ -72,6 +98,93 @@ impl Collection {
         Ok(())
     }
 
+    /// Sets the object if its current version is `version` and returns the
+    /// new version.
+    ///
+    /// A zero `version` means that the object must not exist. Returns
+    /// [`Error::Aborted`] if the version doesn't match.
+    pub async fn set_if_version(
+        &self,
+        id: impl Into<Vec<u8>>,
+        value: impl Into<Value>,
+        version: u64,
+    ) -> Result<u64> {
+        let new_version: i64 = self.mutate(id, Any::set_if_version(value, version)).await?;
+        Ok(new_version as u64)
+    }
+
+    /// Deletes the object if its current version is `version`.
+    ///
+    /// Returns [`Error::Aborted`] if the version doesn't match.
+    pub async fn delete_if_version(&self, id: impl Into<Vec<u8>>, version: u64) -> Result<()> {
+        self.mutate(id, Any::delete_if_version(version)).await
+    }
+
+    /// Scans objects with `prefix` in the order of their ids, starting from
+    /// `start` if it is not empty.
+    ///
+    /// Returns up to `limit` objects, where zero means no limit. If there are
+    /// more objects, the response tells the id to continue from.
+    pub async fn scan(
+        &self,
+        prefix: impl Into<Vec<u8>>,
+        start: impl Into<Vec<u8>>,
+        limit: u32,
+    ) -> Result<ScanResponse> {
+        let req = ScanRequest {
+            dbname: self.dbname.clone(),
+            name: self.name.clone(),
+            prefix: prefix.into(),
+            start: start.into(),
+            limit,
+        };
+        self.client.scan(req).await
+    }
+
+    /// Sets the quota of this collection, where zero limits are unlimited.
+    pub async fn set_quota(&self, quota: Quota) -> Result<()> {
+        let req = SetQuotaRequest {
+            dbname: self.dbname.clone(),
+            name: self.name.clone(),
+            quota: Some(quota),
+        };
+        self.client.set_quota(req).await
+    }
+
+    /// Watches the changes committed to this collection at or after
+    /// `start_version`.
+    ///
+    /// A zero `start_version` watches the changes committed from now on.
+    /// Returns [`Error::DataLoss`] if the changes since `start_version` are
+    /// no longer retained, and [`Error::InvalidArgument`] if it is after the
+    /// next version.
+    pub async fn watch(
+        &self,
+        start_version: u64,
+    ) -> Result<impl Stream<Item = Result<WatchResponse>>> {
+        self.watch_with(Vec::new(), Vec::new(), start_version).await
+    }
+
+    /// Watches the changes to objects with `prefix`.
+    pub async fn watch_prefix(
+        &self,
+        prefix: impl Into<Vec<u8>>,
+        start_version: u64,
+    ) -> Result<impl Stream<Item = Result<WatchResponse>>> {
+        self.watch_with(Vec::new(), prefix.into(), start_version)
+            .await
+    }
+
+    /// Watches the changes to the given objects.
+    pub async fn watch_ids<I: Into<Vec<u8>>>(
+        &self,
+        ids: impl IntoIterator<Item = I>,
+        start_version: u64,
+    ) -> Result<impl Stream<Item = Result<WatchResponse>>> {
+        let ids = ids.into_iter().map(Into::into).collect();
+        self.watch_with(ids, Vec::new(), start_version).await
+    }
+
     pub async fn select<T: TryFrom<Value>>(
         &self,
         id: impl Into<Vec<u8>>,
@@ -99,24 +212,50 @@ impl Collection {
     }
 
     async fn object<T: TryFrom<Value>>(&self, expr: ObjectExpr) -> Result<T> {
+        let req = self.request(expr);
+        let res = self.client.database(req).await?;
+        take_value(res)
+    }
+
+    fn request(&self, expr: ObjectExpr) -> DatabaseRequest {
         let req = CollectionRequest {
             name: self.name.clone(),
             exprs: vec![expr],
         };
-        let req = DatabaseRequest {
+        DatabaseRequest {
             name: self.dbname.clone(),
             requests: vec![req],
+        }
+    }
+
+    async fn watch_with(
+        &self,
+        ids: Vec<Vec<u8>>,
+        prefix: Vec<u8>,
+        start_version: u64,
+    ) -> Result<impl Stream<Item = Result<WatchResponse>>> {
+        let req = WatchRequest {
+            dbname: self.dbname.clone(),
+            name: self.name.clone(),
+            ids,
+            prefix,
+            start_version,
         };
-        let mut res = self.client.database(req).await?;
-        let mut res = res
-            .responses
-            .pop()
-            .ok_or_else(|| Error::internal("missing collection response"))?;
-        let value = res
-            .results
-            .pop()
-            .and_then(|mut r| r.values.pop())
-            .ok_or_else(|| Error::internal("missing expression result"))?;
-        value.try_into().map_err(|_| Error::invalid_conversion())
+        let stream = self.client.watch(req).await?;
+        Ok(stream.map(|res| res.map_err(Error::from)))
     }
 }
+
+/// Takes the value of the only object in a response.
+fn take_value<T: TryFrom<Value>>(mut res: DatabaseResponse) -> Result<T> {
+    let mut res = res
+        .responses
+        .pop()
+        .ok_or_else(|| Error::internal("missing collection response"))?;
+    let value = res
+        .results
+        .pop()
+        .and_then(|mut r| r.values.pop())
+        .ok_or_else(|| Error::internal("missing expression result"))?;
+    value.try_into().map_err(|_| Error::invalid_conversion())
+}
@@ -13,8 +13,9 @@
 // limitations under the License.
 
 use engula_apis::v1::*;
+use engula_cooperator::apis::SetQuotaRequest;
 
-use crate::{Client, Collection, DatabaseTxn, Error, Result};
+use crate::{Client, Collection, DatabaseTxn, Error, Quota, Result};
 
 #[derive(Clone)]
 pub struct Database {
@@ -45,6 +46,16 @@ impl Database {
         desc.ok_or_else(|| Error::internal("missing database descriptor"))
     }
 
+    /// Sets the quota of this database, where zero limits are unlimited.
+    pub async fn set_quota(&self, quota: Quota) -> Result<()> {
+        let req = SetQuotaRequest {
+            dbname: self.name.clone(),
+            quota: Some(quota),
+            ..Default::default()
+        };
+        self.client.set_quota(req).await
+    }
+
     pub fn begin(&self) -> DatabaseTxn {
         DatabaseTxn::new(self.name.clone(), self.client.clone())
     }
@@ -25,6 +25,8 @@ pub enum Error {
     #[error("{0}")]
     Aborted(String),
     #[error("{0}")]
+    ResourceExhausted(String),
+    #[error("{0}")]
     DataLoss(String),
     #[error("{0}")]
     Internal(String),
@@ -45,6 +47,10 @@ impl Error {
         Self::Aborted(m.into())
     }
 
+    pub fn resource_exhausted(m: impl Into<String>) -> Self {
+        Self::ResourceExhausted(m.into())
+    }
+
     pub fn internal(m: impl Into<String>) -> Self {
         Self::Internal(m.into())
     }
@@ -61,6 +67,7 @@ impl From<tonic::Status> for Error {
             tonic::Code::AlreadyExists => Error::AlreadyExists(s.message().into()),
             tonic::Code::InvalidArgument => Error::InvalidArgument(s.message().into()),
             tonic::Code::Aborted => Error::Aborted(s.message().into()),
+            tonic::Code::ResourceExhausted => Error::ResourceExhausted(s.message().into()),
             tonic::Code::DataLoss => Error::DataLoss(s.message().into()),
             tonic::Code::Internal => Error::Internal(s.message().into()),
             _ => Error::Unknown(Box::new(s)),
@@ -75,6 +82,7 @@ impl From<Error> for tonic::Status {
             Error::AlreadyExists(s) => (tonic::Code::AlreadyExists, s),
             Error::InvalidArgument(s) => (tonic::Code::InvalidArgument, s),
             Error::Aborted(s) => (tonic::Code::Aborted, s),
+            Error::ResourceExhausted(s) => (tonic::Code::ResourceExhausted, s),
             Error::DataLoss(s) => (tonic::Code::DataLoss, s),
             Error::Internal(s) => (tonic::Code::Internal, s),
             Error::Unknown(s) => (tonic::Code::Unknown, s.to_string()),
@@ -90,3 +98,26 @@ impl From<tonic::transport::Error> for Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        let err = Error::from(tonic::Status::resource_exhausted("too many requests"));
+        assert!(matches!(err, Error::ResourceExhausted(m) if m == "too many requests"));
+        let err = Error::from(tonic::Status::aborted("version mismatch"));
+        assert!(matches!(err, Error::Aborted(m) if m == "version mismatch"));
+
+        // Codes that clients don't handle keep the status.
+        let err = Error::from(tonic::Status::unavailable("a"));
+        match err {
+            Error::Unknown(err) => {
+                let status = err.downcast_ref::<tonic::Status>().unwrap();
+                assert_eq!(status.code(), tonic::Code::Unavailable);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+}
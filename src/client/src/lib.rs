@@ -20,7 +20,11 @@ mod txn;
 mod types;
 mod universe;
 
+pub use client::BestEffortResponse;
 use client::Client;
+pub use engula_cooperator::apis::{
+    watch_event, Quota, ScanObject, ScanResponse, WatchEvent, WatchResponse,
+};
 
 pub use self::{
     collection::Collection,
@@ -16,7 +16,7 @@ use std::sync::{Arc, Mutex};
 
 use engula_apis::v1::*;
 
-use crate::{Any, Client, Error, Result};
+use crate::{Any, BestEffortResponse, Client, Error, Result};
 
 #[derive(Clone)]
 pub struct DatabaseTxn {
@@ -45,6 +45,21 @@ impl DatabaseTxn {
         inner.client.database(req).await?;
         Ok(())
     }
+
+    /// Commits the transaction in best-effort mode.
+    ///
+    /// Unlike [`Self::commit`], an object that fails doesn't abort the
+    /// others. Returns the values of objects together with the errors of
+    /// failed objects.
+    pub async fn commit_best_effort(self) -> Result<BestEffortResponse> {
+        let inner =
+            Arc::try_unwrap(self.inner).map_err(|_| Error::aborted("pending transactions"))?;
+        let req = DatabaseRequest {
+            name: inner.name,
+            requests: inner.requests.into_inner().unwrap(),
+        };
+        inner.client.database_best_effort(req).await
+    }
 }
 
 struct DatabaseInner {
@@ -137,4 +152,38 @@ impl CollectionTxn {
         handle.client.database(req).await?;
         Ok(())
     }
+
+    /// Commits the transaction in best-effort mode.
+    ///
+    /// Unlike [`Self::commit`], an expression that fails doesn't abort the
+    /// others. Returns the value or the error of each expression.
+    pub async fn commit_best_effort(self) -> Result<Vec<Result<Value>>> {
+        let handle = self.handle.unwrap();
+        let req = DatabaseRequest {
+            name: handle.name,
+            requests: vec![self.request],
+        };
+        let mut res = handle.client.database_best_effort(req).await?;
+        let cores = res
+            .response
+            .responses
+            .pop()
+            .ok_or_else(|| Error::internal("missing collection response"))?;
+        // Each expression of a collection transaction has one object.
+        let mut results: Vec<Result<Value>> = cores
+            .results
+            .into_iter()
+            .map(|mut r| {
+                r.values
+                    .pop()
+                    .ok_or_else(|| Error::internal("missing object value"))
+            })
+            .collect();
+        for ((_, expr, _), err) in res.errors {
+            if let Some(result) = results.get_mut(expr) {
+                *result = Err(err);
+            }
+        }
+        Ok(results)
+    }
 }
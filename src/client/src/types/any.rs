@@ -30,4 +30,12 @@ impl Any {
     pub fn delete() -> Mutate {
         Mutate::default().delete()
     }
+
+    pub fn set_if_version(value: impl Into<Value>, version: u64) -> Mutate {
+        Mutate::default().set_if_version(value, version)
+    }
+
+    pub fn delete_if_version(version: u64) -> Mutate {
+        Mutate::default().delete_if_version(version)
+    }
 }
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ops::RangeBounds;
+use std::{ops::RangeBounds, time::Duration};
 
 use engula_apis::v1::*;
 
@@ -46,6 +46,30 @@ impl List {
         Mutate::default().rpop(count)
     }
 
+    /// Like [`List::lpop`], but waits up to `timeout` for the list to become
+    /// non-empty. A zero timeout doesn't wait.
+    pub fn blpop(count: i64, timeout: Duration) -> Mutate {
+        Mutate::default().blpop(count, timeout)
+    }
+
+    /// Like [`List::rpop`], but waits up to `timeout` for the list to become
+    /// non-empty. A zero timeout doesn't wait.
+    pub fn brpop(count: i64, timeout: Duration) -> Mutate {
+        Mutate::default().brpop(count, timeout)
+    }
+
+    /// Pops the last element and pushes it to the head of `destination` in
+    /// the same collection atomically.
+    pub fn rpoplpush(destination: impl Into<Vec<u8>>) -> Mutate {
+        Mutate::default().rpoplpush(destination, Duration::ZERO)
+    }
+
+    /// Like [`List::rpoplpush`], but waits up to `timeout` for the list to
+    /// become non-empty.
+    pub fn brpoplpush(destination: impl Into<Vec<u8>>, timeout: Duration) -> Mutate {
+        Mutate::default().rpoplpush(destination, timeout)
+    }
+
     pub fn lpush(value: impl Into<ListValue>) -> Mutate {
         Mutate::default().lpush(value.into())
     }
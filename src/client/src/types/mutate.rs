@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use engula_apis::v1::*;
 
 #[derive(Default)]
@@ -34,6 +36,21 @@ impl Mutate {
         self
     }
 
+    /// Sets the object if its current version is `version`, where zero means
+    /// that the object doesn't exist.
+    pub fn set_if_version(mut self, v: impl Into<Value>, version: u64) -> Mutate {
+        self.0.func = MutateFunction::Set as i32;
+        self.0.args = vec![v.into(), version_arg(version)];
+        self
+    }
+
+    /// Deletes the object if its current version is `version`.
+    pub fn delete_if_version(mut self, version: u64) -> Self {
+        self.0.func = MutateFunction::Delete as i32;
+        self.0.args = vec![version_arg(version)];
+        self
+    }
+
     pub fn add(mut self, v: impl Into<Value>) -> Self {
         self.0.func = MutateFunction::Add as i32;
         self.0.args = vec![v.into()];
@@ -58,6 +75,28 @@ impl Mutate {
         self
     }
 
+    pub fn blpop(mut self, count: i64, timeout: Duration) -> Self {
+        self.0.func = MutateFunction::Lpop as i32;
+        self.0.args = vec![count.into(), timeout_millis(timeout).into()];
+        self
+    }
+
+    pub fn brpop(mut self, count: i64, timeout: Duration) -> Self {
+        self.0.func = MutateFunction::Rpop as i32;
+        self.0.args = vec![count.into(), timeout_millis(timeout).into()];
+        self
+    }
+
+    pub fn rpoplpush(mut self, destination: impl Into<Vec<u8>>, timeout: Duration) -> Self {
+        self.0.func = MutateFunction::Rpop as i32;
+        self.0.args = vec![
+            1i64.into(),
+            timeout_millis(timeout).into(),
+            destination.into().into(),
+        ];
+        self
+    }
+
     pub fn lpush(mut self, value: impl Into<Value>) -> Self {
         self.0.func = MutateFunction::Lpush as i32;
         self.0.args = vec![value.into()];
@@ -88,6 +127,14 @@ impl Mutate {
     }
 }
 
+fn version_arg(version: u64) -> Value {
+    i64::try_from(version).unwrap_or(i64::MAX).into()
+}
+
+fn timeout_millis(timeout: Duration) -> i64 {
+    i64::try_from(timeout.as_millis()).unwrap_or(i64::MAX)
+}
+
 impl From<Mutate> for MutateExpr {
     fn from(v: Mutate) -> Self {
         v.0
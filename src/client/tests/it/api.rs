@@ -13,9 +13,11 @@
 // limitations under the License.
 
 use anyhow::Result;
-use engula_client::Blob;
+use engula_apis::v1::Value;
+use engula_client::{watch_event, Blob, Error, Quota, I64};
+use futures::StreamExt;
 
-use crate::create_universe;
+use crate::{create_universe, start_universe};
 
 #[tokio::test]
 #[ignore]
@@ -36,3 +38,166 @@ async fn test_apis() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_versions() -> Result<()> {
+    let uv = start_universe().await?;
+    let db = uv.create_database("versions").await?;
+    let co = db.create_collection("versions").await?;
+
+    let v1 = co.set_if_version("o", 1, 0).await?;
+    assert!(co.set_if_version("o", 2, 0).await.is_err());
+    let (o, v): (i64, u64) = co.get_with_version("o").await?;
+    assert_eq!((1, v1), (o, v));
+    let v2 = co.set_if_version("o", 2, v1).await?;
+    assert!(v2 > v1);
+    let (o, v): (i64, u64) = co.get_with_version("o").await?;
+    assert_eq!((2, v2), (o, v));
+
+    assert!(co.delete_if_version("o", v1).await.is_err());
+    co.delete_if_version("o", v2).await?;
+    let (_, v): (Value, u64) = co.get_with_version("o").await?;
+    assert_eq!(0, v);
+    co.set_if_version("o", 3, 0).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_best_effort() -> Result<()> {
+    let uv = start_universe().await?;
+    let db = uv.create_database("best_effort").await?;
+    let co = db.create_collection("best_effort").await?;
+
+    co.set("blob", Blob::value([1, 2])).await?;
+    let mut txn = co.begin();
+    txn.set("a", 1i64);
+    // Adding to a blob fails.
+    txn.mutate("blob", I64::add(1));
+    txn.set("b", 2i64);
+    let results = txn.commit_best_effort().await?;
+    assert_eq!(3, results.len());
+    assert!(matches!(&results[0], Ok(v) if *v == Value::from(())));
+    assert!(matches!(results[1], Err(Error::InvalidArgument(_))));
+    assert!(results[2].is_ok());
+
+    let a: i64 = co.get("a").await?;
+    assert_eq!(1, a);
+    let b: i64 = co.get("b").await?;
+    assert_eq!(2, b);
+    let blob: Vec<u8> = co.get("blob").await?;
+    assert_eq!(vec![1, 2], blob);
+
+    // Objects that exceed the quota fail alone.
+    co.set_quota(Quota {
+        max_objects: 4,
+        ..Default::default()
+    })
+    .await?;
+    let txn = db.begin();
+    let mut cotxn = txn.collection("best_effort");
+    cotxn.set("c", 3i64);
+    cotxn.set("d", 4i64);
+    cotxn.submit();
+    let mut cotxn = txn.collection("best_effort");
+    cotxn.mutate("a", I64::add(1));
+    cotxn.submit();
+    let res = txn.commit_best_effort().await?;
+    assert_eq!(1, res.errors.len());
+    let err = res.errors.get(&(0, 1, b"d".to_vec()));
+    assert!(matches!(err, Some(Error::ResourceExhausted(_))));
+    assert_eq!(
+        res.response.responses[1].results[0].values[0],
+        Value::from(2i64)
+    );
+    let c: i64 = co.get("c").await?;
+    assert_eq!(3, c);
+    let d: Value = co.get("d").await?;
+    assert_eq!(Value::default(), d);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_watch() -> Result<()> {
+    let uv = start_universe().await?;
+    let db = uv.create_database("watch").await?;
+    let co = db.create_collection("watch").await?;
+
+    let mut all = Box::pin(co.watch(0).await?);
+    let mut prefix = Box::pin(co.watch_prefix("b", 0).await?);
+    co.set("a", 1i64).await?;
+    co.set("b", 2i64).await?;
+    co.delete("a").await?;
+
+    let res = all.next().await.unwrap()?;
+    assert_eq!(b"a".to_vec(), res.events[0].id);
+    let version = res.version;
+    let res = all.next().await.unwrap()?;
+    assert_eq!(b"b".to_vec(), res.events[0].id);
+    assert!(res.version > version);
+    let res = all.next().await.unwrap()?;
+    assert_eq!(watch_event::Type::Delete as i32, res.events[0].r#type);
+
+    let res = prefix.next().await.unwrap()?;
+    assert_eq!(b"b".to_vec(), res.events[0].id);
+
+    // Replays the changes from a version.
+    let mut replay = Box::pin(co.watch_ids(["a"], version).await?);
+    let res = replay.next().await.unwrap()?;
+    assert_eq!(version, res.version);
+    let res = replay.next().await.unwrap()?;
+    assert_eq!(watch_event::Type::Delete as i32, res.events[0].r#type);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scan() -> Result<()> {
+    let uv = start_universe().await?;
+    let db = uv.create_database("scan").await?;
+    let co = db.create_collection("scan").await?;
+
+    for id in ["b2", "a", "b1", "b3"] {
+        co.set(id, id.to_owned()).await?;
+    }
+    let res = co.scan("b", "", 2).await?;
+    let ids: Vec<_> = res.objects.iter().map(|ob| ob.id.clone()).collect();
+    assert_eq!(vec![b"b1".to_vec(), b"b2".to_vec()], ids);
+    assert_eq!(b"b3".to_vec(), res.next);
+    let res = co.scan("b", res.next, 2).await?;
+    assert_eq!(1, res.objects.len());
+    assert_eq!(Some(Value::from("b3".to_owned())), res.objects[0].value);
+    assert!(res.next.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_quota() -> Result<()> {
+    let uv = start_universe().await?;
+    let db = uv.create_database("quota").await?;
+    let co = db.create_collection("quota").await?;
+
+    co.set_quota(Quota {
+        max_objects: 1,
+        ..Default::default()
+    })
+    .await?;
+    co.set("a", 1i64).await?;
+    let res = co.set("b", 1i64).await;
+    assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+
+    // Zero limits are unlimited.
+    co.set_quota(Quota::default()).await?;
+    co.set("b", 1i64).await?;
+    db.set_quota(Quota {
+        max_objects: 2,
+        ..Default::default()
+    })
+    .await?;
+    let res = co.set("c", 1i64).await;
+    assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+
+    Ok(())
+}
@@ -18,6 +18,9 @@ use std::time::Duration;
 
 use anyhow::{Error, Result};
 use engula_client::Universe;
+use engula_transactor::{Server, Transactor};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
 
 async fn create_universe() -> Result<Universe> {
     let interval = option_env!("RETRY_INTERVAL").unwrap_or("1").parse()?;
@@ -34,3 +37,17 @@ async fn create_universe() -> Result<Universe> {
 
     Err(Error::msg(format!("Exceeds retry times: {}", retry)))
 }
+
+/// Starts a server in this process and connects to it.
+async fn start_universe() -> Result<Universe> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let server = Server::new(Transactor::new());
+    let server = tonic::transport::Server::builder()
+        .add_service(server.clone().into_service())
+        .add_service(server.into_cooperator_service())
+        .serve_with_incoming(TcpListenerStream::new(listener));
+    tokio::spawn(server);
+    let uv = Universe::connect(format!("http://{}", addr)).await?;
+    Ok(uv)
+}
@@ -0,0 +1,609 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    env,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use engula_cooperator::{Quota, Quotas};
+use serde::{Deserialize, Serialize};
+use stream_engine_store::{DbOption, LogOption};
+use toml::Value;
+
+/// Environment variables with this prefix override configuration keys.
+///
+/// Sections are separated by `__`, for example,
+/// `ENGULA_STREAM_ENGINE__STORE__LOG_FILE_SIZE` overrides
+/// `stream_engine.store.log_file_size`.
+const ENV_PREFIX: &str = "ENGULA_";
+const ENV_SEPARATOR: &str = "__";
+
+/// The configuration of all server components.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub quotas: QuotasConfig,
+    pub object_engine: ObjectEngineConfig,
+    pub stream_engine: StreamEngineConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub addr: String,
+    /// Starts every component in this process.
+    ///
+    /// Databases, collections and objects are kept in memory, so they are
+    /// lost on restart.
+    pub standalone: bool,
+    /// The root directory of components started in standalone mode.
+    pub data_dir: PathBuf,
+    /// The number of stream-engine stores started in standalone mode.
+    ///
+    /// Stores listen on consecutive ports from `stream_engine.store.addr`.
+    pub num_stores: usize,
+    /// How long servers wait for in-flight requests on shutdown.
+    pub shutdown_timeout_ms: u64,
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: "0.0.0.0:21716".to_owned(),
+            standalone: false,
+            data_dir: PathBuf::from("/tmp/engula"),
+            num_stores: 3,
+            shutdown_timeout_ms: 10_000,
+        }
+    }
+}
+
+/// The default quotas of databases and collections, which apply until a
+/// database or a collection is given its own quota.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotasConfig {
+    pub database: QuotaConfig,
+    pub collection: QuotaConfig,
+}
+
+impl QuotasConfig {
+    pub fn quotas(&self) -> Quotas {
+        Quotas {
+            database: self.database.quota(),
+            collection: self.collection.quota(),
+        }
+    }
+}
+
+/// Limits on the resources of a database or a collection, where zero means
+/// unlimited.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub max_objects: u64,
+    pub max_bytes: u64,
+    pub max_value_size: u64,
+    pub max_requests_per_second: u64,
+}
+
+impl QuotaConfig {
+    pub fn quota(&self) -> Quota {
+        let limit = |v: u64| if v == 0 { None } else { Some(v) };
+        Quota {
+            max_objects: limit(self.max_objects),
+            max_bytes: limit(self.max_bytes),
+            max_value_size: limit(self.max_value_size),
+            max_requests_per_second: limit(self.max_requests_per_second),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectEngineConfig {
+    pub master: ObjectEngineMasterConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectEngineMasterConfig {
+    pub addr: String,
+    pub path: PathBuf,
+    pub bulkload_lease_ms: u64,
+    pub sweep_interval_ms: u64,
+    pub compaction_interval_ms: u64,
+    pub l0_compaction_trigger: usize,
+    pub level1_target_size: u64,
+    pub level_size_multiplier: u64,
+    pub target_file_size: u64,
+    pub table_cache_size: usize,
+    pub block_cache_size: usize,
+}
+
+impl ObjectEngineMasterConfig {
+    pub fn master_config(&self) -> object_engine_master::Config {
+        object_engine_master::Config {
+            bulkload_lease_ms: self.bulkload_lease_ms,
+            sweep_interval_ms: self.sweep_interval_ms,
+            compaction_interval_ms: self.compaction_interval_ms,
+            l0_compaction_trigger: self.l0_compaction_trigger,
+            level1_target_size: self.level1_target_size,
+            level_size_multiplier: self.level_size_multiplier,
+            target_file_size: self.target_file_size,
+            table_cache_size: self.table_cache_size,
+            block_cache_size: self.block_cache_size,
+            // Custom merge operators can only be registered in code.
+            merge_operators: Default::default(),
+        }
+    }
+}
+
+impl Default for ObjectEngineMasterConfig {
+    fn default() -> Self {
+        let config = object_engine_master::Config::default();
+        Self {
+            addr: "0.0.0.0:21717".to_owned(),
+            path: PathBuf::from("/tmp/object-engine"),
+            bulkload_lease_ms: config.bulkload_lease_ms,
+            sweep_interval_ms: config.sweep_interval_ms,
+            compaction_interval_ms: config.compaction_interval_ms,
+            l0_compaction_trigger: config.l0_compaction_trigger,
+            level1_target_size: config.level1_target_size,
+            level_size_multiplier: config.level_size_multiplier,
+            target_file_size: config.target_file_size,
+            table_cache_size: config.table_cache_size,
+            block_cache_size: config.block_cache_size,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamEngineConfig {
+    pub master: StreamEngineMasterConfig,
+    pub store: StreamEngineStoreConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamEngineMasterConfig {
+    pub addr: String,
+    /// The urls of the stores that replicate streams.
+    pub stores: Vec<String>,
+    pub heartbeat_interval_ms: u64,
+    pub heartbeat_timeout_tick: u64,
+}
+
+impl StreamEngineMasterConfig {
+    pub fn master_config(&self) -> stream_engine_master::Config {
+        stream_engine_master::Config {
+            heartbeat_interval_ms: self.heartbeat_interval_ms,
+            heartbeat_timeout_tick: self.heartbeat_timeout_tick,
+        }
+    }
+}
+
+impl Default for StreamEngineMasterConfig {
+    fn default() -> Self {
+        let config = stream_engine_master::Config::default();
+        Self {
+            addr: "0.0.0.0:21718".to_owned(),
+            stores: Vec::new(),
+            heartbeat_interval_ms: config.heartbeat_interval_ms,
+            heartbeat_timeout_tick: config.heartbeat_timeout_tick,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamEngineStoreConfig {
+    pub addr: String,
+    pub data_dir: PathBuf,
+    pub create_if_missing: bool,
+    pub sync_data: bool,
+    pub log_file_size: usize,
+    pub max_log_files: usize,
+}
+
+impl StreamEngineStoreConfig {
+    pub fn db_option(&self) -> DbOption {
+        DbOption {
+            create_if_missing: self.create_if_missing,
+            log: LogOption {
+                sync_data: self.sync_data,
+                log_file_size: self.log_file_size,
+                max_log_files: self.max_log_files,
+            },
+        }
+    }
+}
+
+impl Default for StreamEngineStoreConfig {
+    fn default() -> Self {
+        let log = LogOption::default();
+        Self {
+            addr: "0.0.0.0:21719".to_owned(),
+            data_dir: PathBuf::from("/tmp/stream-engine"),
+            create_if_missing: false,
+            sync_data: log.sync_data,
+            log_file_size: log.log_file_size,
+            max_log_files: log.max_log_files,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from an optional TOML file and the
+    /// environment.
+    ///
+    /// Keys missing from the file take default values, and environment
+    /// variables take precedence over the file.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        Self::load_with_vars(path, env::vars())
+    }
+
+    fn load_with_vars(
+        path: Option<&Path>,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut value = Value::try_from(Config::default())?;
+        if let Some(path) = path {
+            let content = std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("failed to read config {}: {}", path.display(), err))?;
+            let file: Value = toml::from_str(&content)
+                .map_err(|err| anyhow!("failed to parse config {}: {}", path.display(), err))?;
+            merge(&mut value, file);
+        }
+        apply_env(&mut value, vars)?;
+        let config: Config = serde_path_to_error::deserialize(value)
+            .map_err(|err| invalid(err.path(), err.inner()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns the listen addresses of the stores started in standalone
+    /// mode.
+    pub fn store_addrs(&self) -> Result<Vec<SocketAddr>> {
+        let key = "stream_engine.store.addr";
+        let base: SocketAddr = self
+            .stream_engine
+            .store
+            .addr
+            .parse()
+            .map_err(|err| invalid(key, err))?;
+        (0..self.server.num_stores)
+            .map(|i| {
+                let port = u16::try_from(i)
+                    .ok()
+                    .and_then(|i| base.port().checked_add(i))
+                    .ok_or_else(|| invalid("server.num_stores", "too many stores for the port"))?;
+                Ok(SocketAddr::new(base.ip(), port))
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        check_addr("server.addr", &self.server.addr)?;
+        positive("server.num_stores", self.server.num_stores)?;
+        self.store_addrs()?;
+        let master = &self.object_engine.master;
+        check_addr("object_engine.master.addr", &master.addr)?;
+        positive(
+            "object_engine.master.bulkload_lease_ms",
+            master.bulkload_lease_ms,
+        )?;
+        positive(
+            "object_engine.master.sweep_interval_ms",
+            master.sweep_interval_ms,
+        )?;
+        positive(
+            "object_engine.master.compaction_interval_ms",
+            master.compaction_interval_ms,
+        )?;
+        positive(
+            "object_engine.master.l0_compaction_trigger",
+            master.l0_compaction_trigger,
+        )?;
+        positive(
+            "object_engine.master.level1_target_size",
+            master.level1_target_size,
+        )?;
+        positive(
+            "object_engine.master.level_size_multiplier",
+            master.level_size_multiplier,
+        )?;
+        positive(
+            "object_engine.master.target_file_size",
+            master.target_file_size,
+        )?;
+
+        let master = &self.stream_engine.master;
+        check_addr("stream_engine.master.addr", &master.addr)?;
+        positive(
+            "stream_engine.master.heartbeat_interval_ms",
+            master.heartbeat_interval_ms,
+        )?;
+        positive(
+            "stream_engine.master.heartbeat_timeout_tick",
+            master.heartbeat_timeout_tick,
+        )?;
+
+        let store = &self.stream_engine.store;
+        check_addr("stream_engine.store.addr", &store.addr)?;
+        if !store.log_file_size.is_power_of_two() {
+            return Err(invalid(
+                "stream_engine.store.log_file_size",
+                "must be a power of 2",
+            ));
+        }
+        positive("stream_engine.store.max_log_files", store.max_log_files)?;
+        Ok(())
+    }
+}
+
+pub fn invalid(key: impl Display, msg: impl Display) -> anyhow::Error {
+    anyhow!("invalid config `{}`: {}", key, msg)
+}
+
+fn positive<T: Default + PartialEq>(key: &str, value: T) -> Result<()> {
+    if value == T::default() {
+        return Err(invalid(key, "must be positive"));
+    }
+    Ok(())
+}
+
+fn check_addr(key: &str, addr: &str) -> Result<()> {
+    addr.parse::<SocketAddr>()
+        .map(|_| ())
+        .map_err(|err| invalid(key, err))
+}
+
+/// Merges `from` into `to`, tables are merged recursively.
+fn merge(to: &mut Value, from: Value) {
+    match (to, from) {
+        (Value::Table(to), Value::Table(from)) => {
+            for (key, value) in from {
+                match to.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        to.insert(key, value);
+                    }
+                }
+            }
+        }
+        (to, from) => *to = from,
+    }
+}
+
+/// Overrides keys with environment variables, values are parsed according
+/// to the type of the overridden keys.
+fn apply_env(value: &mut Value, vars: impl Iterator<Item = (String, String)>) -> Result<()> {
+    for (name, raw) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) => key.to_lowercase().replace(ENV_SEPARATOR, "."),
+            None => continue,
+        };
+        let leaf = match key.split('.').try_fold(&mut *value, |v, k| v.get_mut(k)) {
+            Some(leaf) if !leaf.is_table() => leaf,
+            // Not a configuration key.
+            _ => continue,
+        };
+        *leaf = parse_env(leaf, &raw)
+            .map_err(|msg| invalid(&key, format!("{} (from {})", msg, name)))?;
+    }
+    Ok(())
+}
+
+fn parse_env(leaf: &Value, raw: &str) -> std::result::Result<Value, String> {
+    match leaf {
+        Value::String(_) => Ok(Value::String(raw.to_owned())),
+        Value::Integer(_) => raw.parse().map(Value::Integer).map_err(|e| e.to_string()),
+        Value::Boolean(_) => raw.parse().map(Value::Boolean).map_err(|e| e.to_string()),
+        Value::Array(_) => Ok(Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::String(s.to_owned()))
+                .collect(),
+        )),
+        _ => Err(format!("unsupported value {:?}", raw)),
+    }
+}
+
+#[derive(Parser)]
+pub struct Command {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+impl Command {
+    pub async fn run(self, config: Config) -> Result<()> {
+        match self.subcmd {
+            SubCommand::Dump => {
+                print!("{}", toml::to_string_pretty(&config)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    /// Prints the effective configuration.
+    Dump,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str, vars: &[(&str, &str)]) -> Result<Config> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("engula.toml");
+        std::fs::write(&path, content)?;
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        Config::load_with_vars(Some(&path), vars)
+    }
+
+    fn load_err(content: &str, vars: &[(&str, &str)]) -> String {
+        load(content, vars).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut to: Value = toml::from_str("a = 1\n[b]\nc = 2\nd = 3").unwrap();
+        let from: Value = toml::from_str("e = 4\n[b]\nc = 5").unwrap();
+        merge(&mut to, from);
+        let expect: Value = toml::from_str("a = 1\ne = 4\n[b]\nc = 5\nd = 3").unwrap();
+        assert_eq!(to, expect);
+    }
+
+    #[test]
+    fn test_load_file() -> Result<()> {
+        let config = load(
+            r#"
+            [server]
+            addr = "127.0.0.1:1000"
+
+            [object_engine.master]
+            sweep_interval_ms = 5
+            "#,
+            &[],
+        )?;
+        assert_eq!(config.server.addr, "127.0.0.1:1000");
+        assert_eq!(config.object_engine.master.sweep_interval_ms, 5);
+        // Keys missing from the file take default values.
+        assert_eq!(config.server.num_stores, 3);
+        assert_eq!(config.object_engine.master.addr, "0.0.0.0:21717");
+
+        let path = Path::new("/nonexistent/engula.toml");
+        let err = Config::load_with_vars(Some(path), std::iter::empty()).unwrap_err();
+        assert!(
+            err.to_string().contains("/nonexistent/engula.toml"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_precedence() -> Result<()> {
+        let config = load(
+            r#"
+            [stream_engine.store]
+            log_file_size = 1024
+            max_log_files = 4
+            "#,
+            &[
+                ("ENGULA_STREAM_ENGINE__STORE__LOG_FILE_SIZE", "2048"),
+                ("ENGULA_STREAM_ENGINE__MASTER__STORES", "a:1, b:2"),
+                ("ENGULA_SERVER__STANDALONE", "true"),
+                // Unknown keys and variables without the prefix are ignored.
+                ("ENGULA_UNKNOWN__KEY", "1"),
+                ("SERVER__NUM_STORES", "0"),
+            ],
+        )?;
+        let store = &config.stream_engine.store;
+        assert_eq!(store.log_file_size, 2048);
+        assert_eq!(store.max_log_files, 4);
+        assert_eq!(config.stream_engine.master.stores, vec!["a:1", "b:2"]);
+        assert!(config.server.standalone);
+        assert_eq!(config.server.num_stores, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quotas() -> Result<()> {
+        let quotas = Config::default().quotas.quotas();
+        assert_eq!(quotas.database.max_objects, None);
+        assert_eq!(quotas.collection.max_requests_per_second, None);
+
+        let config = load(
+            r#"
+            [quotas.database]
+            max_bytes = 1024
+
+            [quotas.collection]
+            max_objects = 10
+            "#,
+            &[("ENGULA_QUOTAS__COLLECTION__MAX_VALUE_SIZE", "64")],
+        )?;
+        let quotas = config.quotas.quotas();
+        assert_eq!(quotas.database.max_bytes, Some(1024));
+        assert_eq!(quotas.database.max_objects, None);
+        assert_eq!(quotas.collection.max_objects, Some(10));
+        assert_eq!(quotas.collection.max_value_size, Some(64));
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_addrs() -> Result<()> {
+        let config = load(
+            r#"
+            [server]
+            num_stores = 2
+
+            [stream_engine.store]
+            addr = "127.0.0.1:3000"
+            "#,
+            &[],
+        )?;
+        let addrs: Vec<SocketAddr> = vec!["127.0.0.1:3000".parse()?, "127.0.0.1:3001".parse()?];
+        assert_eq!(config.store_addrs()?, addrs);
+
+        let err = load_err(
+            "[server]\nnum_stores = 2\n[stream_engine.store]\naddr = \"0.0.0.0:65535\"",
+            &[],
+        );
+        assert!(err.contains("server.num_stores"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let err = load_err("[server]\nunknown_key = 1", &[]);
+        assert!(err.contains("server.unknown_key"), "{}", err);
+        let err = load_err("[server]\nnum_stores = \"three\"", &[]);
+        assert!(err.contains("server.num_stores"), "{}", err);
+        let err = load_err("", &[("ENGULA_SERVER__NUM_STORES", "three")]);
+        assert!(err.contains("server.num_stores"), "{}", err);
+        assert!(err.contains("ENGULA_SERVER__NUM_STORES"), "{}", err);
+
+        // Values are validated after they are loaded.
+        let err = load_err("[server]\nnum_stores = 0", &[]);
+        assert!(err.contains("server.num_stores"), "{}", err);
+        let err = load_err(
+            "",
+            &[("ENGULA_STREAM_ENGINE__STORE__LOG_FILE_SIZE", "1000")],
+        );
+        assert!(err.contains("stream_engine.store.log_file_size"), "{}", err);
+        let err = load_err("[object_engine.master]\naddr = \"localhost\"", &[]);
+        assert!(err.contains("object_engine.master.addr"), "{}", err);
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use clap::Parser;
+use engula_apis::v1::DatabaseDesc;
+use serde_json::{json, Value as Json};
+
+use crate::output::ClientOptions;
+
+#[derive(Parser)]
+pub struct Command {
+    #[clap(flatten)]
+    opts: ClientOptions,
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+impl Command {
+    pub async fn run(self) -> Result<()> {
+        self.subcmd.run(&self.opts).await?;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    Create { name: String },
+    List,
+    Describe { name: String },
+    Delete { name: String },
+}
+
+impl SubCommand {
+    async fn run(self, opts: &ClientOptions) -> Result<()> {
+        let uv = opts.connect().await?;
+        match self {
+            SubCommand::Create { name } => {
+                uv.create_database(&name).await?;
+            }
+            SubCommand::List => {
+                let descs = uv.list_databases().collect().await?;
+                let rows = descs.into_iter().map(desc_to_json).collect();
+                opts.print_rows(COLUMNS, rows)?;
+            }
+            SubCommand::Describe { name } => {
+                let desc = uv.database(&name).desc().await?;
+                opts.print_rows(COLUMNS, vec![desc_to_json(desc)])?;
+            }
+            SubCommand::Delete { name } => {
+                uv.delete_database(&name).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const COLUMNS: &[&str] = &["id", "name", "num_collections"];
+
+fn desc_to_json(desc: DatabaseDesc) -> Json {
+    let num_collections = desc.properties.map(|p| p.num_collections);
+    json!({
+        "id": desc.id,
+        "name": desc.name,
+        "num_collections": num_collections,
+    })
+}
@@ -12,23 +12,48 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
+
 use anyhow::Result;
 use clap::Parser;
-use tracing::{error, info};
+use tracing::error;
+
+use self::{config::Config, shutdown::Shutdown};
 
+mod collection;
+mod config;
+mod database;
+mod object;
 mod object_engine;
+mod output;
 mod server;
+mod shell;
+mod shutdown;
+mod stream_engine;
+mod value;
 
 #[derive(Parser)]
 struct Command {
+    /// The TOML configuration file of server components.
+    #[clap(long, global = true, env = "ENGULA_CONFIG")]
+    config: Option<PathBuf>,
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
 
 impl Command {
-    async fn run(self) -> Result<()> {
-        self.subcmd.run().await?;
-        Ok(())
+    async fn run(self, shutdown: Shutdown) -> Result<()> {
+        let load = || Config::load(self.config.as_deref());
+        match self.subcmd {
+            SubCommand::Server(cmd) => cmd.run(load()?, shutdown).await,
+            SubCommand::ObjectEngine(cmd) => cmd.run(load()?, shutdown).await,
+            SubCommand::StreamEngine(cmd) => cmd.run(load()?, shutdown).await,
+            SubCommand::Config(cmd) => cmd.run(load()?).await,
+            SubCommand::Database(cmd) => shutdown.cancel(cmd.run()).await,
+            SubCommand::Collection(cmd) => shutdown.cancel(cmd.run()).await,
+            SubCommand::Object(cmd) => shutdown.cancel(cmd.run()).await,
+            SubCommand::Shell(cmd) => shutdown.cancel(cmd.run()).await,
+        }
     }
 }
 
@@ -36,30 +61,23 @@ impl Command {
 enum SubCommand {
     Server(server::Command),
     ObjectEngine(object_engine::Command),
-}
-
-impl SubCommand {
-    async fn run(self) -> Result<()> {
-        match self {
-            SubCommand::Server(cmd) => cmd.run().await,
-            SubCommand::ObjectEngine(cmd) => cmd.run().await,
-        }
-    }
+    StreamEngine(stream_engine::Command),
+    Database(database::Command),
+    Collection(collection::Command),
+    Object(object::Command),
+    Shell(shell::Command),
+    Config(config::Command),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cmd: Command = Command::parse();
     tracing_subscriber::fmt::init();
-    tokio::select! {
-        res = cmd.run() => {
-            if let Err(err) = res {
-                error!(cause = %err, "Fatal error occurs!");
-            }
-        }
-        _ = tokio::signal::ctrl_c() => {
-            info!("Goodbye!");
-        }
+    let shutdown = Shutdown::listen()?;
+    if let Err(err) = cmd.run(shutdown).await {
+        error!(cause = %err, "Fatal error occurs!");
+        // Lets scripts tell failed commands apart.
+        std::process::exit(1);
     }
     Ok(())
 }
@@ -0,0 +1,186 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use engula_apis::v1::{MutateExpr, MutateFunction, SelectExpr, SelectFunction, Value};
+use engula_client::{Collection, ScanObject};
+use serde_json::{json, Value as Json};
+
+use crate::{output::ClientOptions, value};
+
+#[derive(Parser)]
+pub struct Command {
+    #[clap(flatten)]
+    opts: ClientOptions,
+    /// The database that objects belong to.
+    #[clap(long, short)]
+    database: String,
+    /// The collection that objects belong to.
+    #[clap(long, short)]
+    collection: String,
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+impl Command {
+    pub async fn run(self) -> Result<()> {
+        let co = self
+            .opts
+            .connect()
+            .await?
+            .database(&self.database)
+            .collection(&self.collection);
+        self.subcmd.run(&self.opts, co).await?;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    /// Gets an object or a part of it.
+    Get {
+        id: String,
+        /// The index of the part to get, in JSON.
+        #[clap(long)]
+        index: Option<String>,
+    },
+    /// Returns the length of an object.
+    Len {
+        id: String,
+    },
+    /// Sets an object to a value in JSON.
+    Set {
+        id: String,
+        value: String,
+    },
+    Delete {
+        id: String,
+    },
+    /// Lists objects in the order of their ids.
+    Scan {
+        /// Lists objects with this prefix only.
+        #[clap(long, default_value = "")]
+        prefix: String,
+        /// Lists objects with ids at or after this one.
+        #[clap(long, default_value = "")]
+        start: String,
+        /// The maximum number of objects to list, zero means no limit.
+        #[clap(long, default_value = "0")]
+        limit: u32,
+    },
+    /// Applies a mutation to an object.
+    ///
+    /// For example, `mutate counter add 1` or `mutate list rpush '[1, 2]'`.
+    Mutate {
+        id: String,
+        /// One of add, trim, lpop, rpop, lpush, rpush, clear, extend and
+        /// remove.
+        func: String,
+        /// The arguments of the mutation, in JSON.
+        args: Vec<String>,
+        /// The index of the mutation, in JSON.
+        #[clap(long)]
+        index: Option<String>,
+    },
+}
+
+impl SubCommand {
+    async fn run(self, opts: &ClientOptions, co: Collection) -> Result<()> {
+        match self {
+            SubCommand::Get { id, index } => {
+                let expr = SelectExpr {
+                    func: SelectFunction::Get as i32,
+                    index: index.as_deref().map(value::parse).transpose()?,
+                    ..Default::default()
+                };
+                let value: Value = co.select(id, expr).await?;
+                opts.print_value(value::to_json(value))?;
+            }
+            SubCommand::Len { id } => {
+                let expr = SelectExpr {
+                    func: SelectFunction::Len as i32,
+                    ..Default::default()
+                };
+                let value: Value = co.select(id, expr).await?;
+                opts.print_value(value::to_json(value))?;
+            }
+            SubCommand::Set { id, value } => {
+                co.set(id, value::parse(&value)?).await?;
+            }
+            SubCommand::Delete { id } => {
+                co.delete(id).await?;
+            }
+            SubCommand::Scan {
+                prefix,
+                start,
+                limit,
+            } => {
+                let res = co.scan(prefix, start, limit).await?;
+                let rows = res.objects.into_iter().map(object_to_json).collect();
+                opts.print_rows(SCAN_COLUMNS, rows)?;
+                if !res.next.is_empty() {
+                    eprintln!("next: {}", String::from_utf8_lossy(&res.next));
+                }
+            }
+            SubCommand::Mutate {
+                id,
+                func,
+                args,
+                index,
+            } => {
+                let expr = MutateExpr {
+                    func: mutate_function(&func)? as i32,
+                    index: index.as_deref().map(value::parse).transpose()?,
+                    args: args
+                        .iter()
+                        .map(|x| value::parse(x))
+                        .collect::<Result<_>>()?,
+                    ..Default::default()
+                };
+                let value: Value = co.mutate(id, expr).await?;
+                if value.value.is_some() {
+                    opts.print_value(value::to_json(value))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const SCAN_COLUMNS: &[&str] = &["id", "value", "version"];
+
+fn object_to_json(ob: ScanObject) -> Json {
+    json!({
+        "id": String::from_utf8_lossy(&ob.id),
+        "value": value::to_json(ob.value.unwrap_or_default()),
+        "version": ob.version,
+    })
+}
+
+fn mutate_function(name: &str) -> Result<MutateFunction> {
+    let func = match name {
+        "add" => MutateFunction::Add,
+        "trim" => MutateFunction::Trim,
+        "lpop" => MutateFunction::Lpop,
+        "rpop" => MutateFunction::Rpop,
+        "lpush" => MutateFunction::Lpush,
+        "rpush" => MutateFunction::Rpush,
+        "clear" => MutateFunction::Clear,
+        "extend" => MutateFunction::Extend,
+        "remove" => MutateFunction::Remove,
+        _ => bail!("unknown mutate function {}", name),
+    };
+    Ok(func)
+}
@@ -12,13 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::PathBuf;
+
 use anyhow::Result;
 use clap::Parser;
+use futures::TryFutureExt;
 use object_engine_master::{Master, Server};
 use tokio::net::TcpListener;
 use tokio_stream::wrappers::TcpListenerStream;
 use tracing::info;
 
+use crate::{config::Config, shutdown::Shutdown};
+
 #[derive(Parser)]
 pub struct Command {
     #[clap(subcommand)]
@@ -26,8 +31,8 @@ pub struct Command {
 }
 
 impl Command {
-    pub async fn run(self) -> Result<()> {
-        self.subcmd.run().await?;
+    pub async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        self.subcmd.run(config, shutdown).await?;
         Ok(())
     }
 }
@@ -38,9 +43,9 @@ enum SubCommand {
 }
 
 impl SubCommand {
-    async fn run(self) -> Result<()> {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
         match self {
-            SubCommand::Master(cmd) => cmd.run().await,
+            SubCommand::Master(cmd) => cmd.run(config, shutdown).await,
         }
     }
 }
@@ -52,8 +57,8 @@ struct MasterCommand {
 }
 
 impl MasterCommand {
-    pub async fn run(self) -> Result<()> {
-        self.subcmd.run().await?;
+    pub async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        self.subcmd.run(config, shutdown).await?;
         Ok(())
     }
 }
@@ -64,33 +69,51 @@ enum MasterSubCommand {
 }
 
 impl MasterSubCommand {
-    async fn run(self) -> Result<()> {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
         match self {
-            MasterSubCommand::Start(cmd) => cmd.run().await,
+            MasterSubCommand::Start(cmd) => cmd.run(config, shutdown).await,
         }
     }
 }
 
 #[derive(Parser)]
 struct MasterStartCommand {
-    #[clap(long, default_value = "0.0.0.0:21717")]
-    addr: String,
-    #[clap(long, default_value = "/tmp/object-engine")]
-    path: String,
+    #[clap(long)]
+    addr: Option<String>,
+    #[clap(long)]
+    path: Option<PathBuf>,
 }
 
 impl MasterStartCommand {
-    async fn run(self) -> Result<()> {
-        let listener = TcpListener::bind(self.addr).await?;
+    async fn run(self, mut config: Config, shutdown: Shutdown) -> Result<()> {
+        let master_config = &mut config.object_engine.master;
+        if let Some(addr) = self.addr {
+            master_config.addr = addr;
+        }
+        if let Some(path) = self.path {
+            master_config.path = path;
+        }
+        config.validate()?;
+
+        let master_config = &config.object_engine.master;
+        let listener = TcpListener::bind(&master_config.addr).await?;
         let addr = listener.local_addr()?;
         info!(message = "The master is running at", %addr);
 
-        let master = Master::open(self.path).await?;
+        let master =
+            Master::open_with_config(&master_config.path, master_config.master_config()).await?;
         let master_service = Server::new(master).into_service();
-        tonic::transport::Server::builder()
+        let server = tonic::transport::Server::builder()
             .add_service(master_service)
-            .serve_with_incoming(TcpListenerStream::new(listener))
+            .serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                shutdown.clone().wait(),
+            );
+        shutdown
+            .drain(
+                config.server.shutdown_timeout(),
+                server.map_err(|e| e.into()),
+            )
             .await
-            .map_err(|e| e.into())
     }
 }
@@ -0,0 +1,85 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use clap::{ArgEnum, Parser};
+use comfy_table::Table;
+use engula_client::Universe;
+use serde_json::Value as Json;
+
+#[derive(ArgEnum, Clone, Copy)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+/// Options shared by commands that talk to an Engula server.
+#[derive(Parser)]
+pub struct ClientOptions {
+    #[clap(long, global = true, default_value = "http://localhost:21716")]
+    endpoint: String,
+    #[clap(long, global = true, arg_enum, default_value = "table")]
+    output: Format,
+}
+
+impl ClientOptions {
+    pub async fn connect(&self) -> Result<Universe> {
+        let uv = Universe::connect(self.endpoint.clone()).await?;
+        Ok(uv)
+    }
+
+    /// Prints rows of JSON objects with the same keys as `columns`.
+    pub fn print_rows(&self, columns: &[&str], rows: Vec<Json>) -> Result<()> {
+        self.output.print_rows(columns, rows)
+    }
+
+    /// Prints a single JSON value.
+    pub fn print_value(&self, value: Json) -> Result<()> {
+        self.output.print_value(value)
+    }
+}
+
+impl Format {
+    /// Prints rows of JSON objects with the same keys as `columns`.
+    pub fn print_rows(self, columns: &[&str], rows: Vec<Json>) -> Result<()> {
+        match self {
+            Format::Table => {
+                let mut table = Table::new();
+                table.set_header(columns.to_vec());
+                for row in rows {
+                    table.add_row(columns.iter().map(|c| cell(&row[*c])));
+                }
+                println!("{}", table);
+            }
+            Format::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        }
+        Ok(())
+    }
+
+    /// Prints a single JSON value.
+    pub fn print_value(self, value: Json) -> Result<()> {
+        match self {
+            Format::Table => println!("{}", cell(&value)),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        }
+        Ok(())
+    }
+}
+
+fn cell(value: &Json) -> String {
+    match value {
+        Json::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
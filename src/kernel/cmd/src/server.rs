@@ -12,12 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+};
+
 use anyhow::Result;
 use clap::Parser;
+use engula_transactor::{Engines, Server as TransactorServer, Transactor};
+use futures::{
+    future::{self, BoxFuture},
+    TryFutureExt,
+};
+use object_engine_master::{Master, Server as ObjectEngineServer};
+use stream_engine_master::Server as StreamMasterServer;
+use stream_engine_store::{DbOption, Server as StreamStoreServer, StreamDb};
 use tokio::net::TcpListener;
 use tokio_stream::wrappers::TcpListenerStream;
 use tracing::info;
 
+use crate::{config::Config, shutdown::Shutdown};
+
 #[derive(Parser)]
 pub struct Command {
     #[clap(subcommand)]
@@ -25,8 +40,8 @@ pub struct Command {
 }
 
 impl Command {
-    pub async fn run(self) -> Result<()> {
-        self.subcmd.run().await?;
+    pub async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        self.subcmd.run(config, shutdown).await?;
         Ok(())
     }
 }
@@ -37,30 +52,204 @@ enum SubCommand {
 }
 
 impl SubCommand {
-    async fn run(self) -> Result<()> {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
         match self {
-            SubCommand::Start(cmd) => cmd.run().await,
+            SubCommand::Start(cmd) => cmd.run(config, shutdown).await,
         }
     }
 }
 
 #[derive(Parser)]
 struct StartCommand {
-    #[clap(long, default_value = "0.0.0.0:21716")]
-    addr: String,
+    #[clap(long)]
+    addr: Option<String>,
+    /// Starts every component in this process.
+    ///
+    /// The engines only keep a tenant per database. Databases, collections
+    /// and objects are kept in memory, so they are lost on restart.
+    ///
+    /// The options below are only used in standalone mode.
+    #[clap(long)]
+    standalone: bool,
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+    #[clap(long)]
+    object_engine_addr: Option<String>,
+    #[clap(long)]
+    stream_engine_addr: Option<String>,
+    /// The address of the first stream-engine store, the others listen on
+    /// the following ports.
+    #[clap(long)]
+    store_addr: Option<String>,
+    /// The number of stream-engine stores to start.
+    #[clap(long)]
+    num_stores: Option<usize>,
 }
 
 impl StartCommand {
-    async fn run(self) -> Result<()> {
-        let listener = TcpListener::bind(self.addr).await?;
-        let addr = listener.local_addr()?;
-        info!(message = "The server is running at", %addr);
+    async fn run(self, mut config: Config, shutdown: Shutdown) -> Result<()> {
+        self.apply(&mut config);
+        config.validate()?;
+        start(config, shutdown).await
+    }
+
+    fn apply(self, config: &mut Config) {
+        if let Some(addr) = self.addr {
+            config.server.addr = addr;
+        }
+        config.server.standalone |= self.standalone;
+        if let Some(data_dir) = self.data_dir {
+            config.server.data_dir = data_dir;
+        }
+        if let Some(addr) = self.object_engine_addr {
+            config.object_engine.master.addr = addr;
+        }
+        if let Some(addr) = self.stream_engine_addr {
+            config.stream_engine.master.addr = addr;
+        }
+        if let Some(addr) = self.store_addr {
+            config.stream_engine.store.addr = addr;
+        }
+        if let Some(num_stores) = self.num_stores {
+            config.server.num_stores = num_stores;
+        }
+    }
+}
 
-        let transactor = engula_transactor::Server::default().into_service();
-        tonic::transport::Server::builder()
-            .add_service(transactor)
-            .serve_with_incoming(TcpListenerStream::new(listener))
+async fn start(config: Config, shutdown: Shutdown) -> Result<()> {
+    let timeout = config.server.shutdown_timeout();
+    if !config.server.standalone {
+        let transactor = Transactor::with_quotas(config.quotas.quotas());
+        let server = start_transactor(&config, &shutdown, transactor).await?;
+        return shutdown.drain(timeout, server).await;
+    }
+
+    // Engines serve requests before the transactor connects to them.
+    let (object_engine, object_engine_url) = start_object_engine(&config, &shutdown).await?;
+    let mut servers = vec![spawn(object_engine)];
+    let (stream_engine, stream_engine_url, dbs) = start_stream_engine(&config, &shutdown).await?;
+    servers.extend(stream_engine.into_iter().map(spawn));
+    let res: Result<()> = async {
+        let engines = Engines::connect(&object_engine_url, &stream_engine_url).await?;
+        let transactor = Transactor::with_engines(config.quotas.quotas(), engines);
+        servers.push(start_transactor(&config, &shutdown, transactor).await?);
+        shutdown
+            .drain(timeout, future::try_join_all(servers).map_ok(|_| ()))
             .await
-            .map_err(|e| e.into())
     }
+    .await;
+    // Stores are closed after all servers stop.
+    for db in dbs {
+        db.close()?;
+    }
+    res
+}
+
+type ServerFuture = BoxFuture<'static, Result<()>>;
+
+/// Spawns `server` so that it serves requests before the returned future is
+/// polled.
+fn spawn(server: ServerFuture) -> ServerFuture {
+    let handle = tokio::spawn(server);
+    Box::pin(async move { handle.await? })
+}
+
+/// Returns the url to connect to a local server listening on `addr`.
+fn local_url(addr: SocketAddr) -> String {
+    let ip = match addr {
+        SocketAddr::V4(addr) if addr.ip().is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+        SocketAddr::V6(addr) if addr.ip().is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+        _ => addr.ip(),
+    };
+    format!("http://{}", SocketAddr::new(ip, addr.port()))
+}
+
+async fn start_transactor(
+    config: &Config,
+    shutdown: &Shutdown,
+    transactor: Transactor,
+) -> Result<ServerFuture> {
+    let listener = TcpListener::bind(&config.server.addr).await?;
+    let addr = listener.local_addr()?;
+    info!(message = "The server is running at", %addr);
+
+    let signal = {
+        let shutdown = shutdown.clone();
+        let transactor = transactor.clone();
+        async move {
+            shutdown.wait().await;
+            // Finishes ongoing watches, otherwise they never end.
+            transactor.close().await;
+        }
+    };
+    let transactor_server = TransactorServer::new(transactor);
+    let server = tonic::transport::Server::builder()
+        .add_service(transactor_server.clone().into_service())
+        .add_service(transactor_server.into_cooperator_service())
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), signal);
+    Ok(Box::pin(server.map_err(|e| e.into())))
+}
+
+async fn start_object_engine(
+    config: &Config,
+    shutdown: &Shutdown,
+) -> Result<(ServerFuture, String)> {
+    let listener = TcpListener::bind(&config.object_engine.master.addr).await?;
+    let addr = listener.local_addr()?;
+    info!(message = "The object-engine master is running at", %addr);
+
+    let master = Master::open_with_config(
+        config.server.data_dir.join("object-engine"),
+        config.object_engine.master.master_config(),
+    )
+    .await?;
+    let server = tonic::transport::Server::builder()
+        .add_service(ObjectEngineServer::new(master).into_service())
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown.clone().wait());
+    Ok((Box::pin(server.map_err(|e| e.into())), local_url(addr)))
+}
+
+type StreamEngineServers = (Vec<ServerFuture>, String, Vec<StreamDb>);
+
+async fn start_stream_engine(config: &Config, shutdown: &Shutdown) -> Result<StreamEngineServers> {
+    let mut servers: Vec<ServerFuture> = Vec::new();
+    let mut dbs = Vec::new();
+    let mut stores = Vec::new();
+    for (i, addr) in config.store_addrs()?.into_iter().enumerate() {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        info!(message = "The stream-engine store is running at", %addr);
+
+        let opt = DbOption {
+            create_if_missing: true,
+            ..config.stream_engine.store.db_option()
+        };
+        let path = config
+            .server
+            .data_dir
+            .join("stream-engine")
+            .join(format!("store-{}", i));
+        let db = StreamDb::open(path, opt)?;
+        let server = tonic::transport::Server::builder()
+            .add_service(StreamStoreServer::new(db.clone()).into_service())
+            .serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                shutdown.clone().wait(),
+            );
+        servers.push(Box::pin(server.map_err(|e| e.into())));
+        dbs.push(db);
+        stores.push(local_url(addr));
+    }
+
+    let master = &config.stream_engine.master;
+    let listener = TcpListener::bind(&master.addr).await?;
+    let addr = listener.local_addr()?;
+    info!(message = "The stream-engine master is running at", %addr);
+
+    let master_server = StreamMasterServer::with_config(master.master_config(), stores);
+    let server = tonic::transport::Server::builder()
+        .add_service(master_server.into_service())
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown.clone().wait());
+    servers.push(Box::pin(server.map_err(|e| e.into())));
+    Ok((servers, local_url(addr), dbs))
 }
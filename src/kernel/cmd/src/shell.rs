@@ -0,0 +1,566 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use engula_apis::v1::{MutateExpr, MutateFunction, SelectExpr, SelectFunction, Value};
+use engula_client::{CollectionTxn, Database, DatabaseTxn, Universe};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use serde_json::Value as Json;
+
+use crate::value;
+
+const HELP: &str = r#"Statements are separated by `;`:
+
+  use <database>                 Selects a database
+  show databases                 Lists databases
+  show collections               Lists collections of the current database
+  <collection>.<method>(<args>)  Calls a method on an object, for example:
+                                   users.get("u1")
+                                   users.set("u1", {"name": "alice"})
+                                   counters.add("c", 5)
+                                   queue.rpop("q", 1)
+  begin                          Starts a transaction of the current database
+  commit                         Commits the current transaction
+  abort                          Discards the current transaction
+  help                           Shows this message
+  exit                           Exits the shell
+
+Arguments are JSON values, where the first one is the object id. Methods
+are get, len, set, delete, add, trim, lpop, rpop, lpush, rpush, clear,
+extend and remove. Within a transaction, mutations are buffered until
+commit, and reads are rejected since they can't see the buffered
+mutations."#;
+
+const KEYWORDS: &[&str] = &[
+    "use",
+    "show",
+    "databases",
+    "collections",
+    "begin",
+    "commit",
+    "abort",
+    "help",
+    "exit",
+];
+
+const METHODS: &[&str] = &[
+    "get", "len", "set", "delete", "add", "trim", "lpop", "rpop", "lpush", "rpush", "clear",
+    "extend", "remove",
+];
+
+#[derive(Parser)]
+pub struct Command {
+    #[clap(long, default_value = "http://localhost:21716")]
+    endpoint: String,
+    /// The database to use at startup.
+    #[clap(long, short)]
+    database: Option<String>,
+}
+
+impl Command {
+    pub async fn run(self) -> Result<()> {
+        let uv = Universe::connect(self.endpoint).await?;
+        let mut shell = Shell::new(uv);
+        if let Some(name) = self.database {
+            shell.execute(&format!("use {}", name)).await?;
+        }
+        shell.run().await
+    }
+}
+
+/// Names used for tab completion.
+#[derive(Default)]
+struct Names {
+    databases: Vec<String>,
+    collections: Vec<String>,
+}
+
+impl Names {
+    /// Lists the databases and the collections of the database, if any.
+    async fn list(uv: &Universe, dbname: Option<&str>) -> Result<Self> {
+        let databases = uv.list_databases().collect().await?;
+        let collections = if let Some(dbname) = dbname {
+            uv.database(dbname).list_collections().collect().await?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            databases: databases.into_iter().map(|x| x.name).collect(),
+            collections: collections.into_iter().map(|x| x.name).collect(),
+        })
+    }
+}
+
+struct Shell {
+    uv: Universe,
+    db: Option<Database>,
+    txn: Option<Transaction>,
+    // The name of the current database, shared with the completer.
+    dbname: Arc<Mutex<Option<String>>>,
+}
+
+struct Transaction {
+    txn: DatabaseTxn,
+    collections: HashMap<String, CollectionTxn>,
+}
+
+impl Shell {
+    fn new(uv: Universe) -> Self {
+        Self {
+            uv,
+            db: None,
+            txn: None,
+            dbname: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        let mut editor = Editor::<ShellHelper>::new();
+        editor.set_helper(Some(ShellHelper {
+            uv: self.uv.clone(),
+            dbname: self.dbname.clone(),
+        }));
+        let history = history_path();
+        if let Some(path) = &history {
+            // The history file doesn't exist on the first run.
+            let _ = editor.load_history(path);
+        }
+        println!("Type `help` for more information.");
+        loop {
+            let prompt = self.prompt();
+            let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err.into()),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line);
+            match self.execute(line).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => eprintln!("Error: {}", err),
+            }
+        }
+        if let Some(path) = &history {
+            editor.save_history(path)?;
+        }
+        Ok(())
+    }
+
+    fn prompt(&self) -> String {
+        let dbname = self.db.as_ref().map(|db| db.name()).unwrap_or("");
+        let suffix = if self.txn.is_some() { "*" } else { "" };
+        format!("engula:{}{}> ", dbname, suffix)
+    }
+
+    /// Executes a line of statements and returns false if the shell should
+    /// exit.
+    async fn execute(&mut self, line: &str) -> Result<bool> {
+        for stmt in split_statements(line) {
+            if !self.execute_statement(stmt.trim()).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn execute_statement(&mut self, stmt: &str) -> Result<bool> {
+        let words: Vec<&str> = stmt.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["exit" | "quit"] => return Ok(false),
+            ["help"] => println!("{}", HELP),
+            ["use", name] => {
+                if self.txn.is_some() {
+                    bail!("can't change the database within a transaction");
+                }
+                let db = self.uv.database(name);
+                db.desc().await?;
+                self.db = Some(db);
+                *self.dbname.lock().unwrap() = Some(name.to_string());
+            }
+            ["show", "databases"] => {
+                for desc in self.uv.list_databases().collect().await? {
+                    println!("{}", desc.name);
+                }
+            }
+            ["show", "collections"] => {
+                for desc in self.database()?.list_collections().collect().await? {
+                    println!("{}", desc.name);
+                }
+            }
+            ["begin"] => {
+                if self.txn.is_some() {
+                    bail!("a transaction is in progress");
+                }
+                self.txn = Some(Transaction {
+                    txn: self.database()?.begin(),
+                    collections: HashMap::new(),
+                });
+            }
+            ["commit"] => {
+                let txn = self
+                    .txn
+                    .take()
+                    .ok_or_else(|| anyhow!("no transaction in progress"))?;
+                for (_, co) in txn.collections {
+                    co.submit();
+                }
+                txn.txn.commit().await?;
+            }
+            ["abort"] => {
+                self.txn
+                    .take()
+                    .ok_or_else(|| anyhow!("no transaction in progress"))?;
+            }
+            _ => self.execute_call(stmt).await?,
+        }
+        Ok(true)
+    }
+
+    async fn execute_call(&mut self, stmt: &str) -> Result<()> {
+        let call = Call::parse(stmt)?;
+        let co = self.database()?.collection(&call.collection);
+        let (select, mutate) = call.expr()?;
+        if let Some(expr) = select {
+            if self.txn.is_some() {
+                bail!("can't read within a transaction, which doesn't see its own mutations");
+            }
+            let value: Value = co.select(call.id, expr).await?;
+            print_value(value)?;
+        } else if let Some(expr) = mutate {
+            if let Some(txn) = &mut self.txn {
+                let name = call.collection.clone();
+                txn.collections
+                    .entry(name.clone())
+                    .or_insert_with(|| txn.txn.collection(&name))
+                    .mutate(call.id, expr);
+            } else {
+                let value: Value = co.mutate(call.id, expr).await?;
+                if value.value.is_some() {
+                    print_value(value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn database(&self) -> Result<&Database> {
+        self.db
+            .as_ref()
+            .ok_or_else(|| anyhow!("no database selected, try `use <database>`"))
+    }
+}
+
+fn print_value(value: Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&value::to_json(value))?);
+    Ok(())
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".engula_history"))
+}
+
+/// Splits a line by `;` outside of string literals.
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut stmts = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => {
+                stmts.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    stmts.push(&line[start..]);
+    stmts
+}
+
+/// A method call like `users.get("u1")`.
+struct Call {
+    collection: String,
+    method: String,
+    id: String,
+    args: Vec<Value>,
+}
+
+impl Call {
+    fn parse(stmt: &str) -> Result<Call> {
+        let syntax_error = || anyhow!("invalid statement `{}`, try `help`", stmt);
+        let (target, rest) = stmt.split_once('(').ok_or_else(syntax_error)?;
+        let args = rest.trim_end().strip_suffix(')').ok_or_else(syntax_error)?;
+        let (collection, method) = target.trim().split_once('.').ok_or_else(syntax_error)?;
+        // Parses the arguments as a JSON array.
+        let mut args: Vec<Json> = serde_json::from_str(&format!("[{}]", args))?;
+        if args.is_empty() {
+            bail!("missing object id");
+        }
+        let id = match args.remove(0) {
+            Json::String(id) => id,
+            _ => bail!("the object id must be a string"),
+        };
+        let args = args
+            .into_iter()
+            .map(value::from_json)
+            .collect::<Result<_>>()?;
+        Ok(Call {
+            collection: collection.trim().to_owned(),
+            method: method.trim().to_owned(),
+            id,
+            args,
+        })
+    }
+
+    fn expr(&self) -> Result<(Option<SelectExpr>, Option<MutateExpr>)> {
+        let select = |func: SelectFunction| SelectExpr {
+            func: func as i32,
+            index: self.args.first().cloned(),
+            ..Default::default()
+        };
+        let mutate = |func: MutateFunction| MutateExpr {
+            func: func as i32,
+            args: self.args.clone(),
+            ..Default::default()
+        };
+        let exprs = match self.method.as_str() {
+            "get" => (Some(select(SelectFunction::Get)), None),
+            "len" => (Some(select(SelectFunction::Len)), None),
+            "set" => (None, Some(mutate(MutateFunction::Set))),
+            "delete" => (None, Some(mutate(MutateFunction::Delete))),
+            "add" => (None, Some(mutate(MutateFunction::Add))),
+            "lpop" => (None, Some(mutate(MutateFunction::Lpop))),
+            "rpop" => (None, Some(mutate(MutateFunction::Rpop))),
+            "lpush" => (None, Some(mutate(MutateFunction::Lpush))),
+            "rpush" => (None, Some(mutate(MutateFunction::Rpush))),
+            "clear" => (None, Some(mutate(MutateFunction::Clear))),
+            "extend" => (None, Some(mutate(MutateFunction::Extend))),
+            // These take an index instead of arguments.
+            "trim" | "remove" => {
+                let func = if self.method == "trim" {
+                    MutateFunction::Trim
+                } else {
+                    MutateFunction::Remove
+                };
+                let expr = MutateExpr {
+                    func: func as i32,
+                    index: self.args.first().cloned(),
+                    ..Default::default()
+                };
+                (None, Some(expr))
+            }
+            _ => bail!("unknown method `{}`", self.method),
+        };
+        Ok(exprs)
+    }
+}
+
+struct ShellHelper {
+    uv: Universe,
+    dbname: Arc<Mutex<Option<String>>>,
+}
+
+impl ShellHelper {
+    /// Lists names from the server, so that completions are up to date
+    /// without a request on every prompt.
+    fn names(&self) -> Names {
+        let dbname = self.dbname.lock().unwrap().clone();
+        // Completion runs within `block_in_place` of the prompt, so it can
+        // block on the runtime.
+        let handle = tokio::runtime::Handle::current();
+        handle
+            .block_on(Names::list(&self.uv, dbname.as_deref()))
+            .unwrap_or_default()
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = complete(&line[..pos], || self.names());
+        let pairs = candidates
+            .into_iter()
+            .map(|x| Pair {
+                display: x.clone(),
+                replacement: x,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+/// Returns the start of the word to complete at the end of a line and the
+/// candidates for it. Names are only listed if they are candidates.
+fn complete(line: &str, names: impl FnOnce() -> Names) -> (usize, Vec<String>) {
+    let start = line
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &line[start..];
+    let before = line[..start].trim_end();
+    let candidates: Vec<String> = if line[..start].ends_with('.') {
+        METHODS.iter().map(|x| x.to_string()).collect()
+    } else if before.ends_with("use") {
+        names().databases
+    } else if before.ends_with("show") {
+        vec!["databases".to_owned(), "collections".to_owned()]
+    } else {
+        names()
+            .collections
+            .into_iter()
+            .chain(KEYWORDS.iter().map(|x| x.to_string()))
+            .collect()
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|x| x.starts_with(word))
+        .collect();
+    (start, candidates)
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(split_statements("use db"), vec!["use db"]);
+        assert_eq!(
+            split_statements("use db; show collections;"),
+            vec!["use db", " show collections", ""]
+        );
+        // Separators and escaped quotes in strings are kept.
+        assert_eq!(
+            split_statements(r#"a.set("x;y", 1); a.get("x;y")"#),
+            vec![r#"a.set("x;y", 1)"#, r#" a.get("x;y")"#]
+        );
+        assert_eq!(
+            split_statements(r#"a.set("x\";y", 1); help"#),
+            vec![r#"a.set("x\";y", 1)"#, " help"]
+        );
+        assert_eq!(
+            split_statements(r#"a.set("x\\", 1); help"#),
+            vec![r#"a.set("x\\", 1)"#, " help"]
+        );
+    }
+
+    #[test]
+    fn test_parse_call() -> Result<()> {
+        let call = Call::parse(r#" users . set ("u1", {"name": "alice"}) "#)?;
+        assert_eq!(call.collection, "users");
+        assert_eq!(call.method, "set");
+        assert_eq!(call.id, "u1");
+        assert_eq!(call.args.len(), 1);
+        let (select, mutate) = call.expr()?;
+        assert!(select.is_none());
+        assert_eq!(mutate.unwrap().func, MutateFunction::Set as i32);
+
+        let call = Call::parse(r#"users.get("u1")"#)?;
+        assert!(call.args.is_empty());
+        let (select, mutate) = call.expr()?;
+        assert_eq!(select.unwrap().func, SelectFunction::Get as i32);
+        assert!(mutate.is_none());
+
+        // Trim takes its argument as the index.
+        let call = Call::parse(r#"queue.trim("q", 1)"#)?;
+        let mutate = call.expr()?.1.unwrap();
+        assert!(mutate.args.is_empty());
+        assert_eq!(mutate.index, Some(1i64.into()));
+
+        for stmt in [
+            "users",
+            "users.get",
+            r#"users("u1")"#,
+            r#"users.get("u1""#,
+            "users.get()",
+            "users.get(1)",
+            r#"users.get("u1",)"#,
+        ] {
+            assert!(Call::parse(stmt).is_err(), "{}", stmt);
+        }
+        let call = Call::parse(r#"users.fly("u1")"#)?;
+        assert!(call.expr().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete() {
+        let names = || Names {
+            databases: vec!["db1".to_owned(), "db2".to_owned()],
+            collections: vec!["users".to_owned(), "queue".to_owned()],
+        };
+        let no_names = || -> Names { panic!("names are listed") };
+        assert_eq!(
+            complete("us", names),
+            (0, vec!["users".to_owned(), "use".to_owned()])
+        );
+        assert_eq!(
+            complete("use d", names),
+            (4, vec!["db1".to_owned(), "db2".to_owned()])
+        );
+        assert_eq!(
+            complete("show c", no_names),
+            (5, vec!["collections".to_owned()])
+        );
+        assert_eq!(
+            complete("users.l", no_names),
+            (
+                6,
+                vec!["len".to_owned(), "lpop".to_owned(), "lpush".to_owned()]
+            )
+        );
+        assert_eq!(complete("use db; q", names), (8, vec!["queue".to_owned()]));
+        assert_eq!(complete("x", names), (0, vec![]));
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{future::Future, time::Duration};
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::info;
+
+/// Notifies commands that the process is requested to shut down by SIGINT or
+/// SIGTERM.
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Starts listening to shutdown signals.
+    pub fn listen() -> Result<Self> {
+        let (sender, receiver) = watch::channel(false);
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            info!("Shutting down...");
+            sender.send(true).unwrap_or_default();
+        });
+        Ok(Self { receiver })
+    }
+
+    /// Waits until a shutdown signal is received.
+    pub async fn wait(mut self) {
+        while !*self.receiver.borrow() {
+            if self.receiver.changed().await.is_err() {
+                // The listener is gone, so no signal will be received.
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Runs `f` until it completes or a shutdown signal is received.
+    pub async fn cancel<F>(&self, f: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        tokio::select! {
+            res = f => res,
+            _ = self.clone().wait() => Ok(()),
+        }
+    }
+
+    /// Runs a server that stops serving on shutdown signals, and waits at most
+    /// `timeout` for the server to finish in-flight requests.
+    pub async fn drain<F>(&self, timeout: Duration, server: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        tokio::pin!(server);
+        tokio::select! {
+            res = &mut server => return res,
+            _ = self.clone().wait() => {}
+        }
+        match tokio::time::timeout(timeout, server).await {
+            Ok(res) => res,
+            Err(_) => Err(anyhow!(
+                "in-flight requests are not finished in {:?}",
+                timeout
+            )),
+        }
+    }
+}
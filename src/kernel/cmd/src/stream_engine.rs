@@ -0,0 +1,411 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use futures::{StreamExt, TryFutureExt};
+use serde_json::{json, Value as Json};
+use stream_engine_client::{Engine, Error, Role, Tenant};
+use stream_engine_master::Server as MasterServer;
+use stream_engine_store::{Server as StoreServer, StreamDb};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tracing::info;
+
+use crate::{
+    config::{invalid, Config},
+    output::Format,
+    shutdown::Shutdown,
+};
+
+#[derive(Parser)]
+pub struct Command {
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+impl Command {
+    pub async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        self.subcmd.run(config, shutdown).await?;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+enum SubCommand {
+    Master(MasterCommand),
+    Store(StoreCommand),
+    Tenant(TenantCommand),
+    Stream(StreamCommand),
+}
+
+impl SubCommand {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        match self {
+            SubCommand::Master(cmd) => cmd.run(config, shutdown).await,
+            SubCommand::Store(cmd) => cmd.run(config, shutdown).await,
+            SubCommand::Tenant(cmd) => cmd.run(shutdown).await,
+            SubCommand::Stream(cmd) => cmd.run(shutdown).await,
+        }
+    }
+}
+
+#[derive(Parser)]
+struct MasterCommand {
+    #[clap(subcommand)]
+    subcmd: MasterSubCommand,
+}
+
+impl MasterCommand {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        match self.subcmd {
+            MasterSubCommand::Start(cmd) => cmd.run(config, shutdown).await,
+        }
+    }
+}
+
+#[derive(Parser)]
+enum MasterSubCommand {
+    Start(MasterStartCommand),
+}
+
+#[derive(Parser)]
+struct MasterStartCommand {
+    #[clap(long)]
+    addr: Option<String>,
+    /// The urls of the stores that replicate streams.
+    #[clap(long, multiple_values = true)]
+    stores: Vec<String>,
+    /// Observer heartbeat intervals in ms.
+    #[clap(long)]
+    heartbeat_interval_ms: Option<u64>,
+    /// How many ticks before an observer's lease is timeout.
+    #[clap(long)]
+    heartbeat_timeout_tick: Option<u64>,
+}
+
+impl MasterStartCommand {
+    async fn run(self, mut config: Config, shutdown: Shutdown) -> Result<()> {
+        let master_config = &mut config.stream_engine.master;
+        if let Some(addr) = self.addr {
+            master_config.addr = addr;
+        }
+        if !self.stores.is_empty() {
+            master_config.stores = self.stores;
+        }
+        if let Some(interval) = self.heartbeat_interval_ms {
+            master_config.heartbeat_interval_ms = interval;
+        }
+        if let Some(tick) = self.heartbeat_timeout_tick {
+            master_config.heartbeat_timeout_tick = tick;
+        }
+        config.validate()?;
+
+        let master_config = config.stream_engine.master;
+        if master_config.stores.is_empty() {
+            return Err(invalid("stream_engine.master.stores", "must not be empty"));
+        }
+        let listener = TcpListener::bind(&master_config.addr).await?;
+        let addr = listener.local_addr()?;
+        info!(message = "The stream-engine master is running at", %addr);
+
+        let master_server =
+            MasterServer::with_config(master_config.master_config(), master_config.stores);
+        let server = tonic::transport::Server::builder()
+            .add_service(master_server.into_service())
+            .serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                shutdown.clone().wait(),
+            );
+        shutdown
+            .drain(
+                config.server.shutdown_timeout(),
+                server.map_err(|e| e.into()),
+            )
+            .await
+    }
+}
+
+#[derive(Parser)]
+struct StoreCommand {
+    #[clap(subcommand)]
+    subcmd: StoreSubCommand,
+}
+
+impl StoreCommand {
+    async fn run(self, config: Config, shutdown: Shutdown) -> Result<()> {
+        match self.subcmd {
+            StoreSubCommand::Start(cmd) => cmd.run(config, shutdown).await,
+        }
+    }
+}
+
+#[derive(Parser)]
+enum StoreSubCommand {
+    Start(StoreStartCommand),
+}
+
+#[derive(Parser)]
+struct StoreStartCommand {
+    #[clap(long)]
+    addr: Option<String>,
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
+    /// Creates the store if it does not exist.
+    #[clap(long)]
+    create_if_missing: bool,
+    /// Responds before log data are synced.
+    #[clap(long)]
+    no_sync_data: bool,
+    /// The number of bytes per log file, it must be a power of 2.
+    #[clap(long)]
+    log_file_size: Option<usize>,
+    /// The maximum number of log files.
+    #[clap(long)]
+    max_log_files: Option<usize>,
+}
+
+impl StoreStartCommand {
+    async fn run(self, mut config: Config, shutdown: Shutdown) -> Result<()> {
+        let store_config = &mut config.stream_engine.store;
+        if let Some(addr) = self.addr {
+            store_config.addr = addr;
+        }
+        if let Some(data_dir) = self.data_dir {
+            store_config.data_dir = data_dir;
+        }
+        store_config.create_if_missing |= self.create_if_missing;
+        store_config.sync_data &= !self.no_sync_data;
+        if let Some(size) = self.log_file_size {
+            store_config.log_file_size = size;
+        }
+        if let Some(num) = self.max_log_files {
+            store_config.max_log_files = num;
+        }
+        config.validate()?;
+
+        let store_config = &config.stream_engine.store;
+        let db = StreamDb::open(&store_config.data_dir, store_config.db_option())?;
+
+        let listener = TcpListener::bind(&store_config.addr).await?;
+        let addr = listener.local_addr()?;
+        info!(message = "The stream-engine store is running at", %addr);
+
+        let server = tonic::transport::Server::builder()
+            .add_service(StoreServer::new(db.clone()).into_service())
+            .serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                shutdown.clone().wait(),
+            );
+        let res = shutdown
+            .drain(
+                config.server.shutdown_timeout(),
+                server.map_err(|e| e.into()),
+            )
+            .await;
+        // Flushes the log and manifest files after the server stops.
+        db.close()?;
+        res
+    }
+}
+
+/// Options shared by commands that talk to a stream-engine master.
+#[derive(Parser)]
+struct AdminOptions {
+    #[clap(long, global = true, default_value = "http://localhost:21718")]
+    master: String,
+    /// The observer id reported to the master, defaults to the process id.
+    #[clap(long, global = true)]
+    observer_id: Option<String>,
+    #[clap(long, global = true, arg_enum, default_value = "table")]
+    output: Format,
+}
+
+impl AdminOptions {
+    async fn connect(&self) -> Result<Engine> {
+        let id = self
+            .observer_id
+            .clone()
+            .unwrap_or_else(|| std::process::id().to_string());
+        let engine = Engine::new(id, self.master.clone()).await?;
+        Ok(engine)
+    }
+}
+
+#[derive(Parser)]
+struct TenantCommand {
+    #[clap(flatten)]
+    opts: AdminOptions,
+    #[clap(subcommand)]
+    subcmd: TenantSubCommand,
+}
+
+impl TenantCommand {
+    async fn run(self, shutdown: Shutdown) -> Result<()> {
+        let engine = self.opts.connect().await?;
+        let res = shutdown.cancel(self.subcmd.run(&self.opts, &engine)).await;
+        engine.close().await;
+        res
+    }
+}
+
+#[derive(Parser)]
+enum TenantSubCommand {
+    Create { name: String },
+    List,
+    Describe { name: String },
+    Delete { name: String },
+}
+
+impl TenantSubCommand {
+    async fn run(self, opts: &AdminOptions, engine: &Engine) -> Result<()> {
+        match self {
+            TenantSubCommand::Create { name } => {
+                engine.create_tenant(&name).await?;
+            }
+            TenantSubCommand::List => {
+                let rows = engine
+                    .list_tenants()
+                    .await?
+                    .into_iter()
+                    .map(|desc| json!({ "id": desc.id, "name": desc.name }))
+                    .collect();
+                opts.output.print_rows(TENANT_COLUMNS, rows)?;
+            }
+            TenantSubCommand::Describe { name } => {
+                let desc = engine.tenant(&name).desc().await?;
+                let row = json!({ "id": desc.id, "name": desc.name });
+                opts.output.print_rows(TENANT_COLUMNS, vec![row])?;
+            }
+            TenantSubCommand::Delete { name } => {
+                engine.delete_tenant(&name).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const TENANT_COLUMNS: &[&str] = &["id", "name"];
+
+#[derive(Parser)]
+struct StreamCommand {
+    #[clap(flatten)]
+    opts: AdminOptions,
+    /// The tenant that streams belong to.
+    #[clap(long, short)]
+    tenant: String,
+    #[clap(subcommand)]
+    subcmd: StreamSubCommand,
+}
+
+impl StreamCommand {
+    async fn run(self, shutdown: Shutdown) -> Result<()> {
+        let engine = self.opts.connect().await?;
+        let tenant = engine.tenant(&self.tenant);
+        let res = shutdown.cancel(self.subcmd.run(&self.opts, &tenant)).await;
+        engine.close().await;
+        res
+    }
+}
+
+#[derive(Parser)]
+enum StreamSubCommand {
+    Create {
+        name: String,
+    },
+    List,
+    Describe {
+        name: String,
+    },
+    Delete {
+        name: String,
+    },
+    /// Appends an event and prints its sequence.
+    Append {
+        name: String,
+        event: String,
+    },
+    /// Prints events from a sequence and waits for new ones.
+    Tail {
+        name: String,
+        #[clap(long, default_value = "0")]
+        start: u64,
+    },
+}
+
+impl StreamSubCommand {
+    async fn run(self, opts: &AdminOptions, tenant: &Tenant) -> Result<()> {
+        match self {
+            StreamSubCommand::Create { name } => {
+                tenant.create_stream(&name).await?;
+            }
+            StreamSubCommand::List => {
+                let rows = tenant
+                    .list_streams()
+                    .await?
+                    .into_iter()
+                    .map(|desc| json!({ "id": desc.id, "name": desc.name }))
+                    .collect();
+                opts.output.print_rows(STREAM_COLUMNS, rows)?;
+            }
+            StreamSubCommand::Describe { name } => {
+                let desc = tenant.stream_desc(&name).await?;
+                let row = json!({ "id": desc.id, "name": desc.name });
+                opts.output.print_rows(STREAM_COLUMNS, vec![row])?;
+            }
+            StreamSubCommand::Delete { name } => {
+                tenant.delete_stream(&name).await?;
+            }
+            StreamSubCommand::Append { name, event } => {
+                let seq = append_event(tenant, &name, event.into_bytes()).await?;
+                opts.output.print_value(json!(seq))?;
+            }
+            StreamSubCommand::Tail { name, start } => {
+                let stream = tenant.stream(&name).await?;
+                let mut reader = stream.new_reader().await?;
+                reader.seek(start).await?;
+                loop {
+                    let event = reader.wait_next().await?;
+                    let event = String::from_utf8_lossy(&event).into_owned();
+                    opts.output.print_value(Json::String(event))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const STREAM_COLUMNS: &[&str] = &["id", "name"];
+
+/// Appends an event once this observer becomes the leader of the stream.
+async fn append_event(tenant: &Tenant, name: &str, event: Vec<u8>) -> Result<u64> {
+    let stream = tenant.stream(name).await?;
+    let mut states = stream.subscribe_state().await?;
+    while let Some(state) = states.next().await {
+        if state.role != Role::Leader {
+            continue;
+        }
+        match stream.append(event.clone().into()).await {
+            Ok(seq) => return Ok(seq),
+            Err(Error::NotLeader(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(anyhow!(
+        "stream {} is closed before the event is appended",
+        name
+    ))
+}
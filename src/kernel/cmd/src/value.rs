@@ -0,0 +1,259 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, bail, Result};
+use engula_apis::v1::{value, ListValue, MapValue, Value};
+use serde_json::{Map, Number, Value as Json};
+
+/// The key of a JSON object that represents a blob, e.g. `{"$blob": [1, 2]}`.
+const BLOB_KEY: &str = "$blob";
+
+/// Parses a value from JSON.
+///
+/// Numbers, strings, arrays and objects are converted to i64 or f64, text,
+/// list and map values respectively. Arrays must contain elements of the
+/// same type. Blobs are written as `{"$blob": [<bytes>]}`.
+pub fn parse(s: &str) -> Result<Value> {
+    let json: Json = serde_json::from_str(s)?;
+    from_json(json)
+}
+
+pub fn from_json(json: Json) -> Result<Value> {
+    let value = match json {
+        Json::Null => return Ok(Value::default()),
+        Json::Number(n) => number_from_json(n)?,
+        Json::String(s) => value::Value::TextValue(s),
+        Json::Array(a) => value::Value::ListValue(list_from_json(a)?),
+        Json::Object(mut m) => {
+            if m.len() == 1 && m.contains_key(BLOB_KEY) {
+                value::Value::BlobValue(blob_from_json(m.remove(BLOB_KEY).unwrap())?)
+            } else {
+                value::Value::MapValue(map_from_json(m)?)
+            }
+        }
+        Json::Bool(_) => bail!("boolean values are not supported"),
+    };
+    Ok(Value { value: Some(value) })
+}
+
+fn number_from_json(n: Number) -> Result<value::Value> {
+    if let Some(v) = n.as_i64() {
+        Ok(value::Value::I64Value(v))
+    } else if let Some(v) = n.as_f64() {
+        Ok(value::Value::F64Value(v))
+    } else {
+        Err(anyhow!("invalid number {}", n))
+    }
+}
+
+fn blob_from_json(json: Json) -> Result<Vec<u8>> {
+    let bytes = match json {
+        Json::Array(a) => a,
+        _ => bail!("a blob must be an array of bytes"),
+    };
+    bytes
+        .into_iter()
+        .map(|b| {
+            b.as_u64()
+                .and_then(|b| u8::try_from(b).ok())
+                .ok_or_else(|| anyhow!("invalid byte {}", b))
+        })
+        .collect()
+}
+
+fn list_from_json(elems: Vec<Json>) -> Result<ListValue> {
+    let mut list = ListValue::default();
+    for elem in elems {
+        match from_json(elem)?.value {
+            Some(value::Value::I64Value(v)) if list.f64_value.is_empty() => list.i64_value.push(v),
+            Some(value::Value::I64Value(v)) => list.f64_value.push(v as f64),
+            Some(value::Value::F64Value(v)) => {
+                // Promotes integers to floats if the list mixes them.
+                list.f64_value
+                    .extend(list.i64_value.drain(..).map(|x| x as f64));
+                list.f64_value.push(v);
+            }
+            Some(value::Value::BlobValue(v)) => list.blob_value.push(v),
+            Some(value::Value::TextValue(v)) => list.text_value.push(v),
+            _ => bail!("unsupported list element"),
+        }
+    }
+    let kinds = [
+        list.i64_value.is_empty(),
+        list.f64_value.is_empty(),
+        list.blob_value.is_empty(),
+        list.text_value.is_empty(),
+    ];
+    if kinds.iter().filter(|empty| !**empty).count() > 1 {
+        bail!("list elements must have the same type");
+    }
+    Ok(list)
+}
+
+fn map_from_json(m: Map<String, Json>) -> Result<MapValue> {
+    let keys = ListValue {
+        text_value: m.keys().cloned().collect(),
+        ..Default::default()
+    };
+    let values = list_from_json(m.into_iter().map(|(_, v)| v).collect())?;
+    Ok(MapValue::from((keys, values)))
+}
+
+/// Converts a value to JSON, the reverse of [`from_json`].
+pub fn to_json(value: Value) -> Json {
+    match value.value {
+        None => Json::Null,
+        Some(value::Value::I64Value(v)) => v.into(),
+        Some(value::Value::F64Value(v)) => v.into(),
+        Some(value::Value::TextValue(v)) => v.into(),
+        Some(value::Value::BlobValue(v)) => blob_to_json(v),
+        Some(value::Value::ListValue(v)) => Json::Array(list_to_json(v)),
+        Some(value::Value::MapValue(v)) => {
+            let keys = list_to_json(v.keys.unwrap_or_default());
+            let values = list_to_json(v.values.unwrap_or_default());
+            let all_text = keys.iter().all(|k| k.is_string());
+            if all_text {
+                let m = keys
+                    .into_iter()
+                    .map(|k| k.as_str().unwrap().to_owned())
+                    .zip(values)
+                    .collect();
+                Json::Object(m)
+            } else {
+                // Non-text keys can't be JSON object keys.
+                let pairs = keys
+                    .into_iter()
+                    .zip(values)
+                    .map(|(k, v)| Json::Array(vec![k, v]))
+                    .collect();
+                Json::Array(pairs)
+            }
+        }
+        Some(v) => Json::String(format!("{:?}", v)),
+    }
+}
+
+fn blob_to_json(v: Vec<u8>) -> Json {
+    let mut m = Map::new();
+    m.insert(BLOB_KEY.to_owned(), v.into());
+    Json::Object(m)
+}
+
+fn list_to_json(v: ListValue) -> Vec<Json> {
+    let mut elems: Vec<Json> = Vec::new();
+    elems.extend(v.i64_value.into_iter().map(Json::from));
+    elems.extend(v.f64_value.into_iter().map(Json::from));
+    elems.extend(v.blob_value.into_iter().map(blob_to_json));
+    elems.extend(v.text_value.into_iter().map(Json::from));
+    elems
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn round_trip(s: &str) -> Result<Json> {
+        Ok(to_json(parse(s)?))
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let cases = [
+            "null",
+            "1",
+            "-2",
+            "1.5",
+            r#""text""#,
+            r#"{"$blob": []}"#,
+            r#"{"$blob": [0, 1, 255]}"#,
+            "[]",
+            "[1, 2, 3]",
+            "[1.5, 2.5]",
+            r#"["a", "b"]"#,
+            r#"[{"$blob": [1]}, {"$blob": [2, 3]}]"#,
+            "{}",
+            r#"{"a": 1, "b": 2}"#,
+            r#"{"a": "x", "b": "y"}"#,
+            r#"{"a": {"$blob": [1]}}"#,
+        ];
+        for s in cases {
+            let json: Json = serde_json::from_str(s)?;
+            assert_eq!(round_trip(s)?, json, "{}", s);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_kinds() -> Result<()> {
+        assert_eq!(parse("null")?.value, None);
+        assert_eq!(parse("1")?.value, Some(value::Value::I64Value(1)));
+        assert_eq!(parse("1.0")?.value, Some(value::Value::F64Value(1.0)));
+        assert_eq!(
+            parse(r#""1""#)?.value,
+            Some(value::Value::TextValue("1".to_owned()))
+        );
+        assert_eq!(
+            parse(r#"{"$blob": [1]}"#)?.value,
+            Some(value::Value::BlobValue(vec![1]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_numbers() -> Result<()> {
+        // Integers are promoted to floats if a list mixes them.
+        assert_eq!(round_trip("[1, 2.5]")?, json!([1.0, 2.5]));
+        assert_eq!(round_trip("[2.5, 1]")?, json!([2.5, 1.0]));
+        assert_eq!(
+            round_trip(r#"{"a": 1, "b": 2.5}"#)?,
+            json!({"a": 1.0, "b": 2.5})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_text_keys() {
+        let keys = ListValue {
+            i64_value: vec![1, 2],
+            ..Default::default()
+        };
+        let values = ListValue {
+            text_value: vec!["a".to_owned(), "b".to_owned()],
+            ..Default::default()
+        };
+        let value: Value = MapValue::from((keys, values)).into();
+        assert_eq!(to_json(value), json!([[1, "a"], [2, "b"]]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let cases = [
+            "",
+            "{",
+            "true",
+            "[null]",
+            "[[1]]",
+            r#"[1, "a"]"#,
+            r#"{"$blob": "a"}"#,
+            r#"{"$blob": [256]}"#,
+            r#"{"$blob": [-1]}"#,
+            r#"{"a": 1, "b": "x"}"#,
+        ];
+        for s in cases {
+            assert!(parse(s).is_err(), "{}", s);
+        }
+    }
+}
@@ -23,6 +23,10 @@ pub enum Error {
     #[error("{0}")]
     InvalidArgument(String),
     #[error("{0}")]
+    Aborted(String),
+    #[error("{0}")]
+    ResourceExhausted(String),
+    #[error("{0}")]
     DataLoss(String),
     #[error("{0}")]
     Internal(String),
@@ -37,6 +41,14 @@ impl Error {
         Self::InvalidArgument(m.into())
     }
 
+    pub fn aborted(m: impl Into<String>) -> Self {
+        Self::Aborted(m.into())
+    }
+
+    pub fn resource_exhausted(m: impl Into<String>) -> Self {
+        Self::ResourceExhausted(m.into())
+    }
+
     pub fn dataloss(m: impl Into<String>) -> Self {
         Self::DataLoss(m.into())
     }
@@ -56,6 +68,8 @@ impl From<tonic::Status> for Error {
             tonic::Code::NotFound => Error::NotFound(s.message().into()),
             tonic::Code::AlreadyExists => Error::AlreadyExists(s.message().into()),
             tonic::Code::InvalidArgument => Error::InvalidArgument(s.message().into()),
+            tonic::Code::Aborted => Error::Aborted(s.message().into()),
+            tonic::Code::ResourceExhausted => Error::ResourceExhausted(s.message().into()),
             tonic::Code::DataLoss => Error::DataLoss(s.message().into()),
             tonic::Code::Internal => Error::Internal(s.message().into()),
             _ => Error::Unknown(Box::new(s)),
@@ -75,6 +89,8 @@ impl From<Error> for tonic::Status {
             Error::NotFound(s) => (tonic::Code::NotFound, s),
             Error::AlreadyExists(s) => (tonic::Code::AlreadyExists, s),
             Error::InvalidArgument(s) => (tonic::Code::InvalidArgument, s),
+            Error::Aborted(s) => (tonic::Code::Aborted, s),
+            Error::ResourceExhausted(s) => (tonic::Code::ResourceExhausted, s),
             Error::DataLoss(s) => (tonic::Code::DataLoss, s),
             Error::Internal(s) => (tonic::Code::Internal, s),
             Error::Io(s) => (tonic::Code::Unknown, s.to_string()),
@@ -85,3 +101,35 @@ impl From<Error> for tonic::Status {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_status() {
+        let cases = [
+            (Error::NotFound("a".into()), tonic::Code::NotFound),
+            (Error::AlreadyExists("a".into()), tonic::Code::AlreadyExists),
+            (Error::invalid_argument("a"), tonic::Code::InvalidArgument),
+            (Error::aborted("a"), tonic::Code::Aborted),
+            (
+                Error::resource_exhausted("a"),
+                tonic::Code::ResourceExhausted,
+            ),
+            (Error::dataloss("a"), tonic::Code::DataLoss),
+            (Error::internal("a"), tonic::Code::Internal),
+        ];
+        for (err, code) in cases {
+            let status = tonic::Status::from(err);
+            assert_eq!(status.code(), code);
+            assert_eq!(status.message(), "a");
+        }
+
+        // Errors without a matching code are unknown to clients.
+        let io = std::io::Error::new(std::io::ErrorKind::Other, "a");
+        let status = tonic::Status::from(Error::from(io));
+        assert_eq!(status.code(), tonic::Code::Unknown);
+        assert_eq!(status.message(), "a");
+    }
+}
@@ -33,4 +33,21 @@ impl Args {
         v.try_into()
             .map_err(|_| Error::invalid_argument("argument type mismatch"))
     }
+
+    pub fn take_opt<T: TryFrom<Value>>(&mut self) -> Result<Option<T>> {
+        if self.0.is_empty() {
+            Ok(None)
+        } else {
+            self.take().map(Some)
+        }
+    }
+
+    /// Checks that all arguments are taken.
+    pub fn finish(&self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::invalid_argument("too many arguments"))
+        }
+    }
 }
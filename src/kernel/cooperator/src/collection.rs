@@ -16,74 +16,391 @@ use std::{
     collections::HashMap,
     ops::{Bound, RangeBounds},
     sync::Arc,
+    time::Duration,
 };
 
 use engula_apis::v1::*;
 use prost::Message;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 
-use crate::{Args, Error, Result, Write, WriteBatch};
+use crate::{
+    apis::{ObjectError, ObjectVersion, ScanObject, ScanRequest, ScanResponse},
+    write_batch::Owner,
+    Args, Error, Quota, RateLimiter, Result, Usage, Write, WriteBatch,
+};
+
+struct Object {
+    value: Value,
+    /// The commit version of the last write to this object.
+    version: u64,
+}
+
+impl Object {
+    fn size(id: &[u8], value: &Value) -> u64 {
+        (id.len() + value.encoded_len()) as u64
+    }
+}
+
+/// Tracks the usage of a collection as writes are applied in order.
+struct UsageTracker<'a> {
+    objects: &'a HashMap<Vec<u8>, Object>,
+    usage: Usage,
+    // The sizes of objects written by previous writes.
+    written: HashMap<&'a [u8], Option<u64>>,
+    // The previous entries of `written`, to roll back writes.
+    undo: Vec<(&'a [u8], Option<Option<u64>>)>,
+}
+
+impl<'a> UsageTracker<'a> {
+    fn new(objects: &'a HashMap<Vec<u8>, Object>, usage: Usage) -> Self {
+        Self {
+            objects,
+            usage,
+            written: HashMap::new(),
+            undo: Vec::new(),
+        }
+    }
+
+    /// Applies a write, which must not exceed the value size limits.
+    fn apply(&mut self, write: &'a Write, quota: &Quota, db_quota: &Quota) -> Result<()> {
+        let (id, new_size) = match write {
+            Write::Put(id, value) => {
+                let size = value.encoded_len();
+                quota.check_value_size(size)?;
+                db_quota.check_value_size(size)?;
+                (id.as_slice(), Some(Object::size(id, value)))
+            }
+            Write::Delete(id) => (id.as_slice(), None),
+        };
+        let old_size = self
+            .written
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| self.objects.get(id).map(|ob| Object::size(id, &ob.value)));
+        if let Some(size) = old_size {
+            self.usage.objects -= 1;
+            self.usage.bytes -= size;
+        }
+        if let Some(size) = new_size {
+            self.usage.objects += 1;
+            self.usage.bytes += size;
+        }
+        let prev = self.written.insert(id, new_size);
+        self.undo.push((id, prev));
+        Ok(())
+    }
+
+    fn savepoint(&self) -> (usize, Usage) {
+        (self.undo.len(), self.usage)
+    }
+
+    fn rollback(&mut self, (len, usage): (usize, Usage)) {
+        for (id, prev) in self.undo.drain(len..).rev() {
+            match prev {
+                Some(size) => self.written.insert(id, size),
+                None => self.written.remove(id),
+            };
+        }
+        self.usage = usage;
+    }
+}
 
 #[derive(Clone)]
 pub struct Collection {
-    objects: Arc<Mutex<HashMap<Vec<u8>, Value>>>,
+    id: u64,
+    objects: Arc<Mutex<HashMap<Vec<u8>, Object>>>,
+    waiters: Arc<Mutex<HashMap<Vec<u8>, Vec<Arc<Notify>>>>>,
+    quota: Quota,
+    usage: Arc<Mutex<Usage>>,
+    limiter: Arc<Mutex<RateLimiter>>,
 }
 
 impl Collection {
-    pub fn new(_: CollectionDesc) -> Self {
+    pub fn new(desc: CollectionDesc, quota: Quota) -> Self {
+        let limiter = RateLimiter::new(quota.max_requests_per_second);
         Self {
+            id: desc.id,
             objects: Arc::new(Mutex::new(HashMap::new())),
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+            quota,
+            usage: Arc::new(Mutex::new(Usage::default())),
+            limiter: Arc::new(Mutex::new(limiter)),
         }
     }
 
+    /// Returns a collection that shares the objects of this one but applies
+    /// another quota.
+    pub fn with_quota(&self, quota: Quota) -> Self {
+        let limiter = RateLimiter::new(quota.max_requests_per_second);
+        Self {
+            quota,
+            limiter: Arc::new(Mutex::new(limiter)),
+            ..self.clone()
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub async fn usage(&self) -> Usage {
+        *self.usage.lock().await
+    }
+
+    /// Checks the rate limit of this collection without taking a token.
+    pub async fn check_rate(&self) -> Result<()> {
+        self.limiter.lock().await.check()
+    }
+
+    /// Takes a token from the rate limit of this collection, which must be
+    /// checked before.
+    pub async fn take_rate(&self) {
+        self.limiter.lock().await.take()
+    }
+
+    /// Checks batches against the quota of this collection and the quota of
+    /// the database it belongs to.
+    ///
+    /// Returns the usage of this collection after the batches are written in
+    /// order.
+    pub async fn check_quota(&self, wbs: &[&WriteBatch], db_quota: &Quota) -> Result<Usage> {
+        let objects = self.objects.lock().await;
+        let before = self.usage().await;
+        let mut tracker = UsageTracker::new(&objects, before);
+        for write in wbs.iter().flat_map(|wb| &wb.writes) {
+            tracker.apply(write, &self.quota, db_quota)?;
+        }
+        self.quota.check_usage(before, tracker.usage)?;
+        Ok(tracker.usage)
+    }
+
+    /// Checks batches against the quotas object by object, in best-effort
+    /// mode.
+    ///
+    /// The writes of an object that exceeds the quota of this collection or
+    /// the database are discarded, and the object is returned with the index
+    /// of its batch and the error. `db_usage` is the usage of the database,
+    /// which is updated with the writes that are kept.
+    pub async fn check_quota_best_effort(
+        &self,
+        wbs: &mut [&mut WriteBatch],
+        db_quota: &Quota,
+        db_usage: &mut Usage,
+    ) -> Vec<(usize, Owner, Error)> {
+        let objects = self.objects.lock().await;
+        let mut failed = Vec::new();
+        {
+            let mut tracker = UsageTracker::new(&objects, self.usage().await);
+            for (index, wb) in wbs.iter().enumerate() {
+                // The writes of an object are contiguous in a batch.
+                let mut start = 0;
+                while start < wb.writes.len() {
+                    let owner = wb.owners[start];
+                    let end = wb.owners[start..]
+                        .iter()
+                        .position(|x| *x != owner)
+                        .map_or(wb.writes.len(), |n| start + n);
+                    let savepoint = tracker.savepoint();
+                    let before = tracker.usage;
+                    let mut db_after = *db_usage;
+                    let res = wb.writes[start..end]
+                        .iter()
+                        .try_for_each(|write| tracker.apply(write, &self.quota, db_quota))
+                        .and_then(|_| self.quota.check_usage(before, tracker.usage))
+                        .and_then(|_| {
+                            db_after.sub(before);
+                            db_after.add(tracker.usage);
+                            db_quota.check_usage(*db_usage, db_after)
+                        });
+                    match res {
+                        Ok(()) => *db_usage = db_after,
+                        Err(err) => {
+                            tracker.rollback(savepoint);
+                            failed.push((index, owner, err));
+                        }
+                    }
+                    start = end;
+                }
+            }
+        }
+        for (index, owner, _) in &failed {
+            wbs[*index].discard(*owner);
+        }
+        failed
+    }
+
     async fn get<T: TryFrom<Value>>(&self, id: &[u8]) -> Result<T> {
         let objects = self.objects.lock().await;
-        let ob = objects.get(id).cloned().unwrap_or_default();
+        let ob = objects.get(id).map(|x| x.value.clone()).unwrap_or_default();
         ob.try_into()
             .map_err(|_| Error::invalid_argument("object type mismatch"))
     }
 
+    /// Returns the version of an object, or zero if it doesn't exist.
+    async fn version(&self, id: &[u8]) -> u64 {
+        let objects = self.objects.lock().await;
+        objects.get(id).map(|x| x.version).unwrap_or_default()
+    }
+
+    /// Checks the version of an object against an expected version from
+    /// arguments, if any.
+    ///
+    /// An object written earlier in the batch has the version of the batch,
+    /// so that only one of the conditional writes to it succeeds.
+    async fn check_version(&self, wb: &WriteBatch, id: &[u8], expected: Option<i64>) -> Result<()> {
+        if let Some(expected) = expected {
+            let version = if wb.is_written(id) {
+                wb.version
+            } else {
+                self.version(id).await
+            };
+            if expected < 0 || expected as u64 != version {
+                return Err(Error::aborted(format!(
+                    "version mismatch, expected {} but got {}",
+                    expected, version
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the objects that a scan request selects in the order of ids.
+    pub async fn scan(&self, req: &ScanRequest) -> ScanResponse {
+        let objects = self.objects.lock().await;
+        let mut ids: Vec<&Vec<u8>> = objects
+            .keys()
+            .filter(|id| id.starts_with(&req.prefix) && **id >= req.start)
+            .collect();
+        ids.sort();
+        let mut res = ScanResponse::default();
+        let limit = req.limit as usize;
+        if limit > 0 && ids.len() > limit {
+            res.next = ids[limit].clone();
+            ids.truncate(limit);
+        }
+        res.objects = ids
+            .into_iter()
+            .map(|id| {
+                let ob = &objects[id];
+                ScanObject {
+                    id: id.clone(),
+                    value: Some(ob.value.clone()),
+                    version: ob.version,
+                }
+            })
+            .collect();
+        res
+    }
+
+    /// Registers `notify` to be notified on the next write to any of `ids`.
+    pub async fn wait(&self, ids: &[Vec<u8>], notify: Arc<Notify>) {
+        let mut waiters = self.waiters.lock().await;
+        for id in ids {
+            let list = waiters.entry(id.clone()).or_default();
+            // Drops waiters that have already given up.
+            list.retain(|x| Arc::strong_count(x) > 1);
+            list.push(notify.clone());
+        }
+    }
+
     pub async fn write(&self, wb: WriteBatch) {
         let mut objects = self.objects.lock().await;
+        let mut waiters = self.waiters.lock().await;
+        let mut usage = self.usage.lock().await;
+        let version = wb.version;
         for write in wb.writes {
-            match write {
+            let (id, old) = match write {
                 Write::Put(id, value) => {
-                    objects.insert(id, value);
+                    if let Some(list) = waiters.remove(&id) {
+                        list.iter().for_each(|x| x.notify_one());
+                    }
+                    usage.objects += 1;
+                    usage.bytes += Object::size(&id, &value);
+                    let old = objects.insert(id.clone(), Object { value, version });
+                    (id, old)
                 }
                 Write::Delete(id) => {
-                    objects.remove(&id);
+                    let old = objects.remove(&id);
+                    (id, old)
                 }
+            };
+            if let Some(old) = old {
+                usage.objects -= 1;
+                usage.bytes -= Object::size(&id, &old.value);
             }
         }
     }
 
+    /// Executes a collection request.
+    ///
+    /// If `errors` is given, the request is executed in best-effort mode: a
+    /// failed object is recorded in `errors` and its writes are discarded,
+    /// while other objects are executed as usual. Otherwise, the request
+    /// fails on the first error.
+    ///
+    /// The versions of objects read by select expressions are recorded in
+    /// `versions`.
     pub async fn execute(
         &self,
         wb: &mut WriteBatch,
         req: CollectionRequest,
+        mut errors: Option<&mut Vec<ObjectError>>,
+        versions: &mut Vec<ObjectVersion>,
     ) -> Result<CollectionResponse> {
         let mut res = CollectionResponse::default();
-        for expr in req.exprs {
-            let result = self.execute_expr(wb, expr).await?;
+        for (index, expr) in req.exprs.into_iter().enumerate() {
+            let mut result = ObjectResult::default();
+            if expr.select.is_none() && expr.mutate.is_none() {
+                res.results.push(result);
+                continue;
+            }
+            for (object, id) in expr.batch.iter().enumerate() {
+                wb.set_owner(Owner {
+                    expr: index,
+                    object,
+                });
+                let savepoint = wb.savepoint();
+                match (
+                    self.execute_object(wb, id.clone(), &expr).await,
+                    &mut errors,
+                ) {
+                    (Ok(value), _) => {
+                        if expr.select.is_some() {
+                            versions.push(ObjectVersion {
+                                expr: index as u32,
+                                object: object as u32,
+                                version: self.version(id).await,
+                                ..Default::default()
+                            });
+                        }
+                        result.values.push(value);
+                    }
+                    (Err(err), Some(errors)) => {
+                        wb.rollback(savepoint);
+                        result.values.push(().into());
+                        errors.push(object_error(index, object, err));
+                    }
+                    (Err(err), None) => return Err(err),
+                }
+            }
             res.results.push(result);
         }
         Ok(res)
     }
 
-    async fn execute_expr(&self, wb: &mut WriteBatch, expr: ObjectExpr) -> Result<ObjectResult> {
-        let mut result = ObjectResult::default();
-        if let Some(select) = expr.select {
-            for id in expr.batch {
-                let value = self.execute_select(id, select.clone()).await?;
-                result.values.push(value);
-            }
-        } else if let Some(mutate) = expr.mutate {
-            for id in expr.batch {
-                let value = self.execute_mutate(wb, id, mutate.clone()).await?;
-                result.values.push(value);
-            }
+    async fn execute_object(
+        &self,
+        wb: &mut WriteBatch,
+        id: Vec<u8>,
+        expr: &ObjectExpr,
+    ) -> Result<Value> {
+        if let Some(select) = &expr.select {
+            self.execute_select(id, select.clone()).await
+        } else if let Some(mutate) = &expr.mutate {
+            self.execute_mutate(wb, id, mutate.clone()).await
+        } else {
+            Ok(().into())
         }
-        Ok(result)
     }
 
     async fn execute_select(&self, id: Vec<u8>, expr: SelectExpr) -> Result<Value> {
@@ -205,20 +522,44 @@ impl Collection {
     }
 
     async fn execute_mutate(
+        &self,
+        wb: &mut WriteBatch,
+        id: Vec<u8>,
+        mut expr: MutateExpr,
+    ) -> Result<Value> {
+        let mut args = Args::new(std::mem::take(&mut expr.args));
+        let value = self.mutate(wb, id, expr, &mut args).await?;
+        // Only set and delete take an expected version, so extra arguments,
+        // such as a version given to other functions, are rejected instead of
+        // being ignored.
+        args.finish()?;
+        Ok(value)
+    }
+
+    async fn mutate(
         &self,
         wb: &mut WriteBatch,
         id: Vec<u8>,
         expr: MutateExpr,
+        args: &mut Args,
     ) -> Result<Value> {
         let func = MutateFunction::from_i32(expr.func).unwrap_or_default();
-        let mut args = Args::new(expr.args);
         match func {
             MutateFunction::Set => {
                 let value: Value = args.take()?;
+                let expected: Option<i64> = args.take_opt()?;
+                self.check_version(wb, &id, expected).await?;
                 wb.put(id, value);
-                Ok(().into())
+                if expected.is_some() {
+                    // Returns the new version for conditional updates.
+                    Ok((wb.version as i64).into())
+                } else {
+                    Ok(().into())
+                }
             }
             MutateFunction::Delete => {
+                let expected: Option<i64> = args.take_opt()?;
+                self.check_version(wb, &id, expected).await?;
                 wb.delete(id);
                 Ok(().into())
             }
@@ -273,6 +614,12 @@ impl Collection {
             MutateFunction::Lpop => {
                 let ob: Value = self.get(&id).await?;
                 let count: i64 = args.take()?;
+                let timeout = blocking_timeout(args.take_opt()?)?;
+                if let Some(timeout) = timeout {
+                    if is_empty(&ob) && wb.block(id.clone(), timeout) {
+                        return Ok(().into());
+                    }
+                }
                 if let Some(value) = ob.value {
                     match value {
                         value::Value::BlobValue(mut v) => {
@@ -295,51 +642,49 @@ impl Collection {
             MutateFunction::Rpop => {
                 let ob: Value = self.get(&id).await?;
                 let count: i64 = args.take()?;
-                if let Some(value) = ob.value {
+                let timeout = blocking_timeout(args.take_opt()?)?;
+                let destination: Option<Vec<u8>> = args.take_opt()?;
+                let empty = is_empty(&ob);
+                if let Some(timeout) = timeout {
+                    if empty && wb.block(id.clone(), timeout) {
+                        return Ok(().into());
+                    }
+                }
+                let (new_value, ret_value): (Value, Value) = if let Some(value) = ob.value {
                     match value {
                         value::Value::BlobValue(mut v) => {
                             let ret_value = list_rpop(&mut v, count)?;
-                            wb.put(id, v.into());
-                            Ok(ret_value.into())
+                            (v.into(), ret_value.into())
                         }
                         value::Value::ListValue(v) => {
                             let mut list = List(v);
                             let ret_value = list.rpop(count)?;
-                            wb.put(id, list.0.into());
-                            Ok(ret_value.into())
+                            (list.0.into(), ret_value.into())
                         }
-                        _ => Err(Error::invalid_argument("unsupported object")),
+                        _ => return Err(Error::invalid_argument("unsupported object")),
                     }
                 } else {
-                    Ok(().into())
+                    return Ok(().into());
+                };
+                match destination {
+                    Some(destination) if !empty => {
+                        // Moves the popped elements to the head of the destination atomically.
+                        let dst_ob = if destination == id {
+                            new_value
+                        } else {
+                            wb.put(id, new_value);
+                            self.get(&destination).await?
+                        };
+                        wb.put(destination, lpush_value(dst_ob, ret_value.clone())?);
+                    }
+                    _ => wb.put(id, new_value),
                 }
+                Ok(ret_value)
             }
             MutateFunction::Lpush => {
                 let ob: Value = self.get(&id).await?;
-                if let Some(value) = ob.value {
-                    match value {
-                        value::Value::BlobValue(v) => {
-                            let mut operand: Vec<u8> = args.take()?;
-                            operand.extend(v);
-                            wb.put(id, operand.into());
-                        }
-                        value::Value::ListValue(v) => {
-                            let operand: ListValue = args.take()?;
-                            let mut list = List(v);
-                            list.lpush(operand)?;
-                            wb.put(id, list.0.into());
-                        }
-                        _ => return Err(Error::invalid_argument("unsupported object")),
-                    }
-                } else {
-                    let value: value::Value = args.take()?;
-                    match value {
-                        v @ (value::Value::BlobValue(_) | value::Value::ListValue(_)) => {
-                            wb.put(id, v.into());
-                        }
-                        _ => return Err(Error::invalid_argument("unsupported object")),
-                    }
-                }
+                let operand: Value = args.take()?;
+                wb.put(id, lpush_value(ob, operand)?);
                 Ok(().into())
             }
             MutateFunction::Rpush => {
@@ -457,6 +802,66 @@ impl Collection {
     }
 }
 
+pub fn object_error(expr: usize, object: usize, err: Error) -> ObjectError {
+    let status = tonic::Status::from(err);
+    ObjectError {
+        expr: expr as u32,
+        object: object as u32,
+        code: status.code() as i32,
+        message: status.message().to_owned(),
+        ..Default::default()
+    }
+}
+
+fn lpush_value(ob: Value, operand: Value) -> Result<Value> {
+    let new_value = if let Some(value) = ob.value {
+        match value {
+            value::Value::BlobValue(v) => {
+                let mut operand: Vec<u8> = operand
+                    .try_into()
+                    .map_err(|_| Error::invalid_argument("argument type mismatch"))?;
+                operand.extend(v);
+                operand.into()
+            }
+            value::Value::ListValue(v) => {
+                let operand: ListValue = operand
+                    .try_into()
+                    .map_err(|_| Error::invalid_argument("argument type mismatch"))?;
+                let mut list = List(v);
+                list.lpush(operand)?;
+                list.0.into()
+            }
+            _ => return Err(Error::invalid_argument("unsupported object")),
+        }
+    } else {
+        match operand.value {
+            Some(v @ (value::Value::BlobValue(_) | value::Value::ListValue(_))) => v.into(),
+            _ => return Err(Error::invalid_argument("unsupported object")),
+        }
+    };
+    Ok(new_value)
+}
+
+fn is_empty(ob: &Value) -> bool {
+    match &ob.value {
+        Some(value::Value::BlobValue(v)) => v.is_empty(),
+        Some(value::Value::ListValue(v)) => v.encoded_len() == 0,
+        Some(_) => false,
+        None => true,
+    }
+}
+
+/// Converts a timeout in milliseconds, where zero means not to block.
+fn blocking_timeout(timeout: Option<i64>) -> Result<Option<Duration>> {
+    match timeout {
+        None | Some(0) => Ok(None),
+        Some(ms) => {
+            let ms = u64::try_from(ms).map_err(|_| Error::invalid_argument("negative timeout"))?;
+            Ok(Some(Duration::from_millis(ms)))
+        }
+    }
+}
+
 struct List(ListValue);
 
 impl List {
@@ -831,3 +1236,460 @@ fn adjust_index_value(i: i64, len: usize) -> Result<usize> {
     };
     usize::try_from(i).map_err(|_| Error::invalid_argument("convert i64 to usize"))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn mutate(batch: &[&[u8]], func: MutateFunction, args: Vec<Value>) -> ObjectExpr {
+        ObjectExpr {
+            batch: batch.iter().map(|id| id.to_vec()).collect(),
+            mutate: Some(MutateExpr {
+                func: func as i32,
+                args,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn select(batch: &[&[u8]]) -> ObjectExpr {
+        ObjectExpr {
+            batch: batch.iter().map(|id| id.to_vec()).collect(),
+            select: Some(SelectExpr {
+                func: SelectFunction::Get as i32,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn request(exprs: Vec<ObjectExpr>) -> CollectionRequest {
+        CollectionRequest {
+            name: "co".to_owned(),
+            exprs,
+        }
+    }
+
+    async fn write(co: &Collection, version: u64, exprs: Vec<ObjectExpr>) -> Result<()> {
+        let mut wb = WriteBatch::new(Instant::now(), version);
+        co.execute(&mut wb, request(exprs), None, &mut Vec::new())
+            .await?;
+        co.write(wb).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_best_effort() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let blob: Value = vec![1u8].into();
+        write(
+            &co,
+            1,
+            vec![mutate(&[b"blob"], MutateFunction::Set, vec![blob])],
+        )
+        .await?;
+
+        let exprs = vec![
+            mutate(&[b"a"], MutateFunction::Set, vec![1i64.into()]),
+            // Adding to a blob fails.
+            mutate(&[b"blob"], MutateFunction::Add, vec![1i64.into()]),
+            mutate(&[b"b", b"blob"], MutateFunction::Add, vec![2i64.into()]),
+        ];
+
+        // The request fails as a whole without best-effort.
+        let mut wb = WriteBatch::new(Instant::now(), 2);
+        assert!(co
+            .execute(&mut wb, request(exprs.clone()), None, &mut Vec::new())
+            .await
+            .is_err());
+
+        let mut wb = WriteBatch::new(Instant::now(), 2);
+        let mut errors = Vec::new();
+        let res = co
+            .execute(&mut wb, request(exprs), Some(&mut errors), &mut Vec::new())
+            .await?;
+        assert_eq!(res.results.len(), 3);
+        assert_eq!(res.results[2].values.len(), 2);
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].expr, errors[0].object), (1, 0));
+        assert_eq!((errors[1].expr, errors[1].object), (2, 1));
+        for err in &errors {
+            assert_eq!(err.code, tonic::Code::InvalidArgument as i32);
+        }
+        co.write(wb).await;
+
+        let a: i64 = co.get(b"a").await?;
+        assert_eq!(a, 1);
+        let b: i64 = co.get(b"b").await?;
+        assert_eq!(b, 2);
+        let blob: Vec<u8> = co.get(b"blob").await?;
+        assert_eq!(blob, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_version() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let wb = WriteBatch::new(Instant::now(), 5);
+        // Zero means that the object doesn't exist.
+        co.check_version(&wb, b"a", Some(0)).await?;
+        co.check_version(&wb, b"a", None).await?;
+        assert!(co.check_version(&wb, b"a", Some(1)).await.is_err());
+
+        let set = mutate(&[b"a"], MutateFunction::Set, vec![1i64.into()]);
+        write(&co, 3, vec![set]).await?;
+        co.check_version(&wb, b"a", Some(3)).await?;
+        co.check_version(&wb, b"a", None).await?;
+        for expected in [0, 2, 4, -1] {
+            let res = co.check_version(&wb, b"a", Some(expected)).await;
+            assert!(matches!(res, Err(Error::Aborted(_))));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_version_in_batch() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let set = |v: i64| mutate(&[b"a"], MutateFunction::Set, vec![v.into(), 0i64.into()]);
+        // Only the first conditional write to an object in a batch passes.
+        let res = write(&co, 1, vec![set(1), set(2)]).await;
+        assert!(matches!(res, Err(Error::Aborted(_))));
+        let delete = mutate(&[b"a"], MutateFunction::Delete, vec![0i64.into()]);
+        let res = write(&co, 1, vec![set(1), delete]).await;
+        assert!(matches!(res, Err(Error::Aborted(_))));
+        write(&co, 1, vec![set(1)]).await?;
+        assert_eq!(co.version(b"a").await, 1);
+
+        // Other functions don't take an expected version.
+        let add = mutate(&[b"a"], MutateFunction::Add, vec![1i64.into(), 1i64.into()]);
+        let res = write(&co, 2, vec![add]).await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_versions() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let set = |id: &[u8], v: i64| mutate(&[id], MutateFunction::Set, vec![v.into()]);
+        write(&co, 1, vec![set(b"a", 1), set(b"b", 1)]).await?;
+        assert_eq!(co.version(b"a").await, 1);
+        assert_eq!(co.version(b"b").await, 1);
+
+        // Every kind of write bumps the version of the object.
+        let add = mutate(&[b"a"], MutateFunction::Add, vec![1i64.into()]);
+        write(&co, 2, vec![add]).await?;
+        assert_eq!(co.version(b"a").await, 2);
+        assert_eq!(co.version(b"b").await, 1);
+        let delete = mutate(&[b"b"], MutateFunction::Delete, vec![]);
+        write(&co, 3, vec![delete]).await?;
+        assert_eq!(co.version(b"b").await, 0);
+
+        // A conditional set returns the new version.
+        let mut wb = WriteBatch::new(Instant::now(), 4);
+        let expr = mutate(&[b"a"], MutateFunction::Set, vec![3i64.into(), 2i64.into()]);
+        let mut res = co
+            .execute(&mut wb, request(vec![expr]), None, &mut Vec::new())
+            .await?;
+        co.write(wb).await;
+        let version: Option<i64> = res.results[0].values.pop().unwrap().try_into().ok();
+        assert_eq!(version, Some(4));
+        assert_eq!(co.version(b"a").await, 4);
+
+        // Selects return the versions of objects.
+        let mut wb = WriteBatch::new(Instant::now(), 5);
+        let mut versions = Vec::new();
+        let exprs = vec![select(&[b"a", b"b"]), set(b"c", 1)];
+        co.execute(&mut wb, request(exprs), None, &mut versions)
+            .await?;
+        let versions: Vec<_> = versions
+            .into_iter()
+            .map(|v| (v.expr, v.object, v.version))
+            .collect();
+        assert_eq!(versions, vec![(0, 0, 4), (0, 1, 0)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let set = |id: &[u8]| mutate(&[id], MutateFunction::Set, vec![1i64.into()]);
+        write(&co, 1, vec![set(b"b2"), set(b"a"), set(b"b1"), set(b"c")]).await?;
+        write(&co, 2, vec![set(b"b3")]).await?;
+
+        let ids = |res: &ScanResponse| -> Vec<String> {
+            res.objects
+                .iter()
+                .map(|ob| String::from_utf8(ob.id.clone()).unwrap())
+                .collect()
+        };
+        let mut req = ScanRequest::default();
+        let res = co.scan(&req).await;
+        assert_eq!(ids(&res), vec!["a", "b1", "b2", "b3", "c"]);
+        assert!(res.next.is_empty());
+
+        req.prefix = b"b".to_vec();
+        req.limit = 2;
+        let res = co.scan(&req).await;
+        assert_eq!(ids(&res), vec!["b1", "b2"]);
+        assert_eq!(res.objects[0].version, 1);
+        assert_eq!(res.objects[0].value, Some(Value::from(1i64)));
+        assert_eq!(res.next, b"b3");
+
+        req.start = res.next;
+        let res = co.scan(&req).await;
+        assert_eq!(ids(&res), vec!["b3"]);
+        assert_eq!(res.objects[0].version, 2);
+        assert!(res.next.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_quota() -> Result<()> {
+        let quota = Quota {
+            max_objects: Some(2),
+            max_value_size: Some(16),
+            ..Default::default()
+        };
+        let co = Collection::new(CollectionDesc::default(), quota);
+        let db_quota = Quota::default();
+        let mut wb1 = WriteBatch::new(Instant::now(), 1);
+        wb1.put(b"a".to_vec(), 1i64.into());
+        wb1.put(b"b".to_vec(), 1i64.into());
+        let usage = co.check_quota(&[&wb1], &db_quota).await?;
+        assert_eq!(usage.objects, 2);
+
+        // Batches of the same collection are checked together.
+        let mut wb2 = WriteBatch::new(Instant::now(), 1);
+        wb2.put(b"c".to_vec(), 1i64.into());
+        co.check_quota(&[&wb2], &db_quota).await?;
+        let res = co.check_quota(&[&wb1, &wb2], &db_quota).await;
+        assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+
+        // Overwrites and deletes in later batches are counted.
+        let mut wb2 = WriteBatch::new(Instant::now(), 1);
+        wb2.put(b"a".to_vec(), 2i64.into());
+        wb2.delete(b"b".to_vec());
+        wb2.put(b"c".to_vec(), 1i64.into());
+        let usage = co.check_quota(&[&wb1, &wb2], &db_quota).await?;
+        assert_eq!(usage.objects, 2);
+
+        let mut wb = WriteBatch::new(Instant::now(), 1);
+        wb.put(b"a".to_vec(), vec![0u8; 32].into());
+        let res = co.check_quota(&[&wb], &db_quota).await;
+        assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_quota_best_effort() -> Result<()> {
+        let quota = Quota {
+            max_objects: Some(2),
+            max_value_size: Some(16),
+            ..Default::default()
+        };
+        let co = Collection::new(CollectionDesc::default(), quota);
+        let db_quota = Quota {
+            max_objects: Some(3),
+            ..Default::default()
+        };
+        let owner = |expr, object| Owner { expr, object };
+        let mut wb1 = WriteBatch::new(Instant::now(), 1);
+        wb1.set_owner(owner(0, 0));
+        wb1.put(b"a".to_vec(), vec![0u8; 32].into());
+        wb1.set_owner(owner(0, 1));
+        wb1.put(b"b".to_vec(), 1i64.into());
+        let mut wb2 = WriteBatch::new(Instant::now(), 1);
+        wb2.set_owner(owner(0, 0));
+        wb2.put(b"c".to_vec(), 1i64.into());
+        wb2.set_owner(owner(1, 0));
+        wb2.put(b"d".to_vec(), 1i64.into());
+        // Deletes an object that fails, which doesn't free anything.
+        wb2.set_owner(owner(1, 1));
+        wb2.delete(b"a".to_vec());
+        wb2.put(b"e".to_vec(), 1i64.into());
+
+        // Other collections use one object of the database.
+        let mut db_usage = Usage {
+            objects: 1,
+            bytes: 0,
+        };
+        let failed = co
+            .check_quota_best_effort(&mut [&mut wb1, &mut wb2], &db_quota, &mut db_usage)
+            .await;
+        let failed: Vec<_> = failed
+            .into_iter()
+            .map(|(index, owner, err)| {
+                assert!(matches!(err, Error::ResourceExhausted(_)));
+                (index, owner)
+            })
+            .collect();
+        assert_eq!(
+            failed,
+            vec![(0, owner(0, 0)), (1, owner(1, 0)), (1, owner(1, 1))]
+        );
+        assert_eq!(wb1.owners, vec![owner(0, 1)]);
+        assert_eq!(wb2.owners, vec![owner(0, 0)]);
+        assert_eq!(db_usage.objects, 3);
+        Ok(())
+    }
+
+    fn list(values: &[i64]) -> Value {
+        ListValue {
+            i64_value: values.to_vec(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    async fn get_list(co: &Collection, id: &[u8]) -> Result<Vec<i64>> {
+        let list: ListValue = co.get(id).await?;
+        Ok(list.i64_value)
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let blpop = mutate(
+            &[b"q"],
+            MutateFunction::Lpop,
+            vec![1i64.into(), 1000i64.into()],
+        );
+        let brpop = mutate(
+            &[b"q"],
+            MutateFunction::Rpop,
+            vec![1i64.into(), 1000i64.into()],
+        );
+
+        // Pops on an empty object wait for it instead of writing anything.
+        for expr in [blpop.clone(), brpop.clone()] {
+            let mut wb = WriteBatch::new(Instant::now(), 1);
+            let res = co
+                .execute(&mut wb, request(vec![expr]), None, &mut Vec::new())
+                .await?;
+            assert_eq!(res.results[0].values, vec![Value::default()]);
+            assert!(wb.writes.is_empty());
+            assert_eq!(wb.waits, vec![b"q".to_vec()]);
+            assert!(wb.deadline.is_some());
+        }
+
+        let rpush = mutate(&[b"q"], MutateFunction::Rpush, vec![list(&[1, 2, 3])]);
+        write(&co, 1, vec![rpush]).await?;
+        write(&co, 2, vec![blpop]).await?;
+        write(&co, 3, vec![brpop]).await?;
+        assert_eq!(get_list(&co, b"q").await?, vec![2]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blocking_timeout() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        // The request started long enough ago that the timeout has elapsed.
+        let start = Instant::now() - Duration::from_secs(2);
+        let mut wb = WriteBatch::new(start, 1);
+        let expr = mutate(
+            &[b"q"],
+            MutateFunction::Lpop,
+            vec![1i64.into(), 1000i64.into()],
+        );
+        let res = co
+            .execute(&mut wb, request(vec![expr]), None, &mut Vec::new())
+            .await?;
+        assert_eq!(res.results[0].values, vec![Value::default()]);
+        assert!(wb.waits.is_empty());
+        assert!(wb.deadline.is_none());
+
+        let expr = mutate(
+            &[b"q"],
+            MutateFunction::Lpop,
+            vec![1i64.into(), (-1i64).into()],
+        );
+        let mut wb = WriteBatch::new(Instant::now(), 1);
+        assert!(co
+            .execute(&mut wb, request(vec![expr]), None, &mut Vec::new())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wakeup() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let notify = Arc::new(Notify::new());
+        co.wait(&[b"q".to_vec()], notify.clone()).await;
+        let notified = notify.notified();
+        tokio::pin!(notified);
+
+        // Writes to other objects don't wake up the waiter.
+        let set = mutate(&[b"other"], MutateFunction::Set, vec![list(&[1])]);
+        write(&co, 1, vec![set]).await?;
+        let timeout = Duration::from_millis(10);
+        assert!(tokio::time::timeout(timeout, &mut notified).await.is_err());
+
+        let lpush = mutate(&[b"q"], MutateFunction::Lpush, vec![list(&[1])]);
+        write(&co, 2, vec![lpush]).await?;
+        assert!(tokio::time::timeout(timeout, &mut notified).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpoplpush() -> Result<()> {
+        let co = Collection::new(CollectionDesc::default(), Quota::default());
+        let rpoplpush = |src: &[u8], dst: &[u8]| {
+            let args = vec![1i64.into(), 0i64.into(), dst.to_vec().into()];
+            mutate(&[src], MutateFunction::Rpop, args)
+        };
+        let set =
+            |id: &[u8], values: &[i64]| mutate(&[id], MutateFunction::Set, vec![list(values)]);
+        write(&co, 1, vec![set(b"src", &[1, 2, 3]), set(b"dst", &[9])]).await?;
+
+        // Moves the tail of the source to the head of the destination.
+        let mut wb = WriteBatch::new(Instant::now(), 2);
+        let res = co
+            .execute(
+                &mut wb,
+                request(vec![rpoplpush(b"src", b"dst")]),
+                None,
+                &mut Vec::new(),
+            )
+            .await?;
+        assert_eq!(res.results[0].values, vec![list(&[3])]);
+        // Nothing changes until the batch is written.
+        assert_eq!(get_list(&co, b"src").await?, vec![1, 2, 3]);
+        co.write(wb).await;
+        assert_eq!(get_list(&co, b"src").await?, vec![1, 2]);
+        assert_eq!(get_list(&co, b"dst").await?, vec![3, 9]);
+
+        // Rotates the list if the source is the destination.
+        write(&co, 3, vec![rpoplpush(b"src", b"src")]).await?;
+        assert_eq!(get_list(&co, b"src").await?, vec![2, 1]);
+
+        // A missing destination is created.
+        write(&co, 4, vec![rpoplpush(b"src", b"new")]).await?;
+        assert_eq!(get_list(&co, b"src").await?, vec![2]);
+        assert_eq!(get_list(&co, b"new").await?, vec![1]);
+
+        // A failed move leaves both objects unchanged.
+        let blob: Value = vec![1u8].into();
+        write(
+            &co,
+            5,
+            vec![mutate(&[b"blob"], MutateFunction::Set, vec![blob])],
+        )
+        .await?;
+        let mut wb = WriteBatch::new(Instant::now(), 6);
+        let res = co
+            .execute(
+                &mut wb,
+                request(vec![rpoplpush(b"src", b"blob")]),
+                None,
+                &mut Vec::new(),
+            )
+            .await;
+        assert!(res.is_err());
+        assert_eq!(get_list(&co, b"src").await?, vec![2]);
+        Ok(())
+    }
+}
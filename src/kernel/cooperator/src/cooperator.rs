@@ -13,8 +13,9 @@
 // limitations under the License.
 
 use engula_supervisor::Supervisor;
+use tokio::sync::mpsc;
 
-use crate::{apis::*, Result, Universe};
+use crate::{apis::*, Quotas, Result, Universe};
 
 #[derive(Clone)]
 pub struct Cooperator {
@@ -23,17 +24,55 @@ pub struct Cooperator {
 
 impl Cooperator {
     pub fn new(sv: Supervisor) -> Self {
-        let uv = Universe::new(sv);
+        Self::with_quotas(sv, Quotas::default())
+    }
+
+    /// Creates a cooperator that applies `quotas` to databases and
+    /// collections until they are given their own quotas.
+    pub fn with_quotas(sv: Supervisor, quotas: Quotas) -> Self {
+        let uv = Universe::new(sv, quotas);
         Self { uv }
     }
 
     pub async fn batch(&self, batch_req: BatchRequest) -> Result<BatchResponse> {
         let mut batch_res = BatchResponse::default();
-        for req in batch_req.databases {
+        for (index, req) in batch_req.databases.into_iter().enumerate() {
             let db = self.uv.database(&req.name).await?;
-            let res = db.execute(req).await?;
-            batch_res.databases.push(res);
+            let output = db.execute(req, batch_req.best_effort).await?;
+            batch_res.databases.push(output.response);
+            for mut err in output.errors {
+                err.database = index as u32;
+                batch_res.errors.push(err);
+            }
+            for mut version in output.versions {
+                version.database = index as u32;
+                batch_res.versions.push(version);
+            }
         }
         Ok(batch_res)
     }
+
+    pub async fn set_quota(&self, req: SetQuotaRequest) -> Result<SetQuotaResponse> {
+        let db = self.uv.database(&req.dbname).await?;
+        let quota = req.quota.unwrap_or_default().into();
+        db.set_quota(&req.name, quota).await?;
+        Ok(SetQuotaResponse {})
+    }
+
+    pub async fn scan(&self, req: ScanRequest) -> Result<ScanResponse> {
+        let db = self.uv.database(&req.dbname).await?;
+        db.scan(req).await
+    }
+
+    /// Closes all databases so that ongoing watches are finished.
+    ///
+    /// Objects are kept in memory, so there is nothing to flush.
+    pub async fn close(&self) {
+        self.uv.close().await;
+    }
+
+    pub async fn watch(&self, req: WatchRequest) -> Result<mpsc::Receiver<Result<WatchResponse>>> {
+        let db = self.uv.database(&req.dbname).await?;
+        db.watch(req).await
+    }
 }
@@ -12,13 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use engula_apis::v1::*;
 use engula_supervisor::Supervisor;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, Notify};
 
-use crate::{Collection, Error, Result, WriteBatch};
+use crate::{
+    apis::{ObjectError, ObjectVersion, ScanRequest, ScanResponse, WatchRequest, WatchResponse},
+    collection::object_error,
+    watch::{ChangeFeed, Watcher},
+    Collection, Error, Quota, Quotas, RateLimiter, Result, Usage, WriteBatch,
+};
+
+const WATCH_CHANNEL_SIZE: usize = 64;
+
+/// The output of a database request.
+#[derive(Default)]
+pub struct Output {
+    pub response: DatabaseResponse,
+    /// The errors of failed objects in best-effort mode.
+    pub errors: Vec<ObjectError>,
+    /// The versions of objects read by select expressions.
+    pub versions: Vec<ObjectVersion>,
+}
 
 #[derive(Clone)]
 pub struct Database {
@@ -26,28 +43,121 @@ pub struct Database {
 }
 
 impl Database {
-    pub fn new(desc: DatabaseDesc, sv: Supervisor) -> Self {
-        let inner = DatabaseInner::new(desc, sv);
+    /// Creates a database with the default quotas of the database and its
+    /// collections.
+    pub fn new(desc: DatabaseDesc, sv: Supervisor, quotas: Quotas) -> Self {
+        let inner = DatabaseInner::new(desc, sv, quotas);
         Self {
             inner: Arc::new(Mutex::new(inner)),
         }
     }
 
-    pub async fn execute(&self, req: DatabaseRequest) -> Result<DatabaseResponse> {
-        let mut inner = self.inner.lock().await;
-        let mut res = DatabaseResponse::default();
-        let mut cx = DatabaseContext::default();
-        for coreq in req.requests {
-            let co = inner.collection(&coreq.name).await?;
-            let mut wb = WriteBatch::default();
-            let cores = co.execute(&mut wb, coreq).await?;
-            res.responses.push(cores);
-            cx.collections.push((co, wb));
+    /// Executes a database request.
+    ///
+    /// In best-effort mode, failed objects are returned as errors instead of
+    /// failing the whole request.
+    pub async fn execute(&self, req: DatabaseRequest, best_effort: bool) -> Result<Output> {
+        let start = Instant::now();
+        self.inner.lock().await.admit(&req).await?;
+        loop {
+            let notify = Arc::new(Notify::new());
+            let deadline = {
+                let mut inner = self.inner.lock().await;
+                let mut output = Output::default();
+                let mut cx = DatabaseContext::default();
+                let version = inner.feed.next_version();
+                for (index, coreq) in req.requests.iter().cloned().enumerate() {
+                    let name = coreq.name.clone();
+                    let co = inner.collection(&name).await?;
+                    let mut wb = WriteBatch::new(start, version);
+                    let mut coerrors = Vec::new();
+                    let mut coversions = Vec::new();
+                    let cores = co
+                        .execute(
+                            &mut wb,
+                            coreq,
+                            best_effort.then(|| &mut coerrors),
+                            &mut coversions,
+                        )
+                        .await?;
+                    for mut err in coerrors {
+                        err.collection = index as u32;
+                        output.errors.push(err);
+                    }
+                    for mut version in coversions {
+                        version.collection = index as u32;
+                        output.versions.push(version);
+                    }
+                    output.response.responses.push(cores);
+                    cx.collections.push((name, co, wb));
+                }
+                if let Some(deadline) = cx.deadline() {
+                    // Some blocking operations are waiting, so nothing is
+                    // written and the request is executed again on wakeup.
+                    for (_, co, wb) in &cx.collections {
+                        co.wait(&wb.waits, notify.clone()).await;
+                    }
+                    deadline
+                } else {
+                    if best_effort {
+                        inner.check_quota_best_effort(&mut cx, &mut output).await;
+                    } else {
+                        inner.check_quota(&cx).await?;
+                    }
+                    let mut changes = Vec::new();
+                    for (name, co, wb) in cx.collections {
+                        if !wb.writes.is_empty() {
+                            changes.push((name, wb.writes.clone()));
+                        }
+                        co.write(wb).await;
+                    }
+                    if !changes.is_empty() {
+                        inner.feed.publish(changes);
+                    }
+                    return Ok(output);
+                }
+            };
+            let _ = tokio::time::timeout_at(deadline.into(), notify.notified()).await;
         }
-        for (co, wb) in cx.collections {
-            co.write(wb).await;
+    }
+
+    /// Sets the quota of the database, or of a collection if `name` is not
+    /// empty.
+    pub async fn set_quota(&self, name: &str, quota: Quota) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        if name.is_empty() {
+            inner.limiter = RateLimiter::new(quota.max_requests_per_second);
+            inner.quota = quota;
+        } else {
+            let co = inner.collection(name).await?;
+            inner.collections.insert(co.id(), co.with_quota(quota));
         }
-        Ok(res)
+        Ok(())
+    }
+
+    pub async fn scan(&self, req: ScanRequest) -> Result<ScanResponse> {
+        let mut inner = self.inner.lock().await;
+        let co = inner.collection(&req.name).await?;
+        Ok(co.scan(&req).await)
+    }
+
+    /// Closes the database, ongoing watches are finished.
+    pub async fn close(&self) {
+        self.inner.lock().await.feed.close();
+    }
+
+    /// Watches the changes committed to a collection.
+    pub async fn watch(&self, req: WatchRequest) -> Result<mpsc::Receiver<Result<WatchResponse>>> {
+        let (history, receiver) = {
+            let mut inner = self.inner.lock().await;
+            // Makes sure that the collection exists.
+            inner.collection(&req.name).await?;
+            inner.feed.subscribe(req.start_version)?
+        };
+        let (tx, rx) = mpsc::channel(WATCH_CHANNEL_SIZE);
+        let watcher = Watcher::new(req);
+        tokio::spawn(watcher.run(history, receiver, tx));
+        Ok(rx)
     }
 }
 
@@ -55,15 +165,112 @@ struct DatabaseInner {
     sv: Supervisor,
     desc: DatabaseDesc,
     collections: HashMap<u64, Collection>,
+    feed: ChangeFeed,
+    quota: Quota,
+    limiter: RateLimiter,
+    // The quota of collections that are not given their own.
+    collection_quota: Quota,
 }
 
 impl DatabaseInner {
-    fn new(desc: DatabaseDesc, sv: Supervisor) -> Self {
+    fn new(desc: DatabaseDesc, sv: Supervisor, quotas: Quotas) -> Self {
+        let limiter = RateLimiter::new(quotas.database.max_requests_per_second);
         Self {
             sv,
             desc,
             collections: HashMap::new(),
+            feed: ChangeFeed::default(),
+            quota: quotas.database,
+            limiter,
+            collection_quota: quotas.collection,
+        }
+    }
+
+    /// Admits a request if the rate limits of the database and the
+    /// collections it accesses allow.
+    ///
+    /// Tokens are taken only if all limits allow, so a rejected request
+    /// doesn't use up the rate of any limit.
+    async fn admit(&mut self, req: &DatabaseRequest) -> Result<()> {
+        let mut collections = HashMap::new();
+        for coreq in &req.requests {
+            let co = self.collection(&coreq.name).await?;
+            collections.insert(co.id(), co);
+        }
+        self.limiter.check()?;
+        for co in collections.values() {
+            co.check_rate().await?;
+        }
+        // The database is locked, so no other request takes tokens between
+        // the checks and here.
+        self.limiter.take();
+        for co in collections.values() {
+            co.take_rate().await;
+        }
+        Ok(())
+    }
+
+    /// Checks the batches of a request against the quotas.
+    async fn check_quota(&self, cx: &DatabaseContext) -> Result<()> {
+        let mut before = Usage::default();
+        for co in self.collections.values() {
+            before.add(co.usage().await);
+        }
+        // A collection may appear in multiple requests, whose batches must
+        // be checked together.
+        let mut batches: HashMap<u64, (&Collection, Vec<&WriteBatch>)> = HashMap::new();
+        for (_, co, wb) in &cx.collections {
+            batches
+                .entry(co.id())
+                .or_insert_with(|| (co, Vec::new()))
+                .1
+                .push(wb);
+        }
+        let mut after = before;
+        for (co, wbs) in batches.values() {
+            after.sub(co.usage().await);
+            after.add(co.check_quota(wbs, &self.quota).await?);
+        }
+        self.quota.check_usage(before, after)
+    }
+
+    /// Checks the batches of a request against the quotas object by object,
+    /// in best-effort mode.
+    ///
+    /// Objects that exceed the quotas are recorded as errors in `output` and
+    /// their writes are discarded. Collections are checked in the order they
+    /// first appear in the request.
+    async fn check_quota_best_effort(&self, cx: &mut DatabaseContext, output: &mut Output) {
+        let mut usage = Usage::default();
+        for co in self.collections.values() {
+            usage.add(co.usage().await);
         }
+        let mut batches: Vec<(Collection, Vec<usize>, Vec<&mut WriteBatch>)> = Vec::new();
+        for (index, (_, co, wb)) in cx.collections.iter_mut().enumerate() {
+            match batches.iter_mut().find(|(x, ..)| x.id() == co.id()) {
+                Some((_, indexes, wbs)) => {
+                    indexes.push(index);
+                    wbs.push(wb);
+                }
+                None => batches.push((co.clone(), vec![index], vec![wb])),
+            }
+        }
+        for (co, indexes, mut wbs) in batches {
+            let failed = co
+                .check_quota_best_effort(&mut wbs, &self.quota, &mut usage)
+                .await;
+            for (i, owner, err) in failed {
+                let index = indexes[i];
+                let mut err = object_error(owner.expr, owner.object, err);
+                err.collection = index as u32;
+                output.errors.push(err);
+                output.response.responses[index].results[owner.expr].values[owner.object] =
+                    ().into();
+            }
+        }
+        output
+            .errors
+            .sort_by_key(|err| (err.collection, err.expr, err.object));
     }
 
     async fn collection(&mut self, name: &str) -> Result<Collection> {
@@ -78,12 +285,164 @@ impl DatabaseInner {
         let co = self
             .collections
             .entry(desc.id)
-            .or_insert_with(|| Collection::new(desc));
+            .or_insert_with(|| Collection::new(desc, self.collection_quota.clone()));
         Ok(co.clone())
     }
 }
 
 #[derive(Default)]
 struct DatabaseContext {
-    collections: Vec<(Collection, WriteBatch)>,
+    collections: Vec<(String, Collection, WriteBatch)>,
+}
+
+impl DatabaseContext {
+    fn deadline(&self) -> Option<Instant> {
+        self.collections
+            .iter()
+            .filter_map(|(_, _, wb)| wb.deadline)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup(quotas: Quotas) -> Result<Database> {
+        let sv = Supervisor::default();
+        let req = CreateDatabaseRequest {
+            name: "db".to_owned(),
+            ..Default::default()
+        };
+        let desc = sv.create_database(req).await?.desc.unwrap();
+        for name in ["a", "b"] {
+            let req = CreateCollectionRequest {
+                dbname: "db".to_owned(),
+                name: name.to_owned(),
+                ..Default::default()
+            };
+            sv.create_collection(req).await?;
+        }
+        Ok(Database::new(desc, sv, quotas))
+    }
+
+    /// Returns a request that sets objects in collections.
+    fn set(objects: &[(&str, &[u8])]) -> DatabaseRequest {
+        let requests = objects
+            .iter()
+            .map(|(name, id)| CollectionRequest {
+                name: name.to_string(),
+                exprs: vec![ObjectExpr {
+                    batch: vec![id.to_vec()],
+                    mutate: Some(MutateExpr {
+                        func: MutateFunction::Set as i32,
+                        args: vec![1i64.into()],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+            })
+            .collect();
+        DatabaseRequest {
+            name: "db".to_owned(),
+            requests,
+        }
+    }
+
+    fn is_exhausted(res: Result<Output>) -> bool {
+        matches!(res, Err(Error::ResourceExhausted(_)))
+    }
+
+    #[tokio::test]
+    async fn test_admit() -> Result<()> {
+        let quotas = Quotas {
+            database: Quota {
+                max_requests_per_second: Some(3),
+                ..Default::default()
+            },
+            collection: Quota {
+                max_requests_per_second: Some(1),
+                ..Default::default()
+            },
+        };
+        let db = setup(quotas).await?;
+        db.execute(set(&[("a", b"x")]), false).await?;
+        // Rejected by collection a, which takes no token from the database
+        // or collection b.
+        assert!(is_exhausted(
+            db.execute(set(&[("b", b"x"), ("a", b"x")]), false).await
+        ));
+        db.execute(set(&[("b", b"x")]), false).await?;
+        assert!(is_exhausted(db.execute(set(&[("b", b"x")]), false).await));
+
+        // A collection that appears in a request more than once takes one token.
+        db.set_quota("b", Quota::default()).await?;
+        db.execute(set(&[("b", b"x"), ("b", b"y")]), false).await?;
+        // Rejected by the database.
+        assert!(is_exhausted(db.execute(set(&[("b", b"x")]), false).await));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_quota() -> Result<()> {
+        let db = setup(Quotas::default()).await?;
+        let quota = Quota {
+            max_objects: Some(1),
+            ..Default::default()
+        };
+        db.set_quota("a", quota.clone()).await?;
+        db.execute(set(&[("a", b"x")]), false).await?;
+        assert!(is_exhausted(db.execute(set(&[("a", b"y")]), false).await));
+        // Other collections keep the default quota.
+        db.execute(set(&[("b", b"x"), ("b", b"y")]), false).await?;
+
+        db.set_quota("", quota).await?;
+        assert!(is_exhausted(db.execute(set(&[("b", b"z")]), false).await));
+        db.set_quota("", Quota::default()).await?;
+        db.execute(set(&[("b", b"z")]), false).await?;
+
+        let res = db.set_quota("c", Quota::default()).await;
+        assert!(matches!(res, Err(Error::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quota_best_effort() -> Result<()> {
+        let quotas = Quotas {
+            database: Quota {
+                max_objects: Some(3),
+                ..Default::default()
+            },
+            collection: Quota {
+                max_objects: Some(2),
+                ..Default::default()
+            },
+        };
+        let db = setup(quotas).await?;
+        let req = set(&[
+            ("a", b"x"),
+            ("b", b"x"),
+            ("a", b"y"),
+            ("a", b"z"),
+            ("b", b"y"),
+        ]);
+        let output = db.execute(req, true).await?;
+        // a/z exceeds the quota of collection a, and b/y exceeds the quota of
+        // the database, while other objects are written.
+        let failed: Vec<_> = output
+            .errors
+            .iter()
+            .map(|err| {
+                assert_eq!(err.code, tonic::Code::ResourceExhausted as i32);
+                (err.collection, err.expr, err.object)
+            })
+            .collect();
+        assert_eq!(failed, vec![(3, 0, 0), (4, 0, 0)]);
+        assert_eq!(output.response.responses[3].results[0].values[0], ().into());
+
+        let output = db.execute(set(&[("b", b"x")]), true).await?;
+        assert!(output.errors.is_empty());
+        assert!(is_exhausted(db.execute(set(&[("b", b"y")]), false).await));
+        Ok(())
+    }
 }
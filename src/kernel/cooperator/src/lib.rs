@@ -17,8 +17,10 @@ mod args;
 mod collection;
 mod cooperator;
 mod database;
+mod quota;
 mod server;
 mod universe;
+mod watch;
 mod write_batch;
 
 use engula_common::{Error, Result};
@@ -27,7 +29,12 @@ use self::{
     args::Args,
     collection::Collection,
     database::Database,
+    quota::{RateLimiter, Usage},
     universe::Universe,
     write_batch::{Write, WriteBatch},
 };
-pub use self::{cooperator::Cooperator, server::Server};
+pub use self::{
+    cooperator::Cooperator,
+    quota::{Quota, Quotas},
+    server::Server,
+};
@@ -0,0 +1,284 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Instant;
+
+use crate::{apis, Error, Result};
+
+/// Limits on the resources of a database or a collection.
+///
+/// A `None` limit means unlimited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quota {
+    /// The maximum number of objects.
+    pub max_objects: Option<u64>,
+    /// The maximum total size of objects in bytes.
+    pub max_bytes: Option<u64>,
+    /// The maximum size of a single value in bytes.
+    pub max_value_size: Option<u64>,
+    /// The maximum number of requests per second.
+    pub max_requests_per_second: Option<u64>,
+}
+
+/// The default quotas of databases and collections, which apply until a
+/// database or a collection is given its own quota.
+#[derive(Clone, Debug, Default)]
+pub struct Quotas {
+    pub database: Quota,
+    pub collection: Quota,
+}
+
+fn limit(value: u64) -> Option<u64> {
+    if value > 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+impl From<apis::Quota> for Quota {
+    fn from(quota: apis::Quota) -> Self {
+        Self {
+            max_objects: limit(quota.max_objects),
+            max_bytes: limit(quota.max_bytes),
+            max_value_size: limit(quota.max_value_size),
+            max_requests_per_second: limit(quota.max_requests_per_second),
+        }
+    }
+}
+
+impl From<Quota> for apis::Quota {
+    fn from(quota: Quota) -> Self {
+        Self {
+            max_objects: quota.max_objects.unwrap_or_default(),
+            max_bytes: quota.max_bytes.unwrap_or_default(),
+            max_value_size: quota.max_value_size.unwrap_or_default(),
+            max_requests_per_second: quota.max_requests_per_second.unwrap_or_default(),
+        }
+    }
+}
+
+/// The resources used by a database or a collection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: Usage) {
+        self.objects += other.objects;
+        self.bytes += other.bytes;
+    }
+
+    pub fn sub(&mut self, other: Usage) {
+        self.objects = self.objects.saturating_sub(other.objects);
+        self.bytes = self.bytes.saturating_sub(other.bytes);
+    }
+}
+
+impl Quota {
+    pub fn check_value_size(&self, size: usize) -> Result<()> {
+        match self.max_value_size {
+            Some(max) if size as u64 > max => Err(Error::resource_exhausted(format!(
+                "value size {} exceeds the limit {}",
+                size, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the usage after a write against the usage before it.
+    ///
+    /// Writes that don't increase the usage are always allowed, so that
+    /// objects can be deleted after the quota is lowered.
+    pub fn check_usage(&self, before: Usage, after: Usage) -> Result<()> {
+        if let Some(max) = self.max_objects {
+            if after.objects > before.objects && after.objects > max {
+                return Err(Error::resource_exhausted(format!(
+                    "number of objects exceeds the limit {}",
+                    max
+                )));
+            }
+        }
+        if let Some(max) = self.max_bytes {
+            if after.bytes > before.bytes && after.bytes > max {
+                return Err(Error::resource_exhausted(format!(
+                    "size of objects exceeds the limit {}",
+                    max
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A token bucket that limits the rate of requests.
+pub struct RateLimiter {
+    rate: Option<u64>,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate,
+            tokens: rate.unwrap_or_default() as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Returns an error if the rate limit is reached, without taking a token.
+    ///
+    /// A request limited by multiple limiters checks all of them before it
+    /// takes any token, so that a rejected request takes no token.
+    pub fn check(&mut self) -> Result<()> {
+        let rate = match self.rate {
+            Some(rate) => rate as f64,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return Err(Error::resource_exhausted("too many requests"));
+        }
+        Ok(())
+    }
+
+    /// Takes a token, which must be checked before.
+    pub fn take(&mut self) {
+        if self.rate.is_some() {
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn usage(objects: u64, bytes: u64) -> Usage {
+        Usage { objects, bytes }
+    }
+
+    #[test]
+    fn test_check_value_size() {
+        let quota = Quota::default();
+        assert!(quota.check_value_size(usize::MAX).is_ok());
+        let quota = Quota {
+            max_value_size: Some(8),
+            ..Default::default()
+        };
+        assert!(quota.check_value_size(8).is_ok());
+        let res = quota.check_value_size(9);
+        assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+    }
+
+    #[test]
+    fn test_check_usage() {
+        let quota = Quota::default();
+        assert!(quota.check_usage(usage(0, 0), usage(100, 100)).is_ok());
+
+        let quota = Quota {
+            max_objects: Some(2),
+            max_bytes: Some(100),
+            ..Default::default()
+        };
+        let cases = [
+            // Up to the limits.
+            (usage(1, 50), usage(2, 100), true),
+            (usage(2, 100), usage(3, 100), false),
+            (usage(2, 100), usage(2, 101), false),
+            // Writes that don't increase the usage are allowed, even if the
+            // usage is over the limits.
+            (usage(3, 200), usage(3, 200), true),
+            (usage(5, 200), usage(4, 150), true),
+            (usage(5, 50), usage(4, 50), true),
+            (usage(5, 50), usage(6, 40), false),
+        ];
+        for (before, after, ok) in cases {
+            let res = quota.check_usage(before, after);
+            assert_eq!(res.is_ok(), ok, "{:?} -> {:?}", before, after);
+            if !ok {
+                assert!(matches!(res, Err(Error::ResourceExhausted(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_quota_proto() {
+        let quota = Quota {
+            max_objects: Some(10),
+            max_value_size: Some(64),
+            ..Default::default()
+        };
+        let proto = apis::Quota::from(quota.clone());
+        assert_eq!(proto.max_objects, 10);
+        assert_eq!(proto.max_bytes, 0);
+        // Zero means unlimited.
+        assert_eq!(Quota::from(proto), quota);
+        assert_eq!(Quota::from(apis::Quota::default()), Quota::default());
+    }
+
+    fn acquire(limiter: &mut RateLimiter) -> Result<()> {
+        limiter.check()?;
+        limiter.take();
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(None);
+        for _ in 0..100 {
+            acquire(&mut limiter).unwrap();
+        }
+
+        // Checks don't take tokens.
+        let mut limiter = RateLimiter::new(Some(1));
+        for _ in 0..4 {
+            limiter.check().unwrap();
+        }
+        acquire(&mut limiter).unwrap();
+        assert!(limiter.check().is_err());
+
+        // Starts with a full bucket.
+        let mut limiter = RateLimiter::new(Some(4));
+        for _ in 0..4 {
+            acquire(&mut limiter).unwrap();
+        }
+        assert!(matches!(
+            acquire(&mut limiter),
+            Err(Error::ResourceExhausted(_))
+        ));
+
+        // Refills tokens at the rate.
+        limiter.last_refill -= Duration::from_millis(500);
+        for _ in 0..2 {
+            acquire(&mut limiter).unwrap();
+        }
+        assert!(acquire(&mut limiter).is_err());
+
+        // Bursts are limited to the rate.
+        limiter.last_refill -= Duration::from_secs(10);
+        for _ in 0..4 {
+            acquire(&mut limiter).unwrap();
+        }
+        assert!(acquire(&mut limiter).is_err());
+    }
+}
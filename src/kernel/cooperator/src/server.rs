@@ -12,6 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 use crate::{apis::*, Cooperator};
@@ -33,9 +37,36 @@ impl Server {
 
 #[tonic::async_trait]
 impl cooperator_server::Cooperator for Server {
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchResponse, Status>> + Send>>;
+
     async fn batch(&self, req: Request<BatchRequest>) -> Result<Response<BatchResponse>, Status> {
         let req = req.into_inner();
         let res = self.cooperator.batch(req).await?;
         Ok(Response::new(res))
     }
+
+    async fn scan(&self, req: Request<ScanRequest>) -> Result<Response<ScanResponse>, Status> {
+        let req = req.into_inner();
+        let res = self.cooperator.scan(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn set_quota(
+        &self,
+        req: Request<SetQuotaRequest>,
+    ) -> Result<Response<SetQuotaResponse>, Status> {
+        let req = req.into_inner();
+        let res = self.cooperator.set_quota(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn watch(
+        &self,
+        req: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let req = req.into_inner();
+        let rx = self.cooperator.watch(req).await?;
+        let stream = ReceiverStream::new(rx).map(|res| res.map_err(Status::from));
+        Ok(Response::new(Box::pin(stream)))
+    }
 }
@@ -18,7 +18,7 @@ use engula_apis::v1::*;
 use engula_supervisor::Supervisor;
 use tokio::sync::Mutex;
 
-use crate::{Database, Error, Result};
+use crate::{Database, Error, Quotas, Result};
 
 #[derive(Clone)]
 pub struct Universe {
@@ -26,8 +26,8 @@ pub struct Universe {
 }
 
 impl Universe {
-    pub fn new(sv: Supervisor) -> Self {
-        let inner = UniverseInner::new(sv);
+    pub fn new(sv: Supervisor, quotas: Quotas) -> Self {
+        let inner = UniverseInner::new(sv, quotas);
         Self {
             inner: Arc::new(Mutex::new(inner)),
         }
@@ -37,17 +37,26 @@ impl Universe {
         let mut inner = self.inner.lock().await;
         inner.database(name).await
     }
+
+    pub async fn close(&self) {
+        let inner = self.inner.lock().await;
+        for db in inner.databases.values() {
+            db.close().await;
+        }
+    }
 }
 
 struct UniverseInner {
     sv: Supervisor,
+    quotas: Quotas,
     databases: HashMap<u64, Database>,
 }
 
 impl UniverseInner {
-    fn new(sv: Supervisor) -> Self {
+    fn new(sv: Supervisor, quotas: Quotas) -> Self {
         Self {
             sv,
+            quotas,
             databases: HashMap::new(),
         }
     }
@@ -63,7 +72,7 @@ impl UniverseInner {
         let db = self
             .databases
             .entry(desc.id)
-            .or_insert_with(|| Database::new(desc, self.sv.clone()));
+            .or_insert_with(|| Database::new(desc, self.sv.clone(), self.quotas.clone()));
         Ok(db.clone())
     }
 }
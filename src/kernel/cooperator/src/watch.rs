@@ -0,0 +1,353 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{apis::*, Error, Result, Write};
+
+/// The number of recent changes retained for watchers to resume from.
+const HISTORY_CAPACITY: usize = 1024;
+
+/// The changes committed by one database request.
+pub struct Change {
+    pub version: u64,
+    pub collections: Vec<(String, Vec<Write>)>,
+}
+
+/// A feed of the changes committed to a database.
+pub struct ChangeFeed {
+    next_version: u64,
+    history: VecDeque<Arc<Change>>,
+    /// None if the feed is closed.
+    sender: Option<broadcast::Sender<Arc<Change>>>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY_CAPACITY);
+        Self {
+            next_version: 1,
+            history: VecDeque::new(),
+            sender: Some(sender),
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// Returns the version that the next changes will be published at.
+    pub fn next_version(&self) -> u64 {
+        self.next_version
+    }
+
+    /// Publishes the changes of a request and returns their version.
+    pub fn publish(&mut self, collections: Vec<(String, Vec<Write>)>) -> u64 {
+        let version = self.next_version;
+        self.next_version += 1;
+        let change = Arc::new(Change {
+            version,
+            collections,
+        });
+        if self.history.len() == HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+        self.history.push_back(change.clone());
+        if let Some(sender) = &self.sender {
+            // It is fine if there is no receiver.
+            let _ = sender.send(change);
+        }
+        version
+    }
+
+    /// Closes the feed so that all watchers finish.
+    pub fn close(&mut self) {
+        self.sender = None;
+    }
+
+    /// Returns the retained changes since `start_version` and a receiver for
+    /// the following ones.
+    ///
+    /// A zero `start_version` returns future changes only. Changes that are
+    /// no longer retained are reported as data loss, like a watcher that lags
+    /// behind, and a `start_version` after the next version is invalid.
+    pub fn subscribe(
+        &self,
+        start_version: u64,
+    ) -> Result<(Vec<Arc<Change>>, broadcast::Receiver<Arc<Change>>)> {
+        let receiver = self
+            .sender
+            .as_ref()
+            .ok_or_else(|| Error::aborted("the change feed is closed"))?
+            .subscribe();
+        if start_version > self.next_version {
+            return Err(Error::invalid_argument(format!(
+                "version {} is in the future, the next version is {}",
+                start_version, self.next_version
+            )));
+        }
+        if start_version == 0 || start_version == self.next_version {
+            return Ok((Vec::new(), receiver));
+        }
+        let oldest = self
+            .history
+            .front()
+            .map(|c| c.version)
+            .unwrap_or(self.next_version);
+        if start_version < oldest {
+            return Err(Error::dataloss(format!(
+                "version {} has been compacted, the oldest version is {}",
+                start_version, oldest
+            )));
+        }
+        let history = self
+            .history
+            .iter()
+            .filter(|c| c.version >= start_version)
+            .cloned()
+            .collect();
+        Ok((history, receiver))
+    }
+}
+
+/// Selects the changes that a watch request is interested in.
+pub struct Watcher {
+    req: WatchRequest,
+}
+
+impl Watcher {
+    pub fn new(req: WatchRequest) -> Self {
+        Self { req }
+    }
+
+    /// Forwards the changes to `tx` until the receiver is dropped or the
+    /// watcher falls too far behind.
+    pub async fn run(
+        self,
+        history: Vec<Arc<Change>>,
+        mut receiver: broadcast::Receiver<Arc<Change>>,
+        tx: mpsc::Sender<Result<WatchResponse>>,
+    ) {
+        let mut last_version = 0;
+        for change in history {
+            last_version = change.version;
+            if !self.forward(&change, &tx).await {
+                return;
+            }
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(change) => {
+                    // Skips changes that have been sent from the history.
+                    if change.version <= last_version {
+                        continue;
+                    }
+                    if !self.forward(&change, &tx).await {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    let err = Error::dataloss(format!("watcher lagged behind {} changes", n));
+                    let _ = tx.send(Err(err)).await;
+                    return;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    async fn forward(&self, change: &Change, tx: &mpsc::Sender<Result<WatchResponse>>) -> bool {
+        let events: Vec<_> = change
+            .collections
+            .iter()
+            .filter(|(name, _)| name == &self.req.name)
+            .flat_map(|(_, writes)| writes.iter())
+            .filter_map(|w| self.event(w))
+            .collect();
+        if events.is_empty() {
+            return true;
+        }
+        let res = WatchResponse {
+            version: change.version,
+            events,
+        };
+        tx.send(Ok(res)).await.is_ok()
+    }
+
+    fn event(&self, write: &Write) -> Option<WatchEvent> {
+        let (tp, id, value) = match write {
+            Write::Put(id, value) => (watch_event::Type::Put, id, Some(value.clone())),
+            Write::Delete(id) => (watch_event::Type::Delete, id, None),
+        };
+        if !self.matches(id) {
+            return None;
+        }
+        Some(WatchEvent {
+            r#type: tp as i32,
+            id: id.clone(),
+            value,
+        })
+    }
+
+    fn matches(&self, id: &[u8]) -> bool {
+        if !self.req.ids.is_empty() && !self.req.ids.iter().any(|x| x == id) {
+            return false;
+        }
+        id.starts_with(&self.req.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engula_apis::v1::Value;
+
+    use super::*;
+
+    fn put(id: &[u8], v: i64) -> Write {
+        Write::Put(id.to_vec(), Value::from(v))
+    }
+
+    fn publish(feed: &mut ChangeFeed, name: &str, writes: Vec<Write>) -> u64 {
+        feed.publish(vec![(name.to_owned(), writes)])
+    }
+
+    fn versions(history: &[Arc<Change>]) -> Vec<u64> {
+        history.iter().map(|c| c.version).collect()
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let mut feed = ChangeFeed::default();
+        for i in 1..=3 {
+            assert_eq!(feed.next_version(), i);
+            assert_eq!(publish(&mut feed, "co", vec![put(b"a", 0)]), i);
+        }
+
+        let (history, _) = feed.subscribe(2)?;
+        assert_eq!(versions(&history), vec![2, 3]);
+        let (history, _) = feed.subscribe(1)?;
+        assert_eq!(versions(&history), vec![1, 2, 3]);
+        // Zero and the next version watch new changes only.
+        let (history, _) = feed.subscribe(0)?;
+        assert!(history.is_empty());
+        let (history, _) = feed.subscribe(4)?;
+        assert!(history.is_empty());
+        assert!(matches!(feed.subscribe(5), Err(Error::InvalidArgument(_))));
+
+        for _ in 0..HISTORY_CAPACITY {
+            publish(&mut feed, "co", vec![put(b"a", 0)]);
+        }
+        assert!(matches!(feed.subscribe(1), Err(Error::DataLoss(_))));
+        let (history, _) = feed.subscribe(4)?;
+        assert_eq!(history.len(), HISTORY_CAPACITY);
+
+        feed.close();
+        assert!(matches!(feed.subscribe(0), Err(Error::Aborted(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher() -> Result<()> {
+        let mut feed = ChangeFeed::default();
+        publish(&mut feed, "co", vec![put(b"a1", 1), put(b"b1", 1)]);
+        publish(&mut feed, "other", vec![put(b"a1", 2)]);
+
+        let req = WatchRequest {
+            name: "co".to_owned(),
+            prefix: b"a".to_vec(),
+            start_version: 1,
+            ..Default::default()
+        };
+        let (history, receiver) = feed.subscribe(req.start_version)?;
+        let (tx, mut rx) = mpsc::channel(16);
+        let handle = tokio::spawn(Watcher::new(req).run(history, receiver, tx));
+
+        publish(
+            &mut feed,
+            "co",
+            vec![Write::Delete(b"a1".to_vec()), put(b"a2", 3)],
+        );
+        // Changes without matched objects are skipped.
+        publish(&mut feed, "co", vec![put(b"b2", 4)]);
+        publish(&mut feed, "co", vec![put(b"a3", 5)]);
+        feed.close();
+
+        let res = rx.recv().await.unwrap()?;
+        assert_eq!(res.version, 1);
+        assert_eq!(res.events.len(), 1);
+        assert_eq!(res.events[0].id, b"a1");
+        assert_eq!(res.events[0].value, Some(Value::from(1i64)));
+
+        let res = rx.recv().await.unwrap()?;
+        assert_eq!(res.version, 3);
+        assert_eq!(res.events.len(), 2);
+        assert_eq!(res.events[0].r#type, watch_event::Type::Delete as i32);
+        assert_eq!(res.events[0].value, None);
+        assert_eq!(res.events[1].r#type, watch_event::Type::Put as i32);
+        assert_eq!(res.events[1].id, b"a2");
+
+        let res = rx.recv().await.unwrap()?;
+        assert_eq!(res.version, 5);
+        // The watcher finishes when the feed is closed.
+        assert!(rx.recv().await.is_none());
+        handle.await.unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_ids() -> Result<()> {
+        let mut feed = ChangeFeed::default();
+        let req = WatchRequest {
+            name: "co".to_owned(),
+            ids: vec![b"a".to_vec(), b"c".to_vec()],
+            ..Default::default()
+        };
+        let (history, receiver) = feed.subscribe(req.start_version)?;
+        let (tx, mut rx) = mpsc::channel(16);
+        tokio::spawn(Watcher::new(req).run(history, receiver, tx));
+
+        publish(
+            &mut feed,
+            "co",
+            vec![put(b"a", 1), put(b"ab", 1), put(b"b", 1), put(b"c", 1)],
+        );
+        feed.close();
+
+        let res = rx.recv().await.unwrap()?;
+        let ids: Vec<_> = res.events.into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![b"a".to_vec(), b"c".to_vec()]);
+        assert!(rx.recv().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watcher_lag() -> Result<()> {
+        let mut feed = ChangeFeed::default();
+        let req = WatchRequest {
+            name: "co".to_owned(),
+            ..Default::default()
+        };
+        let (history, receiver) = feed.subscribe(req.start_version)?;
+        // Publishes more changes than the receiver can hold before the
+        // watcher runs.
+        for _ in 0..=HISTORY_CAPACITY {
+            publish(&mut feed, "co", vec![put(b"a", 0)]);
+        }
+        let (tx, mut rx) = mpsc::channel(16);
+        Watcher::new(req).run(history, receiver, tx).await;
+        assert!(matches!(rx.recv().await, Some(Err(Error::DataLoss(_)))));
+        assert!(rx.recv().await.is_none());
+        Ok(())
+    }
+}
@@ -12,24 +12,194 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::{Duration, Instant};
+
 use engula_apis::v1::*;
 
+#[derive(Clone)]
 pub enum Write {
     Put(Vec<u8>, Value),
     Delete(Vec<u8>),
 }
 
-#[derive(Default)]
+/// The object of a request that a write is made for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Owner {
+    /// The index of the expression in the collection request.
+    pub expr: usize,
+    /// The index of the object in the expression batch.
+    pub object: usize,
+}
+
+/// The state of a batch that it can be rolled back to.
+#[derive(Clone, Copy)]
+pub struct Savepoint {
+    writes: usize,
+    waits: usize,
+    deadline: Option<Instant>,
+}
+
 pub struct WriteBatch {
     pub writes: Vec<Write>,
+    /// The objects that the writes are made for, in the order of writes.
+    pub owners: Vec<Owner>,
+    /// Objects that blocking operations in this batch are waiting on.
+    pub waits: Vec<Vec<u8>>,
+    /// The earliest deadline of blocking operations in this batch.
+    pub deadline: Option<Instant>,
+    /// The version of objects written by this batch.
+    pub version: u64,
+    start: Instant,
+    owner: Owner,
 }
 
 impl WriteBatch {
+    /// Creates a batch for a request that started at `start` and commits at
+    /// `version`.
+    pub fn new(start: Instant, version: u64) -> Self {
+        Self {
+            writes: Vec::new(),
+            owners: Vec::new(),
+            waits: Vec::new(),
+            deadline: None,
+            version,
+            start,
+            owner: Owner::default(),
+        }
+    }
+
+    /// Sets the object that the following writes are made for.
+    pub fn set_owner(&mut self, owner: Owner) {
+        self.owner = owner;
+    }
+
     pub fn put(&mut self, id: Vec<u8>, value: Value) {
-        self.writes.push(Write::Put(id, value))
+        self.writes.push(Write::Put(id, value));
+        self.owners.push(self.owner);
     }
 
     pub fn delete(&mut self, id: Vec<u8>) {
-        self.writes.push(Write::Delete(id))
+        self.writes.push(Write::Delete(id));
+        self.owners.push(self.owner);
+    }
+
+    /// Returns true if the batch writes the object.
+    pub fn is_written(&self, id: &[u8]) -> bool {
+        self.writes.iter().any(|w| match w {
+            Write::Put(x, _) | Write::Delete(x) => x == id,
+        })
+    }
+
+    /// Discards the writes made for `owner`.
+    pub fn discard(&mut self, owner: Owner) {
+        let writes = std::mem::take(&mut self.writes);
+        let owners = std::mem::take(&mut self.owners);
+        (self.writes, self.owners) = writes
+            .into_iter()
+            .zip(owners)
+            .filter(|(_, x)| *x != owner)
+            .unzip();
+    }
+
+    /// Returns a savepoint that the batch can be rolled back to.
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            writes: self.writes.len(),
+            waits: self.waits.len(),
+            deadline: self.deadline,
+        }
+    }
+
+    /// Discards the writes and waits made after `savepoint`.
+    pub fn rollback(&mut self, savepoint: Savepoint) {
+        self.writes.truncate(savepoint.writes);
+        self.owners.truncate(savepoint.writes);
+        self.waits.truncate(savepoint.waits);
+        self.deadline = savepoint.deadline;
+    }
+
+    /// Waits on `id` until `timeout` has elapsed since the request started.
+    ///
+    /// Returns false if the timeout has already elapsed.
+    pub fn block(&mut self, id: Vec<u8>, timeout: Duration) -> bool {
+        let deadline = self.start + timeout;
+        if deadline <= Instant::now() {
+            return false;
+        }
+        self.waits.push(id);
+        self.deadline = Some(self.deadline.map_or(deadline, |x| x.min(deadline)));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_savepoint() {
+        let mut wb = WriteBatch::new(Instant::now(), 1);
+        wb.put(b"a".to_vec(), 1i64.into());
+        assert!(wb.block(b"b".to_vec(), Duration::from_secs(10)));
+        let deadline = wb.deadline;
+        let savepoint = wb.savepoint();
+
+        wb.put(b"c".to_vec(), 2i64.into());
+        wb.delete(b"a".to_vec());
+        assert!(wb.block(b"d".to_vec(), Duration::from_secs(1)));
+        assert!(wb.deadline < deadline);
+        assert_eq!(wb.writes.len(), 3);
+        assert_eq!(wb.waits.len(), 2);
+
+        wb.rollback(savepoint);
+        assert_eq!(wb.writes.len(), 1);
+        assert!(matches!(&wb.writes[0], Write::Put(id, _) if id == b"a"));
+        assert_eq!(wb.waits, vec![b"b".to_vec()]);
+        assert_eq!(wb.deadline, deadline);
+    }
+
+    #[test]
+    fn test_rollback_to_empty() {
+        let mut wb = WriteBatch::new(Instant::now(), 1);
+        let savepoint = wb.savepoint();
+        wb.put(b"a".to_vec(), 1i64.into());
+        assert!(wb.block(b"a".to_vec(), Duration::from_secs(1)));
+        wb.rollback(savepoint);
+        assert!(wb.writes.is_empty());
+        assert!(wb.waits.is_empty());
+        assert!(wb.deadline.is_none());
+    }
+
+    #[test]
+    fn test_discard() {
+        let owner = |expr, object| Owner { expr, object };
+        let mut wb = WriteBatch::new(Instant::now(), 1);
+        wb.set_owner(owner(0, 0));
+        wb.put(b"a".to_vec(), 1i64.into());
+        wb.set_owner(owner(0, 1));
+        wb.put(b"b".to_vec(), 1i64.into());
+        wb.delete(b"c".to_vec());
+        wb.set_owner(owner(1, 0));
+        wb.put(b"d".to_vec(), 1i64.into());
+        let savepoint = wb.savepoint();
+        wb.put(b"e".to_vec(), 1i64.into());
+        wb.rollback(savepoint);
+        assert_eq!(wb.owners.len(), 4);
+
+        wb.discard(owner(0, 1));
+        assert_eq!(wb.owners, vec![owner(0, 0), owner(1, 0)]);
+        assert!(matches!(&wb.writes[0], Write::Put(id, _) if id == b"a"));
+        assert!(matches!(&wb.writes[1], Write::Put(id, _) if id == b"d"));
+        assert!(wb.is_written(b"a"));
+        assert!(!wb.is_written(b"c"));
+    }
+
+    #[test]
+    fn test_block_timeout() {
+        let start = Instant::now() - Duration::from_secs(2);
+        let mut wb = WriteBatch::new(start, 1);
+        assert!(!wb.block(b"a".to_vec(), Duration::from_secs(1)));
+        assert!(wb.waits.is_empty());
+        assert!(wb.deadline.is_none());
     }
 }
@@ -0,0 +1,82 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use engula_common::Error;
+use object_engine_client::{Engine as ObjectEngine, RemoteEnv};
+use stream_engine_client::Engine as StreamEngine;
+
+use crate::Result;
+
+/// Connections to the storage engines that back databases.
+///
+/// Each database owns a tenant with the same name in both engines.
+#[derive(Clone)]
+pub struct Engines {
+    object: ObjectEngine<RemoteEnv>,
+    stream: StreamEngine,
+}
+
+impl Engines {
+    pub async fn connect(object_engine_url: &str, stream_engine_url: &str) -> Result<Self> {
+        let env = RemoteEnv::connect(object_engine_url)
+            .await
+            .map_err(object_error)?;
+        let object = ObjectEngine::open(env).await.map_err(object_error)?;
+        let id = format!("transactor-{}", std::process::id());
+        let stream = StreamEngine::new(id, stream_engine_url)
+            .await
+            .map_err(stream_error)?;
+        Ok(Self { object, stream })
+    }
+
+    pub async fn close(&self) {
+        self.stream.close().await;
+    }
+
+    /// Creates the tenants of a database.
+    ///
+    /// Tenants outlive the in-memory supervisor, so existing tenants are
+    /// reused.
+    pub(crate) async fn create_tenant(&self, name: &str) -> Result<()> {
+        match self.object.create_tenant(name).await {
+            Ok(_) | Err(object_engine_client::Error::AlreadyExists(_)) => {}
+            Err(err) => return Err(object_error(err)),
+        }
+        match self.stream.create_tenant(name).await {
+            Ok(_) | Err(stream_engine_client::Error::AlreadyExists(_)) => {}
+            Err(err) => return Err(stream_error(err)),
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn delete_tenant(&self, name: &str) -> Result<()> {
+        match self.object.delete_tenant(name).await {
+            Ok(()) | Err(object_engine_client::Error::NotFound(_)) => {}
+            Err(err) => return Err(object_error(err)),
+        }
+        match self.stream.delete_tenant(name).await {
+            Ok(()) | Err(stream_engine_client::Error::NotFound(_)) => {}
+            Err(err) => return Err(stream_error(err)),
+        }
+        Ok(())
+    }
+}
+
+fn object_error(err: object_engine_client::Error) -> Error {
+    tonic::Status::from(err).into()
+}
+
+fn stream_error(err: stream_engine_client::Error) -> Error {
+    tonic::Status::from(err).into()
+}
@@ -12,9 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod engines;
 mod server;
 mod transactor;
 
 use engula_common::Result;
 
-pub use self::{server::Server, transactor::Transactor};
+pub use self::{engines::Engines, server::Server, transactor::Transactor};
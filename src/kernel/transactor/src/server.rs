@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::pin::Pin;
+
 use engula_apis::v1::*;
+use engula_cooperator::apis::{self as coapis, cooperator_server};
+use futures::{Stream, StreamExt};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 use crate::Transactor;
@@ -30,6 +35,12 @@ impl Server {
     pub fn into_service(self) -> engula_server::EngulaServer<Self> {
         engula_server::EngulaServer::new(self)
     }
+
+    /// Returns a service that accepts cooperator requests, which supports
+    /// best-effort batches, scans, watches and quotas.
+    pub fn into_cooperator_service(self) -> cooperator_server::CooperatorServer<Self> {
+        cooperator_server::CooperatorServer::new(self)
+    }
 }
 
 #[tonic::async_trait]
@@ -40,3 +51,45 @@ impl engula_server::Engula for Server {
         Ok(Response::new(res))
     }
 }
+
+#[tonic::async_trait]
+impl cooperator_server::Cooperator for Server {
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<coapis::WatchResponse, Status>> + Send>>;
+
+    async fn batch(
+        &self,
+        req: Request<coapis::BatchRequest>,
+    ) -> Result<Response<coapis::BatchResponse>, Status> {
+        let req = req.into_inner();
+        let res = self.transactor.execute(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn scan(
+        &self,
+        req: Request<coapis::ScanRequest>,
+    ) -> Result<Response<coapis::ScanResponse>, Status> {
+        let req = req.into_inner();
+        let res = self.transactor.scan(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn set_quota(
+        &self,
+        req: Request<coapis::SetQuotaRequest>,
+    ) -> Result<Response<coapis::SetQuotaResponse>, Status> {
+        let req = req.into_inner();
+        let res = self.transactor.set_quota(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn watch(
+        &self,
+        req: Request<coapis::WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let req = req.into_inner();
+        let rx = self.transactor.watch(req).await?;
+        let stream = ReceiverStream::new(rx).map(|res| res.map_err(Status::from));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
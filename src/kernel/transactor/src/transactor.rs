@@ -13,15 +13,22 @@
 // limitations under the License.
 
 use engula_apis::v1::*;
-use engula_cooperator::Cooperator;
+use engula_cooperator::{apis as coapis, Cooperator, Quotas};
 use engula_supervisor::Supervisor;
+use tokio::sync::mpsc;
 
-use crate::Result;
+use crate::{Engines, Result};
 
+/// Serves the universe, database and collection APIs.
+///
+/// The supervisor and the cooperator keep their state in memory. The
+/// engines, if any, only get a tenant per database, so data is lost when
+/// the transactor restarts.
 #[derive(Clone)]
 pub struct Transactor {
     supervisor: Supervisor,
     cooperator: Cooperator,
+    engines: Option<Engines>,
 }
 
 impl Default for Transactor {
@@ -32,28 +39,111 @@ impl Default for Transactor {
 
 impl Transactor {
     pub fn new() -> Self {
+        Self::with_quotas(Quotas::default())
+    }
+
+    /// Creates a transactor that applies `quotas` to databases and
+    /// collections until they are given their own quotas.
+    pub fn with_quotas(quotas: Quotas) -> Self {
         let supervisor = Supervisor::default();
-        let cooperator = Cooperator::new(supervisor.clone());
+        let cooperator = Cooperator::with_quotas(supervisor.clone(), quotas);
         Self {
             supervisor,
             cooperator,
+            engines: None,
+        }
+    }
+
+    /// Creates a transactor that provisions the tenants of databases in
+    /// `engines`.
+    pub fn with_engines(quotas: Quotas, engines: Engines) -> Self {
+        Self {
+            engines: Some(engines),
+            ..Self::with_quotas(quotas)
         }
     }
 
+    pub async fn close(&self) {
+        self.cooperator.close().await;
+        if let Some(engines) = &self.engines {
+            engines.close().await;
+        }
+    }
+
+    /// Executes a batch, where each database request is executed atomically.
     pub async fn batch(&self, mut batch_req: BatchRequest) -> Result<BatchResponse> {
         let mut batch_res = BatchResponse::default();
         let universes = std::mem::take(&mut batch_req.universes);
         if !universes.is_empty() {
+            let (created, deleted) = changed_databases(&universes);
+            if let Some(engines) = &self.engines {
+                for name in &created {
+                    engines.create_tenant(name).await?;
+                }
+            }
             let req = engula_supervisor::apis::BatchRequest { universes };
             let mut res = self.supervisor.batch(req).await?;
             batch_res.universes = std::mem::take(&mut res.universes);
+            // Tenants are deleted only after their databases are gone.
+            if let Some(engines) = &self.engines {
+                for name in &deleted {
+                    engines.delete_tenant(name).await?;
+                }
+            }
         }
         let databases = std::mem::take(&mut batch_req.databases);
         if !databases.is_empty() {
-            let req = engula_cooperator::apis::BatchRequest { databases };
+            let req = coapis::BatchRequest {
+                databases,
+                best_effort: false,
+            };
             let mut res = self.cooperator.batch(req).await?;
             batch_res.databases = std::mem::take(&mut res.databases);
         }
         Ok(batch_res)
     }
+
+    /// Executes database requests with the cooperator.
+    ///
+    /// In best-effort mode, failed objects are returned as errors in the
+    /// response instead of failing the whole database request.
+    pub async fn execute(&self, req: coapis::BatchRequest) -> Result<coapis::BatchResponse> {
+        self.cooperator.batch(req).await
+    }
+
+    pub async fn set_quota(
+        &self,
+        req: coapis::SetQuotaRequest,
+    ) -> Result<coapis::SetQuotaResponse> {
+        self.cooperator.set_quota(req).await
+    }
+
+    pub async fn scan(&self, req: coapis::ScanRequest) -> Result<coapis::ScanResponse> {
+        self.cooperator.scan(req).await
+    }
+
+    pub async fn watch(
+        &self,
+        req: coapis::WatchRequest,
+    ) -> Result<mpsc::Receiver<Result<coapis::WatchResponse>>> {
+        self.cooperator.watch(req).await
+    }
+}
+
+/// Returns the databases created and deleted by `universes`.
+fn changed_databases(universes: &[UniverseRequest]) -> (Vec<String>, Vec<String>) {
+    let mut created = Vec::new();
+    let mut deleted = Vec::new();
+    for req in universes {
+        match &req.request {
+            Some(universe_request::Request::CreateDatabase(req)) => {
+                created.push(req.name.clone());
+            }
+            Some(universe_request::Request::DeleteDatabase(req)) => {
+                deleted.push(req.name.clone());
+            }
+            _ => {}
+        }
+    }
+    (created, deleted)
 }
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use object_engine_filestore::SequentialWrite;
+use object_engine_lsmstore::Timestamp;
 use object_engine_master::proto::*;
 
 use crate::{env::Iter, BucketEnv, Env, Error, Result, TenantEnv};
@@ -52,11 +53,24 @@ impl<E: Env> Bucket<E> {
     }
 
     pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        self.bucket.get(key).await
+        self.get_at(key, Timestamp::MAX).await
+    }
+
+    /// Returns the value of the key as of the timestamp.
+    pub async fn get_at(&self, key: &[u8], ts: Timestamp) -> Result<Option<Vec<u8>>> {
+        self.bucket.get_at(key, ts).await
     }
 
     pub async fn iter(&self) -> Result<Box<dyn Iter>> {
-        self.bucket.iter().await
+        self.iter_at(Timestamp::MAX).await
+    }
+
+    /// Returns an iterator over the entries as of the timestamp.
+    ///
+    /// Compactions keep the versions visible to the iterator until it is
+    /// dropped.
+    pub async fn iter_at(&self, ts: Timestamp) -> Result<Box<dyn Iter>> {
+        self.bucket.iter_at(ts).await
     }
 
     pub(crate) async fn new_sequential_writer(
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use object_engine_lsmstore::{TableBuilder, TableBuilderOptions};
+use object_engine_lsmstore::TableBuilder;
 use object_engine_master::proto::*;
 
 use crate::{Bucket, Env, Error, Result, SstBuilder};
@@ -35,7 +35,8 @@ impl<E: Env> BulkLoad<E> {
     pub async fn new_sst_builder(&self, bucket: &Bucket<E>) -> Result<SstBuilder> {
         let file_name = self.allocate_file_name().await?;
         let file_writer = bucket.new_sequential_writer(&file_name).await?;
-        let table_options = TableBuilderOptions::default();
+        let desc = bucket.desc().await?;
+        let table_options = desc.options.unwrap_or_default().table_options();
         let table_builder = TableBuilder::new(file_writer, table_options);
         Ok(SstBuilder::new(
             self.token.clone(),
@@ -70,6 +71,18 @@ impl<E: Env> BulkLoad<E> {
         }
     }
 
+    /// Aborts this bulkload, the written files are deleted later.
+    pub async fn abort(self) -> Result<()> {
+        let req = AbortBulkLoadRequest { token: self.token };
+        let req = request_union::Request::AbortBulkload(req);
+        let res = self.env.handle_union(req).await?;
+        if let response_union::Response::AbortBulkload(_) = res {
+            Ok(())
+        } else {
+            Err(Error::internal("missing abort bulkload response"))
+        }
+    }
+
     async fn allocate_file_name(&self) -> Result<String> {
         let req = AllocateFileNamesRequest {
             token: self.token.clone(),
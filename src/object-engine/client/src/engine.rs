@@ -14,7 +14,7 @@
 
 use object_engine_master::proto::*;
 
-use crate::{Env, Result, Tenant};
+use crate::{Env, Error, Result, Tenant};
 
 #[derive(Clone)]
 pub struct Engine<E: Env> {
@@ -40,4 +40,48 @@ impl<E: Env> Engine<E> {
         self.env.handle_union(req).await?;
         self.tenant(name).await
     }
+
+    pub async fn list_tenants(&self) -> Result<Vec<TenantDesc>> {
+        let req = request_union::Request::ListTenants(ListTenantsRequest {});
+        let res = self.env.handle_union(req).await?;
+        if let response_union::Response::ListTenants(res) = res {
+            Ok(res.descs)
+        } else {
+            Err(Error::internal("missing list tenants response"))
+        }
+    }
+
+    pub async fn update_tenant(&self, name: &str, options: TenantOptions) -> Result<TenantDesc> {
+        let req = UpdateTenantRequest {
+            name: name.to_owned(),
+            options: Some(options),
+        };
+        let req = request_union::Request::UpdateTenant(req);
+        let res = self.env.handle_union(req).await?;
+        let desc = if let response_union::Response::UpdateTenant(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing tenant descriptor"))
+    }
+
+    pub async fn delete_tenant(&self, name: &str) -> Result<()> {
+        let req = DeleteTenantRequest {
+            name: name.to_owned(),
+        };
+        let req = request_union::Request::DeleteTenant(req);
+        self.env.handle_union(req).await?;
+        Ok(())
+    }
+
+    pub async fn cache_stats(&self) -> Result<GetCacheStatsResponse> {
+        let req = request_union::Request::GetCacheStats(GetCacheStatsRequest {});
+        let res = self.env.handle_union(req).await?;
+        if let response_union::Response::GetCacheStats(res) = res {
+            Ok(res)
+        } else {
+            Err(Error::internal("missing get cache stats response"))
+        }
+    }
 }
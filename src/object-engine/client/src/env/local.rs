@@ -15,8 +15,8 @@
 use std::path::PathBuf;
 
 use object_engine_filestore::SequentialWrite;
-use object_engine_lsmstore::{Key, MergingIterator, ValueType};
-use object_engine_master::{proto::*, Bucket, Master, Tenant};
+use object_engine_lsmstore::Timestamp;
+use object_engine_master::{proto::*, Bucket, BucketIter, Config, Master, Tenant};
 
 use super::Iter;
 use crate::{async_trait, Result};
@@ -31,6 +31,15 @@ impl Env {
         let master = Master::open(path).await?;
         Ok(Self { master })
     }
+
+    pub async fn open_with_config(path: impl Into<PathBuf>, config: Config) -> Result<Self> {
+        let master = Master::open_with_config(path, config).await?;
+        Ok(Self { master })
+    }
+
+    pub fn master(&self) -> &Master {
+        &self.master
+    }
 }
 
 #[async_trait]
@@ -85,27 +94,24 @@ impl super::BucketEnv for BucketEnv {
         self.bucket.new_sequential_writer(name).await
     }
 
-    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        self.bucket.get(key).await
+    async fn get_at(&self, key: &[u8], ts: Timestamp) -> Result<Option<Vec<u8>>> {
+        self.bucket.get_at(key, ts).await
     }
 
-    async fn iter(&self) -> Result<Box<dyn Iter>> {
-        Ok(Box::new(LocalIter {
-            iter: self.bucket.iter().await?,
-            last_id: None,
-        }))
+    async fn iter_at(&self, ts: Timestamp) -> Result<Box<dyn Iter>> {
+        let iter = self.bucket.iter_at(ts).await?;
+        Ok(Box::new(LocalIter { iter }))
     }
 }
 
 struct LocalIter {
-    iter: MergingIterator,
-    last_id: Option<Vec<u8>>,
+    iter: BucketIter,
 }
 
 #[async_trait]
 impl Iter for LocalIter {
     fn key(&self) -> Vec<u8> {
-        self.iter.key().id().to_owned()
+        self.iter.id().to_owned()
     }
 
     fn value(&self) -> &[u8] {
@@ -117,55 +123,15 @@ impl Iter for LocalIter {
     }
 
     async fn seek_to_first(&mut self) -> Result<()> {
-        self.iter.seek_to_first().await?;
-        self.skip_invisible_if_needed().await?;
-        Ok(())
+        self.iter.seek_to_first().await
     }
 
     async fn seek(&mut self, target: &[u8]) -> Result<()> {
-        let key = Key::encode_to_vec(target, u64::MAX, object_engine_lsmstore::ValueType::Put);
-        self.iter.seek(key.as_slice().into()).await?;
-        self.skip_invisible_if_needed().await?;
-        Ok(())
+        self.iter.seek(target).await
     }
 
     async fn next(&mut self) -> Result<()> {
-        loop {
-            self.iter.next().await?;
-            if !self.iter.valid() {
-                break;
-            }
-            if let ValueType::Delete = self.iter.key().tp() {
-                self.last_id = Some(self.iter.key().id().to_owned());
-                continue;
-            }
-            if self.last_id.is_none() {
-                self.last_id = Some(self.iter.key().id().to_owned());
-                break;
-            }
-            if self.last_id.as_ref().unwrap() != self.iter.key().id() {
-                self.last_id = Some(self.iter.key().id().to_owned());
-                break;
-            }
-            // TODO: stats & ratelimit ?
-        }
-        Ok(())
-    }
-}
-
-impl LocalIter {
-    async fn skip_invisible_if_needed(&mut self) -> Result<()> {
-        if !self.iter.valid() {
-            return Ok(());
-        }
-        self.last_id = Some(self.iter.key().id().to_owned());
-        match self.iter.key().tp() {
-            ValueType::Delete => {
-                self.next().await?;
-            }
-            ValueType::Merge => todo!(),
-            _ => {}
-        }
-        Ok(())
+        // TODO: stats & ratelimit ?
+        self.iter.next().await
     }
 }
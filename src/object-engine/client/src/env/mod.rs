@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use object_engine_filestore::SequentialWrite;
+use object_engine_lsmstore::Timestamp;
 use object_engine_master::proto::*;
 
 use crate::{async_trait, Error, Result};
@@ -59,9 +60,9 @@ pub trait BucketEnv: Clone + Sync + Send {
 
     async fn new_sequential_writer(&self, name: &str) -> Result<Box<dyn SequentialWrite>>;
 
-    async fn get(&self, k: &[u8]) -> Result<Option<Vec<u8>>>;
+    async fn get_at(&self, k: &[u8], ts: Timestamp) -> Result<Option<Vec<u8>>>;
 
-    async fn iter(&self) -> Result<Box<dyn Iter>>;
+    async fn iter_at(&self, ts: Timestamp) -> Result<Box<dyn Iter>>;
 }
 
 #[async_trait]
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use object_engine_filestore::SequentialWrite;
+use object_engine_lsmstore::Timestamp;
 use object_engine_master::proto::*;
 
 use super::Iter;
@@ -96,11 +97,11 @@ impl super::BucketEnv for BucketEnv {
         todo!();
     }
 
-    async fn get(&self, _k: &[u8]) -> Result<Option<Vec<u8>>> {
+    async fn get_at(&self, _k: &[u8], _ts: Timestamp) -> Result<Option<Vec<u8>>> {
         todo!();
     }
 
-    async fn iter(&self) -> Result<Box<dyn Iter>> {
+    async fn iter_at(&self, _ts: Timestamp) -> Result<Box<dyn Iter>> {
         todo!();
     }
 }
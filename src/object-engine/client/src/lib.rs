@@ -33,9 +33,67 @@ pub use self::{
 
 #[cfg(test)]
 mod testmode {
+    use std::time::Duration;
+
+    use object_engine_lsmstore::Timestamp;
+    use object_engine_master::{
+        proto::{BucketOptions, Compression},
+        Config,
+    };
 
     use super::*;
 
+    /// An engine in a temporary directory with tenant t1 and bucket b1.
+    struct Setup {
+        dir: tempfile::TempDir,
+        env: LocalEnv,
+        tenant: Tenant<LocalEnv>,
+        bucket: Bucket<LocalEnv>,
+    }
+
+    impl Setup {
+        /// Returns the number of files in the directory of b1.
+        fn num_files(&self) -> usize {
+            let bucket_dir = self.dir.path().join("t1").join("b1");
+            std::fs::read_dir(bucket_dir).unwrap().count()
+        }
+    }
+
+    async fn setup(config: Config) -> Result<Setup> {
+        let dir = tempfile::tempdir()?;
+        let env = LocalEnv::open_with_config(dir.path(), config).await?;
+        let eng = Engine::open(env.clone()).await?;
+        eng.create_tenant("t1").await?;
+        let tenant = eng.tenant("t1").await?;
+        tenant.create_bucket("b1").await?;
+        let bucket = tenant.bucket("b1").await?;
+        Ok(Setup {
+            dir,
+            env,
+            tenant,
+            bucket,
+        })
+    }
+
+    /// Bulk loads a file of (id, ts, value) entries into the bucket, where
+    /// entries without a value are deletions.
+    async fn load(
+        tenant: &Tenant<LocalEnv>,
+        bucket: &Bucket<LocalEnv>,
+        entries: &[(&str, Timestamp, Option<&str>)],
+    ) -> Result<()> {
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut builder = bulk_load.new_sst_builder(bucket).await?;
+        for (id, ts, value) in entries {
+            match value {
+                Some(value) => builder.put(id.as_bytes(), *ts, value.as_bytes()).await?,
+                None => builder.delete(id.as_bytes(), *ts).await?,
+            }
+        }
+        bulk_load.finish_sst_builder(builder).await?;
+        bulk_load.commit().await
+    }
+
     #[tokio::test]
     async fn test_get_iter_put() -> Result<()> {
         let p = tempfile::tempdir()?;
@@ -155,4 +213,531 @@ mod testmode {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_list_update_delete() -> Result<()> {
+        use object_engine_master::proto::*;
+
+        let p = tempfile::tempdir()?;
+        let env1 = LocalEnv::open(p.path()).await?;
+        let eng = Engine::open(env1.clone()).await?;
+
+        eng.create_tenant("t1").await?;
+        eng.create_tenant("t2").await?;
+        let names: Vec<_> = eng
+            .list_tenants()
+            .await?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["t1", "t2"]);
+
+        let tenant = eng.tenant("t1").await?;
+        tenant.create_bucket("b1").await?;
+        tenant.create_bucket("b2").await?;
+        let names: Vec<_> = tenant
+            .list_buckets()
+            .await?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["b1", "b2"]);
+
+        let desc = eng.update_tenant("t1", Default::default()).await?;
+        assert_eq!(desc.properties.unwrap().num_buckets, 2);
+        let options = BucketOptions {
+            compression: Compression::Lz4 as i32,
+            ..Default::default()
+        };
+        let desc = tenant.update_bucket("b1", options).await?;
+        assert_eq!(desc.name, "b1");
+
+        // Updates without options are rejected, instead of resetting them.
+        let req = UpdateTenantRequest {
+            name: "t1".to_owned(),
+            options: None,
+        };
+        let res = env1
+            .handle_union(request_union::Request::UpdateTenant(req))
+            .await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        let req = UpdateBucketRequest {
+            tenant: "t1".to_owned(),
+            bucket: "b1".to_owned(),
+            options: None,
+        };
+        let res = env1
+            .handle_union(request_union::Request::UpdateBucket(req))
+            .await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        let options = tenant.bucket("b1").await?.desc().await?.options.unwrap();
+        assert_eq!(options.compression(), Compression::Lz4);
+
+        let bucket1 = tenant.bucket("b1").await?;
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+        t1.put(b"k1", 1, b"123").await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        bulk_load.commit().await?;
+
+        tenant.delete_bucket("b1").await?;
+        assert!(!p.path().join("t1").join("b1").exists());
+        assert!(matches!(tenant.bucket("b1").await, Err(Error::NotFound(_))));
+        assert!(matches!(
+            tenant.delete_bucket("b1").await,
+            Err(Error::NotFound(_))
+        ));
+        tenant.create_bucket("b1").await?;
+        assert_eq!(tenant.bucket("b1").await?.get(b"k1").await?, None);
+
+        eng.delete_tenant("t1").await?;
+        assert!(!p.path().join("t1").exists());
+        assert!(matches!(eng.tenant("t1").await, Err(Error::NotFound(_))));
+        let names: Vec<_> = eng
+            .list_tenants()
+            .await?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["t2"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reopen() -> Result<()> {
+        let p = tempfile::tempdir()?;
+        {
+            let eng = Engine::open(LocalEnv::open(p.path()).await?).await?;
+            eng.create_tenant("t1").await?;
+            let tenant = eng.tenant("t1").await?;
+            tenant.create_bucket("b1").await?;
+            tenant.create_bucket("b2").await?;
+            tenant.delete_bucket("b2").await?;
+            let options = BucketOptions {
+                compression: Compression::Lz4 as i32,
+                ..Default::default()
+            };
+            tenant.update_bucket("b1", options).await?;
+
+            let bucket1 = tenant.bucket("b1").await?;
+            let mut bulk_load = tenant.begin_bulkload().await?;
+            let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+            t1.put(b"k1", 1, b"123").await?;
+            bulk_load.finish_sst_builder(t1).await?;
+            bulk_load.commit().await?;
+        }
+
+        let eng = Engine::open(LocalEnv::open(p.path()).await?).await?;
+        let names: Vec<_> = eng
+            .list_tenants()
+            .await?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["t1"]);
+        let tenant = eng.tenant("t1").await?;
+        let names: Vec<_> = tenant
+            .list_buckets()
+            .await?
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, vec!["b1"]);
+        let bucket1 = tenant.bucket("b1").await?;
+        let options = bucket1.desc().await?.options.unwrap();
+        assert_eq!(options.compression(), Compression::Lz4);
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"123");
+
+        // New files must not overwrite the recovered ones.
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+        t1.put(b"k2", 2, b"456").await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        bulk_load.commit().await?;
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"123");
+        assert_eq!(bucket1.get(b"k2").await?.unwrap(), b"456");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulkload_lifecycle() -> Result<()> {
+        use object_engine_master::proto::*;
+
+        let config = Config {
+            bulkload_lease_ms: 100,
+            ..Default::default()
+        };
+        let s = setup(config).await?;
+        let (env, tenant, bucket1) = (&s.env, &s.tenant, &s.bucket);
+
+        // A committed token is removed, so it can't commit again.
+        let req = BeginBulkLoadRequest {
+            tenant: "t1".to_owned(),
+        };
+        let res = env
+            .handle_union(request_union::Request::BeginBulkload(req))
+            .await?;
+        let token = match res {
+            response_union::Response::BeginBulkload(res) => res.token,
+            _ => panic!("unexpected response"),
+        };
+        let commit = || {
+            let req = CommitBulkLoadRequest {
+                token: token.clone(),
+                files: vec![],
+            };
+            env.handle_union(request_union::Request::CommitBulkload(req))
+        };
+        commit().await?;
+        assert!(matches!(commit().await, Err(Error::NotFound(_))));
+
+        // The files of an aborted bulk load are swept.
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(bucket1).await?;
+        t1.put(b"k1", 1, b"123").await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        bulk_load.abort().await?;
+        assert_eq!(s.num_files(), 1);
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 0);
+
+        // The files of an expired bulk load are swept, and it can't commit.
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(bucket1).await?;
+        t1.put(b"k1", 1, b"123").await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        // Waits until the lease expires.
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 0);
+        assert!(matches!(bulk_load.commit().await, Err(Error::NotFound(_))));
+
+        // The committed files are kept.
+        load(tenant, bucket1, &[("k1", 1, Some("123"))]).await?;
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 1);
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"123");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulkload_commit_validation() -> Result<()> {
+        use object_engine_lsmstore::{Key, TableBuilder, ValueType};
+        use object_engine_master::proto::*;
+
+        let p = tempfile::tempdir()?;
+        let env = LocalEnv::open(p.path()).await?;
+        let eng = Engine::open(env.clone()).await?;
+        eng.create_tenant("t1").await?;
+        eng.create_tenant("t2").await?;
+        let tenant = eng.tenant("t1").await?;
+        tenant.create_bucket("b1").await?;
+        eng.tenant("t2").await?.create_bucket("b2").await?;
+        let bucket1 = tenant.bucket("b1").await?;
+
+        let req = BeginBulkLoadRequest {
+            tenant: "t1".to_owned(),
+        };
+        let res = env
+            .handle_union(request_union::Request::BeginBulkload(req))
+            .await?;
+        let token = match res {
+            response_union::Response::BeginBulkload(res) => res.token,
+            _ => panic!("unexpected response"),
+        };
+        let req = AllocateFileNamesRequest {
+            token: token.clone(),
+            count: 2,
+        };
+        let res = env
+            .handle_union(request_union::Request::AllocateFileNames(req))
+            .await?;
+        let names = match res {
+            response_union::Response::AllocateFileNames(res) => res.names,
+            _ => panic!("unexpected response"),
+        };
+
+        let mut descs = Vec::new();
+        for (name, id) in names.iter().zip([b"k1", b"k2"]) {
+            let writer = bucket1.new_sequential_writer(name).await?;
+            let mut builder = TableBuilder::new(writer, Default::default());
+            let key = Key::encode_to_vec(id, 1, ValueType::Put);
+            builder.add(key.as_slice().into(), b"v").await?;
+            let desc = builder.finish().await?;
+            descs.push(BulkLoadFileDesc {
+                bucket: "b1".to_owned(),
+                file_name: name.clone(),
+                file_size: desc.table_size as u64,
+                lower_bound: desc.lower_bound,
+                upper_bound: desc.upper_bound,
+            });
+        }
+        let commit = |files: Vec<BulkLoadFileDesc>| {
+            let req = CommitBulkLoadRequest {
+                token: token.clone(),
+                files,
+            };
+            env.handle_union(request_union::Request::CommitBulkload(req))
+        };
+
+        let mut invalid_descs = Vec::new();
+        let mut desc = descs[1].clone();
+        desc.file_name = "999999.sst".to_owned();
+        invalid_descs.push(desc);
+        let mut desc = descs[1].clone();
+        desc.file_size += 1;
+        invalid_descs.push(desc);
+        let mut desc = descs[1].clone();
+        desc.lower_bound = descs[0].lower_bound.clone();
+        invalid_descs.push(desc);
+        invalid_descs.push(descs[0].clone());
+        for desc in invalid_descs {
+            let res = commit(vec![descs[0].clone(), desc]).await;
+            assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        }
+        // The bucket must belong to the tenant of the bulk load.
+        let mut desc = descs[1].clone();
+        desc.bucket = "b2".to_owned();
+        let res = commit(vec![descs[0].clone(), desc]).await;
+        assert!(matches!(res, Err(Error::NotFound(_))));
+        // Nothing is applied by the rejected commits.
+        assert_eq!(bucket1.get(b"k1").await?, None);
+
+        commit(descs).await?;
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"v");
+        assert_eq!(bucket1.get(b"k2").await?.unwrap(), b"v");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compaction() -> Result<()> {
+        let config = Config {
+            l0_compaction_trigger: 2,
+            ..Default::default()
+        };
+        let s = setup(config).await?;
+        let (env, tenant, bucket1) = (&s.env, &s.tenant, &s.bucket);
+
+        let entries = [
+            ("k1", 1, Some("123")),
+            ("k2", 1, Some("456")),
+            ("k3", 1, Some("789")),
+        ];
+        load(tenant, bucket1, &entries).await?;
+        load(tenant, bucket1, &[("k1", 2, Some("111")), ("k2", 2, None)]).await?;
+
+        // Both files are merged into one, and the overwritten and deleted
+        // entries are dropped.
+        env.master().compact().await;
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 1);
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"111");
+        assert_eq!(None, bucket1.get(b"k2").await?);
+        assert_eq!(bucket1.get(b"k3").await?.unwrap(), b"789");
+
+        load(tenant, bucket1, &[("k1", 3, None), ("k4", 3, Some("000"))]).await?;
+        load(tenant, bucket1, &[("k3", 4, Some("333"))]).await?;
+
+        // Level 0 files are merged with the overlapping files in level 1.
+        env.master().compact().await;
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 1);
+        assert_eq!(None, bucket1.get(b"k1").await?);
+        assert_eq!(bucket1.get(b"k3").await?.unwrap(), b"333");
+        assert_eq!(bucket1.get(b"k4").await?.unwrap(), b"000");
+
+        let mut b1_iter = bucket1.iter().await?;
+        b1_iter.seek_to_first().await?;
+        assert_eq!(b1_iter.key(), b"k3");
+        assert_eq!(b1_iter.value(), b"333");
+        b1_iter.next().await?;
+        assert_eq!(b1_iter.key(), b"k4");
+        b1_iter.next().await?;
+        assert!(!b1_iter.valid());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge() -> Result<()> {
+        let config = Config {
+            l0_compaction_trigger: 2,
+            ..Default::default()
+        };
+        let s = setup(config).await?;
+        let (env, tenant) = (&s.env, &s.tenant);
+        let options = BucketOptions {
+            merge_operator: "unknown".to_owned(),
+            ..Default::default()
+        };
+        let res = tenant.update_bucket("b1", options).await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        let options = BucketOptions {
+            merge_operator: "i64_add".to_owned(),
+            ..Default::default()
+        };
+        tenant.update_bucket("b1", options).await?;
+        let bucket1 = tenant.bucket("b1").await?;
+        let v = |x: i64| x.to_le_bytes().to_vec();
+
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+        t1.put(b"k1", 1, &v(10)).await?;
+        t1.merge(b"k2", 1, &v(1)).await?;
+        t1.put(b"k3", 1, &v(5)).await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        bulk_load.commit().await?;
+
+        let mut bulk_load = tenant.begin_bulkload().await?;
+        let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+        t1.merge(b"k1", 3, &v(2)).await?;
+        t1.merge(b"k1", 2, &v(3)).await?;
+        t1.merge(b"k2", 2, &v(4)).await?;
+        t1.merge(b"k3", 4, &v(6)).await?;
+        t1.delete(b"k3", 3).await?;
+        bulk_load.finish_sst_builder(t1).await?;
+        bulk_load.commit().await?;
+        // Reads apply the operands in both files.
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), v(15));
+        assert_eq!(bucket1.get(b"k2").await?.unwrap(), v(5));
+        assert_eq!(bucket1.get(b"k3").await?.unwrap(), v(6));
+
+        // Operands are folded into the values by the compaction.
+        env.master().compact().await;
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 1);
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), v(15));
+        assert_eq!(bucket1.get(b"k2").await?.unwrap(), v(5));
+        assert_eq!(bucket1.get(b"k3").await?.unwrap(), v(6));
+
+        let mut b1_iter = bucket1.iter().await?;
+        b1_iter.seek_to_first().await?;
+        let mut entries = Vec::new();
+        while b1_iter.valid() {
+            entries.push((b1_iter.key(), b1_iter.value().to_owned()));
+            b1_iter.next().await?;
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (b"k1".to_vec(), v(15)),
+                (b"k2".to_vec(), v(5)),
+                (b"k3".to_vec(), v(6)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() -> Result<()> {
+        let config = Config {
+            l0_compaction_trigger: 2,
+            ..Default::default()
+        };
+        let s = setup(config).await?;
+        let (env, tenant, bucket1) = (&s.env, &s.tenant, &s.bucket);
+
+        load(
+            tenant,
+            bucket1,
+            &[("k1", 1, Some("123")), ("k2", 1, Some("456"))],
+        )
+        .await?;
+
+        // The iterator holds a snapshot at 1 during the compaction.
+        let mut b1_iter = bucket1.iter_at(1).await?;
+        let entries = [
+            ("k1", 2, Some("111")),
+            ("k2", 2, None),
+            ("k3", 2, Some("789")),
+        ];
+        load(tenant, bucket1, &entries).await?;
+        env.master().compact().await;
+        env.master().sweep().await;
+        // Only the file read by the iterator is kept along with the output.
+        assert_eq!(s.num_files(), 2);
+
+        b1_iter.seek_to_first().await?;
+        assert_eq!(b1_iter.key(), b"k1");
+        assert_eq!(b1_iter.value(), b"123");
+        b1_iter.next().await?;
+        assert_eq!(b1_iter.key(), b"k2");
+        assert_eq!(b1_iter.value(), b"456");
+        b1_iter.next().await?;
+        assert!(!b1_iter.valid());
+
+        // The files of the iterator are deleted once it is dropped, and the
+        // compaction output keeps the versions of the snapshot.
+        drop(b1_iter);
+        env.master().sweep().await;
+        assert_eq!(s.num_files(), 1);
+        // Versions below the snapshot might have been compacted away.
+        let res = bucket1.get_at(b"k1", 0).await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        assert_eq!(bucket1.get_at(b"k1", 1).await?.unwrap(), b"123");
+        assert_eq!(bucket1.get_at(b"k1", 2).await?.unwrap(), b"111");
+        assert_eq!(bucket1.get_at(b"k2", 1).await?.unwrap(), b"456");
+        assert_eq!(bucket1.get_at(b"k2", 2).await?, None);
+        assert_eq!(bucket1.get(b"k3").await?.unwrap(), b"789");
+
+        let mut b1_iter = bucket1.iter_at(2).await?;
+        b1_iter.seek_to_first().await?;
+        assert_eq!(b1_iter.key(), b"k1");
+        assert_eq!(b1_iter.value(), b"111");
+        b1_iter.next().await?;
+        assert_eq!(b1_iter.key(), b"k3");
+        b1_iter.next().await?;
+        assert!(!b1_iter.valid());
+        drop(b1_iter);
+
+        // Without live snapshots, a compaction keeps only the latest versions,
+        // so older timestamps can't be read anymore.
+        for ts in [3, 4] {
+            load(tenant, bucket1, &[("k1", ts, Some("222"))]).await?;
+        }
+        env.master().compact().await;
+        let res = bucket1.get_at(b"k1", 2).await;
+        assert!(matches!(res, Err(Error::InvalidArgument(_))));
+        assert!(bucket1.iter_at(4).await.is_err());
+        assert_eq!(bucket1.get(b"k1").await?.unwrap(), b"222");
+        assert!(bucket1.iter().await.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache() -> Result<()> {
+        let p = tempfile::tempdir()?;
+        let eng = Engine::open(LocalEnv::open(p.path()).await?).await?;
+        for value in [b"123", b"456"] {
+            eng.create_tenant("t1").await?;
+            let tenant = eng.tenant("t1").await?;
+            tenant.create_bucket("b1").await?;
+            let bucket1 = tenant.bucket("b1").await?;
+            let mut bulk_load = tenant.begin_bulkload().await?;
+            let mut t1 = bulk_load.new_sst_builder(&bucket1).await?;
+            t1.put(b"k1", 1, value).await?;
+            bulk_load.finish_sst_builder(t1).await?;
+            bulk_load.commit().await?;
+
+            let stats = eng.cache_stats().await?;
+            let block_hits = stats.block_cache.unwrap().hits;
+            for _ in 0..3 {
+                assert_eq!(bucket1.get(b"k1").await?.unwrap(), value);
+            }
+            let stats = eng.cache_stats().await?;
+            assert_eq!(stats.table_cache.unwrap().usage, 1);
+            assert_eq!(stats.block_cache.unwrap().hits, block_hits + 2);
+
+            // The files of a tenant created again have the same names, which
+            // must not hit the cached tables.
+            eng.delete_tenant("t1").await?;
+        }
+        Ok(())
+    }
 }
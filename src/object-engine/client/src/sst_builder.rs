@@ -56,6 +56,12 @@ impl SstBuilder {
         self.add(id, ts, ValueType::Delete, &[]).await
     }
 
+    /// Adds a merge operand, which is applied to the older value of the id by
+    /// the merge operator of the bucket.
+    pub async fn merge(&mut self, id: &[u8], ts: Timestamp, operand: &[u8]) -> Result<()> {
+        self.add(id, ts, ValueType::Merge, operand).await
+    }
+
     pub fn estimated_size(&self) -> usize {
         self.table_builder.estimated_size()
     }
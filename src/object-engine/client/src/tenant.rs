@@ -61,6 +61,45 @@ impl<E: Env> Tenant<E> {
         self.bucket(name).await
     }
 
+    pub async fn list_buckets(&self) -> Result<Vec<BucketDesc>> {
+        let req = ListBucketsRequest {
+            tenant: self.name().to_owned(),
+        };
+        let req = request_union::Request::ListBuckets(req);
+        let res = self.env.handle_union(req).await?;
+        if let response_union::Response::ListBuckets(res) = res {
+            Ok(res.descs)
+        } else {
+            Err(Error::internal("missing list buckets response"))
+        }
+    }
+
+    pub async fn update_bucket(&self, name: &str, options: BucketOptions) -> Result<BucketDesc> {
+        let req = UpdateBucketRequest {
+            tenant: self.name().to_owned(),
+            bucket: name.to_owned(),
+            options: Some(options),
+        };
+        let req = request_union::Request::UpdateBucket(req);
+        let res = self.env.handle_union(req).await?;
+        let desc = if let response_union::Response::UpdateBucket(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing bucket descriptor"))
+    }
+
+    pub async fn delete_bucket(&self, name: &str) -> Result<()> {
+        let req = DeleteBucketRequest {
+            tenant: self.name().to_owned(),
+            bucket: name.to_owned(),
+        };
+        let req = request_union::Request::DeleteBucket(req);
+        self.env.handle_union(req).await?;
+        Ok(())
+    }
+
     pub async fn begin_bulkload(&self) -> Result<BulkLoad<E>> {
         let req = BeginBulkLoadRequest {
             tenant: self.name().to_owned(),
@@ -36,6 +36,12 @@ impl crate::Bucket for Bucket {
         Ok(Box::new(FileLister::new(dir)))
     }
 
+    async fn file_size(&self, name: &str) -> Result<usize> {
+        let path = self.path.join(name);
+        let metadata = tokio::fs::metadata(&path).await?;
+        Ok(metadata.len() as usize)
+    }
+
     async fn new_random_reader(&self, name: &str) -> Result<Box<dyn crate::RandomRead>> {
         let path = self.path.join(name);
         let file = tokio::fs::File::open(&path).await?;
@@ -60,6 +66,12 @@ impl crate::Bucket for Bucket {
             .await?;
         Ok(Box::new(SequentialWriter { file }))
     }
+
+    async fn delete_file(&self, name: &str) -> Result<()> {
+        let path = self.path.join(name);
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
 }
 
 pub struct RandomReader {
@@ -26,7 +26,7 @@ pub trait Store: Send + Sync {
 }
 
 #[async_trait]
-pub trait Lister {
+pub trait Lister: Send {
     type Item;
 
     /// Returns the next `n` items.
@@ -48,11 +48,15 @@ pub trait Tenant: Send + Sync {
 pub trait Bucket: Send + Sync {
     async fn list_files(&self) -> Result<Box<dyn Lister<Item = FileDesc>>>;
 
+    async fn file_size(&self, name: &str) -> Result<usize>;
+
     async fn new_random_reader(&self, name: &str) -> Result<Box<dyn RandomRead>>;
 
     async fn new_sequential_reader(&self, name: &str) -> Result<Box<dyn SequentialRead>>;
 
     async fn new_sequential_writer(&self, name: &str) -> Result<Box<dyn SequentialWrite>>;
+
+    async fn delete_file(&self, name: &str) -> Result<()>;
 }
 
 pub struct FileDesc {
@@ -0,0 +1,303 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    hash::{BuildHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use object_engine_filestore::Store as FileStore;
+
+use crate::{Result, TableReader};
+
+const NUM_SHARDS: usize = 16;
+
+/// The statistics of a cache.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// The total charge of the cached entries.
+    pub usage: usize,
+    pub capacity: usize,
+}
+
+/// A sharded LRU cache, whose entries are evicted once the total charge
+/// exceeds the capacity.
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    hasher: RandomState,
+    shards: Vec<Mutex<Shard<K, V>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        let shard_capacity = (capacity + NUM_SHARDS - 1) / NUM_SHARDS;
+        let shards = (0..NUM_SHARDS)
+            .map(|_| Mutex::new(Shard::new(shard_capacity)))
+            .collect();
+        Self {
+            capacity,
+            hasher: RandomState::new(),
+            shards,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let value = self.shard(key).lock().unwrap().get(key);
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub fn insert(&self, key: K, value: V, charge: usize) {
+        self.shard(&key).lock().unwrap().insert(key, value, charge);
+    }
+
+    /// Removes the entries whose keys don't satisfy the predicate.
+    pub fn retain(&self, f: impl Fn(&K) -> bool) {
+        for shard in &self.shards {
+            shard.lock().unwrap().retain(&f);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let usage = self.shards.iter().map(|s| s.lock().unwrap().usage).sum();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            usage,
+            capacity: self.capacity,
+        }
+    }
+
+    fn shard(&self, key: &K) -> &Mutex<Shard<K, V>> {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % NUM_SHARDS]
+    }
+}
+
+struct Shard<K, V> {
+    capacity: usize,
+    usage: usize,
+    next_tick: u64,
+    entries: HashMap<K, Entry<V>>,
+    // Orders the keys from the least recently used by the ticks of their
+    // last accesses.
+    lru: BTreeMap<u64, K>,
+}
+
+struct Entry<V> {
+    value: V,
+    charge: usize,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Shard<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            usage: 0,
+            next_tick: 0,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.tick);
+        entry.tick = self.next_tick;
+        self.next_tick += 1;
+        self.lru.insert(entry.tick, key.clone());
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, charge: usize) {
+        self.remove(&key);
+        if charge > self.capacity {
+            return;
+        }
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        self.lru.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                charge,
+                tick,
+            },
+        );
+        self.usage += charge;
+        while self.usage > self.capacity {
+            let (_, key) = self.lru.pop_first().unwrap();
+            let entry = self.entries.remove(&key).unwrap();
+            self.usage -= entry.charge;
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+            self.usage -= entry.charge;
+        }
+    }
+
+    fn retain(&mut self, f: impl Fn(&K) -> bool) {
+        let keys: Vec<_> = self.entries.keys().filter(|k| !f(k)).cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+}
+
+/// Identifies a table file in the store.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileKey {
+    pub tenant: String,
+    pub bucket: String,
+    pub name: String,
+}
+
+/// Caches blocks by their files and offsets, charged by their sizes.
+pub(crate) type BlockCache = LruCache<(Arc<FileKey>, usize), Arc<[u8]>>;
+
+/// Caches opened tables, which read data blocks through a shared block cache.
+pub struct TableCache {
+    store: Arc<dyn FileStore>,
+    tables: LruCache<FileKey, Arc<TableReader>>,
+    blocks: Arc<BlockCache>,
+}
+
+impl TableCache {
+    /// Creates a cache of at most `table_cache_size` tables and blocks of
+    /// `block_cache_size` bytes in total.
+    pub fn new(
+        store: Arc<dyn FileStore>,
+        table_cache_size: usize,
+        block_cache_size: usize,
+    ) -> Self {
+        Self {
+            store,
+            tables: LruCache::new(table_cache_size),
+            blocks: Arc::new(LruCache::new(block_cache_size)),
+        }
+    }
+
+    pub async fn open(
+        &self,
+        tenant: &str,
+        bucket: &str,
+        name: &str,
+        file_size: usize,
+    ) -> Result<Arc<TableReader>> {
+        let key = FileKey {
+            tenant: tenant.to_owned(),
+            bucket: bucket.to_owned(),
+            name: name.to_owned(),
+        };
+        if let Some(table) = self.tables.get(&key) {
+            return Ok(table);
+        }
+        let reader = self
+            .store
+            .tenant(tenant)
+            .bucket(bucket)
+            .new_random_reader(name)
+            .await?;
+        let mut table = TableReader::open(name, reader.into(), file_size).await?;
+        table.set_block_cache(self.blocks.clone(), Arc::new(key.clone()));
+        let table = Arc::new(table);
+        self.tables.insert(key, table.clone(), 1);
+        Ok(table)
+    }
+
+    /// Evicts the tables and blocks of the files that match the predicate.
+    ///
+    /// Files must be evicted once they are deleted, since a tenant created
+    /// again reuses the file names.
+    pub fn evict(&self, f: impl Fn(&FileKey) -> bool) {
+        self.tables.retain(|key| !f(key));
+        self.blocks.retain(|(key, _)| !f(key));
+    }
+
+    pub fn table_stats(&self) -> CacheStats {
+        self.tables.stats()
+    }
+
+    pub fn block_stats(&self) -> CacheStats {
+        self.blocks.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache() {
+        let cache = LruCache::new(NUM_SHARDS * 2);
+        for i in 0..1000 {
+            cache.insert(i, i, 1);
+        }
+        let stats = cache.stats();
+        assert!(stats.usage <= stats.capacity);
+        assert_eq!(cache.get(&999), Some(999));
+
+        // Entries larger than a shard are not cached.
+        cache.insert(1000, 1000, 3);
+        assert_eq!(cache.get(&1000), None);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+
+        cache.retain(|&k| k != 999);
+        assert_eq!(cache.get(&999), None);
+    }
+
+    #[test]
+    fn test_lru_shard() {
+        let mut shard = Shard::new(3);
+        shard.insert("a", 1, 1);
+        shard.insert("b", 2, 1);
+        shard.insert("c", 3, 1);
+        // Touches "a", so that "b" is the least recently used.
+        assert_eq!(shard.get(&"a"), Some(1));
+        shard.insert("d", 4, 1);
+        assert_eq!(shard.get(&"b"), None);
+        assert_eq!(shard.get(&"a"), Some(1));
+        assert_eq!(shard.usage, 3);
+
+        shard.insert("e", 5, 2);
+        assert_eq!(shard.get(&"c"), None);
+        assert_eq!(shard.get(&"d"), None);
+        assert_eq!(shard.usage, 3);
+        // Replaces the entry with a new charge.
+        shard.insert("e", 6, 1);
+        assert_eq!(shard.get(&"e"), Some(6));
+        assert_eq!(shard.usage, 2);
+    }
+}
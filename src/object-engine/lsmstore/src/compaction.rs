@@ -0,0 +1,521 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use crate::{
+    versions::{BucketVersion, FileMetadata, NUM_LEVELS},
+    Key, MergeOperator, Result, Timestamp, ValueType,
+};
+
+/// Options to decide when and how to compact a bucket.
+#[derive(Clone, Debug)]
+pub struct CompactionOptions {
+    /// The number of files in level 0 to trigger a compaction.
+    ///
+    /// Default: 4
+    pub l0_compaction_trigger: usize,
+
+    /// The target size of level 1 in bytes.
+    ///
+    /// Default: 64MB
+    pub level1_target_size: u64,
+
+    /// The ratio of the target size of a level to that of the level above.
+    ///
+    /// Default: 10
+    pub level_size_multiplier: u64,
+
+    /// The target size of compaction outputs in bytes.
+    ///
+    /// Default: 8MB
+    pub target_file_size: u64,
+}
+
+impl Default for CompactionOptions {
+    fn default() -> Self {
+        Self {
+            l0_compaction_trigger: 4,
+            level1_target_size: 64 << 20,
+            level_size_multiplier: 10,
+            target_file_size: 8 << 20,
+        }
+    }
+}
+
+impl CompactionOptions {
+    fn level_target_size(&self, level: usize) -> u64 {
+        let mut size = self.level1_target_size;
+        for _ in 1..level {
+            size = size.saturating_mul(self.level_size_multiplier);
+        }
+        size
+    }
+}
+
+pub(crate) struct Compaction {
+    pub output_level: u32,
+    // Groups of input files ordered from the newest to the oldest. Files in
+    // the same group don't overlap.
+    pub inputs: Vec<Vec<FileMetadata>>,
+    // Whether no file other than the inputs overlaps the inputs.
+    //
+    // Reads order versions by timestamps instead of levels, and bulk loads
+    // may put older versions in upper levels, so files in every level are
+    // checked.
+    pub bottommost: bool,
+}
+
+impl Compaction {
+    pub fn input_files(&self) -> impl Iterator<Item = &FileMetadata> {
+        self.inputs.iter().flatten()
+    }
+}
+
+/// Picks the level with the highest score to compact, where the score of a
+/// level is how many times it exceeds its target.
+pub(crate) fn pick(version: &BucketVersion, options: &CompactionOptions) -> Option<Compaction> {
+    let mut best_level = 0;
+    let mut best_score = version.l0_level.len() as f64 / options.l0_compaction_trigger as f64;
+    // The last level is never compacted.
+    for level in 1..(NUM_LEVELS - 1) {
+        let size: u64 = level_files(version, level).map(|f| f.file_size).sum();
+        let score = size as f64 / options.level_target_size(level) as f64;
+        if score > best_score {
+            best_level = level;
+            best_score = score;
+        }
+    }
+    if best_score < 1.0 {
+        return None;
+    }
+
+    let mut inputs = if best_level == 0 {
+        // Level 0 files overlap each other, so all of them are compacted.
+        version
+            .l0_level
+            .iter()
+            .rev()
+            .map(|f| vec![f.clone()])
+            .collect()
+    } else {
+        // Compacts the largest file, which frees the most space.
+        let file = level_files(version, best_level)
+            .max_by_key(|f| f.file_size)
+            .unwrap();
+        vec![vec![file.clone()]]
+    };
+    let (lower, upper) = id_range(inputs.iter().flatten());
+    let output_level = best_level + 1;
+    let overlapped: Vec<_> = level_files(version, output_level)
+        .filter(|f| overlaps(f, &lower, &upper))
+        .cloned()
+        .collect();
+    let (lower, upper) = id_range(inputs.iter().flatten().chain(&overlapped));
+    if !overlapped.is_empty() {
+        inputs.push(overlapped);
+    }
+    let names: HashSet<&str> = inputs.iter().flatten().map(|f| f.name.as_str()).collect();
+    let bottommost = version
+        .l0_level
+        .iter()
+        .chain((1..NUM_LEVELS).flat_map(|level| level_files(version, level)))
+        .filter(|f| !names.contains(f.name.as_str()))
+        .all(|f| !overlaps(f, &lower, &upper));
+    Some(Compaction {
+        output_level: output_level as u32,
+        inputs,
+        bottommost,
+    })
+}
+
+fn level_files(version: &BucketVersion, level: usize) -> impl Iterator<Item = &FileMetadata> {
+    version.non_l0_levels[level - 1].files.iter().map(|f| &f.0)
+}
+
+// Returns the smallest and the largest ids in the files.
+//
+// Files are compared by ids instead of keys, so that all versions of an id
+// are compacted together.
+fn id_range<'a>(files: impl Iterator<Item = &'a FileMetadata>) -> (Vec<u8>, Vec<u8>) {
+    let mut lower: Option<&[u8]> = None;
+    let mut upper: Option<&[u8]> = None;
+    for f in files {
+        let l = Key::from(f.lower_bound.as_slice()).id();
+        let u = Key::from(f.upper_bound.as_slice()).id();
+        if lower.map_or(true, |lower| l < lower) {
+            lower = Some(l);
+        }
+        if upper.map_or(true, |upper| u > upper) {
+            upper = Some(u);
+        }
+    }
+    (
+        lower.unwrap_or_default().to_owned(),
+        upper.unwrap_or_default().to_owned(),
+    )
+}
+
+fn overlaps(file: &FileMetadata, lower: &[u8], upper: &[u8]) -> bool {
+    Key::from(file.lower_bound.as_slice()).id() <= upper
+        && Key::from(file.upper_bound.as_slice()).id() >= lower
+}
+
+/// Decides which entries to keep in a compaction.
+///
+/// Entries must be given in the order of keys. All entries newer than the
+/// oldest snapshot are kept, since some snapshot may read them. Of the older
+/// ones, only the newest entry that is not a merge operand is visible, which
+/// shadows the rest.
+pub(crate) struct EntryFilter {
+    oldest_snapshot: Timestamp,
+    bottommost: bool,
+    last_id: Option<Vec<u8>>,
+    shadowed: bool,
+}
+
+impl EntryFilter {
+    pub fn new(oldest_snapshot: Timestamp, bottommost: bool) -> Self {
+        Self {
+            oldest_snapshot,
+            bottommost,
+            last_id: None,
+            shadowed: false,
+        }
+    }
+
+    pub fn keep(&mut self, key: Key<'_>) -> bool {
+        if self.last_id.as_deref() != Some(key.id()) {
+            self.last_id = Some(key.id().to_owned());
+            self.shadowed = false;
+        }
+        if self.shadowed {
+            return false;
+        }
+        if key.ts() > self.oldest_snapshot {
+            return true;
+        }
+        match key.tp() {
+            // Merge operands are applied to the older entries.
+            ValueType::Merge => true,
+            ValueType::Put => {
+                self.shadowed = true;
+                true
+            }
+            // A tombstone is useless if there is nothing below to delete.
+            ValueType::Delete => {
+                self.shadowed = true;
+                !self.bottommost
+            }
+        }
+    }
+}
+
+/// Folds the merge operands that no snapshot can tell apart in a compaction.
+///
+/// Entries must be given in the order of keys, after the `EntryFilter`.
+/// Operands older than the oldest snapshot are applied to the entry below
+/// them, which makes a put at the timestamp of the newest operand. If there
+/// is no entry below, the operands are merged without a base value.
+///
+/// Operands are only folded in bottommost compactions. Otherwise, files out
+/// of the compaction may hold versions of the same id between the operands,
+/// which must be applied in the order of timestamps.
+pub(crate) struct MergeFolder<'a> {
+    operator: Option<&'a dyn MergeOperator>,
+    oldest_snapshot: Timestamp,
+    id: Vec<u8>,
+    // Pending operands of `id` from the newest to the oldest.
+    operands: Vec<(Timestamp, Vec<u8>)>,
+}
+
+/// An entry to output, with an encoded key.
+pub(crate) type Entry = (Vec<u8>, Vec<u8>);
+
+impl<'a> MergeFolder<'a> {
+    pub fn new(
+        operator: Option<&'a dyn MergeOperator>,
+        oldest_snapshot: Timestamp,
+        bottommost: bool,
+    ) -> Self {
+        Self {
+            operator: operator.filter(|_| bottommost),
+            oldest_snapshot,
+            id: Vec::new(),
+            operands: Vec::new(),
+        }
+    }
+
+    /// Adds an entry, and appends the entries ready to output to `entries`.
+    pub fn add(&mut self, key: Key<'_>, value: &[u8], entries: &mut Vec<Entry>) -> Result<()> {
+        if key.id() != self.id {
+            self.flush(entries)?;
+        }
+        let operator = match self.operator {
+            Some(operator) if key.ts() <= self.oldest_snapshot => operator,
+            _ => {
+                entries.push((key.to_vec(), value.to_owned()));
+                return Ok(());
+            }
+        };
+        match key.tp() {
+            ValueType::Merge => {
+                if self.operands.is_empty() {
+                    self.id = key.id().to_owned();
+                }
+                self.operands.push((key.ts(), value.to_owned()));
+            }
+            ValueType::Put | ValueType::Delete if !self.operands.is_empty() => {
+                // The put replaces the entry below, which it shadows anyway.
+                let base = match key.tp() {
+                    ValueType::Put => Some(value),
+                    _ => None,
+                };
+                entries.push(self.full_merge(operator, base)?);
+            }
+            _ => entries.push((key.to_vec(), value.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// Appends the rest of the entries to output to `entries`.
+    pub fn finish(&mut self, entries: &mut Vec<Entry>) -> Result<()> {
+        self.flush(entries)
+    }
+
+    // Outputs the pending operands, which have no entry below them.
+    fn flush(&mut self, entries: &mut Vec<Entry>) -> Result<()> {
+        let operator = match self.operator {
+            Some(operator) if !self.operands.is_empty() => operator,
+            _ => return Ok(()),
+        };
+        entries.push(self.full_merge(operator, None)?);
+        Ok(())
+    }
+
+    fn full_merge(&mut self, operator: &dyn MergeOperator, base: Option<&[u8]>) -> Result<Entry> {
+        let operands = std::mem::take(&mut self.operands);
+        let (ts, _) = operands[0];
+        let values: Vec<&[u8]> = operands.iter().rev().map(|(_, v)| v.as_slice()).collect();
+        let value = operator.full_merge(&self.id, base, &values)?;
+        Ok((Key::encode_to_vec(&self.id, ts, ValueType::Put), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::versions::OrdByUpperBound;
+
+    fn file(name: &str, level: u32, lower: &[u8], upper: &[u8], file_size: u64) -> FileMetadata {
+        FileMetadata {
+            name: name.to_owned(),
+            level,
+            lower_bound: Key::encode_to_vec(lower, 1, ValueType::Put),
+            upper_bound: Key::encode_to_vec(upper, 1, ValueType::Put),
+            file_size,
+            ..Default::default()
+        }
+    }
+
+    fn version(files: Vec<FileMetadata>) -> BucketVersion {
+        let mut version = BucketVersion::default();
+        for f in files {
+            if f.level == 0 {
+                version.l0_level.push(f);
+            } else {
+                version.non_l0_levels[f.level as usize - 1]
+                    .files
+                    .insert(OrdByUpperBound(f));
+            }
+        }
+        version
+    }
+
+    fn names(files: &[FileMetadata]) -> Vec<&str> {
+        files.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pick_level0() {
+        let options = CompactionOptions {
+            l0_compaction_trigger: 2,
+            ..Default::default()
+        };
+        let mut files = vec![
+            file("1", 0, b"b", b"d", 1),
+            file("3", 1, b"a", b"a", 1),
+            file("4", 1, b"c", b"e", 1),
+            file("5", 2, b"f", b"g", 1),
+        ];
+        assert!(pick(&version(files.clone()), &options).is_none());
+
+        files.push(file("2", 0, b"d", b"e", 1));
+        let c = pick(&version(files.clone()), &options).unwrap();
+        assert_eq!(c.output_level, 1);
+        assert_eq!(c.inputs.len(), 3);
+        assert_eq!(names(&c.inputs[0]), vec!["2"]);
+        assert_eq!(names(&c.inputs[1]), vec!["1"]);
+        assert_eq!(names(&c.inputs[2]), vec!["4"]);
+        assert!(c.bottommost);
+
+        files.push(file("6", 2, b"e", b"e", 1));
+        let c = pick(&version(files), &options).unwrap();
+        assert!(!c.bottommost);
+    }
+
+    #[test]
+    fn test_pick_level1() {
+        let options = CompactionOptions {
+            level1_target_size: 10,
+            ..Default::default()
+        };
+        let files = vec![
+            file("1", 1, b"a", b"b", 4),
+            file("2", 1, b"c", b"d", 8),
+            file("3", 2, b"a", b"a", 1),
+            file("4", 2, b"b", b"c", 1),
+            file("5", 2, b"d", b"f", 1),
+        ];
+        let c = pick(&version(files), &options).unwrap();
+        assert_eq!(c.output_level, 2);
+        assert_eq!(names(&c.inputs[0]), vec!["2"]);
+        assert_eq!(names(&c.inputs[1]), vec!["4", "5"]);
+        // File 1 is not an input, but it may hold versions of "b".
+        assert!(!c.bottommost);
+
+        let files = vec![
+            file("1", 1, b"a", b"a", 4),
+            file("2", 1, b"c", b"d", 8),
+            file("3", 2, b"c", b"c", 1),
+        ];
+        let c = pick(&version(files.clone()), &options).unwrap();
+        assert!(c.bottommost);
+
+        // Files in upper levels are checked too.
+        let mut files = files;
+        files.push(file("4", 0, b"d", b"e", 1));
+        let c = pick(&version(files), &options).unwrap();
+        assert_eq!(c.output_level, 2);
+        assert!(!c.bottommost);
+    }
+
+    #[test]
+    fn test_entry_filter() {
+        let entries = [
+            (b"a", 9, ValueType::Put, true),
+            (b"a", 5, ValueType::Put, true),
+            (b"a", 3, ValueType::Put, false),
+            (b"b", 4, ValueType::Merge, true),
+            (b"b", 3, ValueType::Delete, true),
+            (b"b", 2, ValueType::Put, false),
+            (b"c", 5, ValueType::Delete, true),
+            (b"c", 4, ValueType::Put, false),
+        ];
+        let mut filter = EntryFilter::new(5, false);
+        for (id, ts, tp, keep) in entries {
+            let key = Key::encode_to_vec(id, ts, tp);
+            assert_eq!(filter.keep(key.as_slice().into()), keep);
+        }
+
+        let mut filter = EntryFilter::new(5, true);
+        let key = Key::encode_to_vec(b"a", 6, ValueType::Delete);
+        assert!(filter.keep(key.as_slice().into()));
+        let key = Key::encode_to_vec(b"a", 5, ValueType::Delete);
+        assert!(!filter.keep(key.as_slice().into()));
+        let key = Key::encode_to_vec(b"a", 4, ValueType::Put);
+        assert!(!filter.keep(key.as_slice().into()));
+    }
+
+    fn fold(bottommost: bool, entries: &[(&[u8], Timestamp, ValueType, &[u8])]) -> Vec<Entry> {
+        let operator = crate::AppendOperator;
+        let mut filter = EntryFilter::new(5, bottommost);
+        let mut folder = MergeFolder::new(Some(&operator), 5, bottommost);
+        let mut outputs = Vec::new();
+        for &(id, ts, tp, value) in entries {
+            let key = Key::encode_to_vec(id, ts, tp);
+            if filter.keep(key.as_slice().into()) {
+                folder
+                    .add(key.as_slice().into(), value, &mut outputs)
+                    .unwrap();
+            }
+        }
+        folder.finish(&mut outputs).unwrap();
+        outputs
+    }
+
+    fn entry(id: &[u8], ts: Timestamp, tp: ValueType, value: &[u8]) -> Entry {
+        (Key::encode_to_vec(id, ts, tp), value.to_owned())
+    }
+
+    #[test]
+    fn test_merge_folder() {
+        let entries: [(&[u8], _, _, &[u8]); 9] = [
+            (b"a", 7, ValueType::Merge, b"4"),
+            (b"a", 5, ValueType::Merge, b"3"),
+            (b"a", 4, ValueType::Merge, b"2"),
+            (b"a", 3, ValueType::Put, b"1"),
+            (b"a", 2, ValueType::Put, b"0"),
+            (b"b", 4, ValueType::Merge, b"2"),
+            (b"b", 3, ValueType::Delete, b""),
+            (b"c", 4, ValueType::Merge, b"2"),
+            (b"c", 3, ValueType::Merge, b"1"),
+        ];
+        // Operands are kept unless the compaction is bottommost.
+        assert_eq!(
+            fold(false, &entries),
+            vec![
+                entry(b"a", 7, ValueType::Merge, b"4"),
+                entry(b"a", 5, ValueType::Merge, b"3"),
+                entry(b"a", 4, ValueType::Merge, b"2"),
+                entry(b"a", 3, ValueType::Put, b"1"),
+                entry(b"b", 4, ValueType::Merge, b"2"),
+                entry(b"b", 3, ValueType::Delete, b""),
+                entry(b"c", 4, ValueType::Merge, b"2"),
+                entry(b"c", 3, ValueType::Merge, b"1"),
+            ]
+        );
+        assert_eq!(
+            fold(true, &entries),
+            vec![
+                entry(b"a", 7, ValueType::Merge, b"4"),
+                entry(b"a", 5, ValueType::Put, b"123"),
+                entry(b"b", 4, ValueType::Put, b"2"),
+                entry(b"c", 4, ValueType::Put, b"12"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_folder_not_bottommost() {
+        // Invalid operands are kept, since they are not merged.
+        let operator = crate::I64AddOperator;
+        let mut folder = MergeFolder::new(Some(&operator), 5, false);
+        let mut outputs = Vec::new();
+        for (ts, value) in [(4, b"x".to_vec()), (3, 1i64.to_le_bytes().to_vec())] {
+            let key = Key::encode_to_vec(b"a", ts, ValueType::Merge);
+            folder
+                .add(key.as_slice().into(), &value, &mut outputs)
+                .unwrap();
+        }
+        folder.finish(&mut outputs).unwrap();
+        assert_eq!(
+            outputs,
+            vec![
+                entry(b"a", 4, ValueType::Merge, b"x"),
+                entry(b"a", 3, ValueType::Merge, &1i64.to_le_bytes()),
+            ]
+        );
+    }
+}
@@ -14,14 +14,19 @@
 
 use std::sync::Arc;
 
-use object_engine_filestore::Store as FileStore;
-
-use crate::{iterator::ManifestIter, versions::FileMetadata, *};
+use crate::{
+    cache::TableCache,
+    iterator::ManifestIter,
+    versions::{FileMetadata, Version},
+    *,
+};
 
 pub struct LevelIter {
     tenant: String,
     bucket: String,
-    local_store: Arc<dyn FileStore>,
+    tables: Arc<TableCache>,
+    // Keeps the files from being deleted while they are read.
+    _version: Version,
 
     manifest_file_iter: ManifestIter,
     current_file: Option<FileMetadata>,
@@ -34,20 +39,21 @@ impl LevelIter {
     pub async fn new(
         tenant: &str,
         bucket: &str,
+        version: Version,
         manifest_file_iter: ManifestIter,
-        local_store: Arc<dyn FileStore>,
+        tables: Arc<TableCache>,
     ) -> Result<Self> {
-        let mut iter = Self {
+        // Tables are opened by the first seek.
+        Ok(Self {
             tenant: tenant.to_owned(),
             bucket: bucket.to_owned(),
+            _version: version,
             manifest_file_iter,
             current_file: None,
             current_iter: None,
-            local_store,
+            tables,
             init: false,
-        };
-        iter.seek_to_first().await?;
-        Ok(iter)
+        })
     }
 
     pub fn key(&self) -> Option<Key<'_>> {
@@ -68,72 +74,67 @@ impl LevelIter {
     }
 
     pub async fn seek_to_first(&mut self) -> Result<()> {
+        self.init = true;
         self.manifest_file_iter.seek_to_first();
-        if !self.manifest_file_iter.valid() {
+        if !self.set_current_tbl_iter().await? {
             return Ok(());
         }
-        self.current_file = Some(self.manifest_file_iter.value());
-        let file_metadata = self.current_file.as_ref().unwrap();
-        self.current_iter = Some(
-            self.open_table_iter(&file_metadata.name, file_metadata.file_size as usize)
-                .await?,
-        );
         self.current_iter.as_mut().unwrap().seek_to_first().await?;
-        if !self.init {
-            self.init = true;
-        }
         Ok(())
     }
 
     pub async fn seek(&mut self, target: Key<'_>) -> Result<()> {
+        self.init = true;
+        // The file with the smallest upper bound not less than the target is
+        // the only one that might contain the target.
         self.manifest_file_iter.seek(target);
-        if !self.manifest_file_iter.valid() {
+        if !self.set_current_tbl_iter().await? {
             return Ok(());
         }
-        self.set_current_tbl_iter().await?;
         self.current_iter.as_mut().unwrap().seek(target).await?;
-        if !self.init {
-            self.init = true;
-        }
         Ok(())
     }
 
     pub async fn next(&mut self) -> Result<()> {
         if !self.init {
-            self.manifest_file_iter.seek_to_first();
-            if !self.manifest_file_iter.valid() {
-                return Ok(());
-            }
-            self.set_current_tbl_iter().await?;
-            self.current_iter.as_mut().unwrap().seek_to_first().await?;
-        } else if !self.current_iter.as_ref().unwrap().valid() {
-            self.manifest_file_iter.next();
-            if !self.manifest_file_iter.valid() {
-                return Ok(());
-            }
-            self.set_current_tbl_iter().await?;
-            self.current_iter.as_mut().unwrap().seek_to_first().await?;
-        } else {
-            self.current_iter.as_mut().unwrap().next().await?;
+            return self.seek_to_first().await;
         }
+        let current_iter = match self.current_iter.as_mut() {
+            Some(iter) => iter,
+            None => return Ok(()),
+        };
+        current_iter.next().await?;
+        if current_iter.valid() {
+            return Ok(());
+        }
+        // Moves to the next file once the current one is exhausted.
+        self.manifest_file_iter.next();
+        if !self.set_current_tbl_iter().await? {
+            return Ok(());
+        }
+        self.current_iter.as_mut().unwrap().seek_to_first().await?;
         Ok(())
     }
 
-    async fn set_current_tbl_iter(&mut self) -> Result<()> {
+    // Opens the file at the manifest iterator, or clears the current file if
+    // the manifest iterator is exhausted. Returns whether a file is opened.
+    async fn set_current_tbl_iter(&mut self) -> Result<bool> {
+        if !self.manifest_file_iter.valid() {
+            self.current_file = None;
+            self.current_iter = None;
+            return Ok(false);
+        }
         self.current_file = Some(self.manifest_file_iter.value());
         let f = self.current_file.as_ref().unwrap();
         self.current_iter = Some(self.open_table_iter(&f.name, f.file_size as usize).await?);
-        Ok(())
+        Ok(true)
     }
 
     async fn open_table_iter(&self, file: &str, file_size: usize) -> Result<TableIter> {
-        let r = self
-            .local_store
-            .tenant(&self.tenant)
-            .bucket(&self.bucket)
-            .new_random_reader(file)
+        let table = self
+            .tables
+            .open(&self.tenant, &self.bucket, file, file_size)
             .await?;
-        let tr = TableReader::open(r.into(), file_size).await?;
-        Ok(tr.iter())
+        Ok(table.iter())
     }
 }
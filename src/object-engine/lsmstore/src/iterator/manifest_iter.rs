@@ -27,12 +27,12 @@ pub struct ManifestIter {
 
 impl ManifestIter {
     pub fn new(files: BTreeSet<OrdByUpperBound>) -> Self {
-        let mut last_upper_bound = None;
+        let mut last_upper_bound: Option<&[u8]> = None;
         for f in &files {
             if let Some(last) = last_upper_bound {
-                assert!(f.lower_bound > last)
+                assert!(Key::from(f.lower_bound.as_slice()) > Key::from(last))
             }
-            last_upper_bound = Some(f.upper_bound.to_owned());
+            last_upper_bound = Some(f.upper_bound.as_slice());
         }
         Self {
             files,
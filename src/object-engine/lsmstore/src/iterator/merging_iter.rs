@@ -173,7 +173,11 @@ pub struct HeapItem {
 
 impl Ord for HeapItem {
     fn cmp(&self, other: &Self) -> Ordering {
-        Key::from(self.key.as_slice()).cmp(&Key::from(other.key.as_slice()))
+        // Levels are ordered from the newest to the oldest, so the newer one
+        // comes first if the keys are equal.
+        Key::from(self.key.as_slice())
+            .cmp(&Key::from(other.key.as_slice()))
+            .then(self.index.cmp(&other.index))
     }
 }
 
@@ -185,6 +189,6 @@ impl PartialOrd for HeapItem {
 
 impl PartialEq for HeapItem {
     fn eq(&self, other: &Self) -> bool {
-        self.key == other.key
+        self.key == other.key && self.index == other.index
     }
 }
@@ -15,5 +15,9 @@
 mod level_iter;
 mod manifest_iter;
 mod merging_iter;
+mod snapshot_iter;
 
-pub use self::{level_iter::LevelIter, manifest_iter::ManifestIter, merging_iter::MergingIterator};
+pub use self::{
+    level_iter::LevelIter, manifest_iter::ManifestIter, merging_iter::MergingIterator,
+    snapshot_iter::SnapshotIter,
+};
@@ -0,0 +1,145 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use crate::{
+    iterator::{LevelIter, MergingIterator},
+    Error, Key, MergeOperator, Result, Timestamp, ValueType,
+};
+
+/// Iterates over the ids and their values that are visible at a snapshot.
+///
+/// Merge operands are applied to the older values by the merge operator, and
+/// deleted ids are skipped.
+pub struct SnapshotIter {
+    iter: MergingIterator,
+    snapshot_ts: Timestamp,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    // The current id and its value.
+    current: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SnapshotIter {
+    /// Creates an iterator over the levels, which are ordered from the newest
+    /// to the oldest.
+    pub(crate) fn new(
+        levels: Vec<LevelIter>,
+        snapshot_ts: Timestamp,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+    ) -> Self {
+        Self {
+            iter: MergingIterator::new(levels, Some(snapshot_ts)),
+            snapshot_ts,
+            merge_operator,
+            current: None,
+        }
+    }
+
+    pub fn id(&self) -> &[u8] {
+        debug_assert!(self.valid());
+        &self.current.as_ref().unwrap().0
+    }
+
+    pub fn value(&self) -> &[u8] {
+        debug_assert!(self.valid());
+        &self.current.as_ref().unwrap().1
+    }
+
+    pub fn valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub async fn seek_to_first(&mut self) -> Result<()> {
+        self.iter.seek_to_first().await?;
+        self.read_next_visible().await
+    }
+
+    /// Seeks to the first id that is not less than the target.
+    pub async fn seek(&mut self, target: &[u8]) -> Result<()> {
+        self.seek_to_newest_visible(target).await?;
+        self.read_next_visible().await
+    }
+
+    pub async fn next(&mut self) -> Result<()> {
+        // The merging iterator is already at the next id.
+        self.read_next_visible().await
+    }
+
+    /// Returns the value of the id, and leaves the iterator invalid.
+    pub(crate) async fn get(&mut self, id: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.current = None;
+        self.seek_to_newest_visible(id).await?;
+        if !self.iter.valid() || self.iter.key().id() != id {
+            return Ok(None);
+        }
+        self.read_value().await
+    }
+
+    // Seeks to the newest version of the id that is visible at the snapshot,
+    // or to the next id if there is none.
+    async fn seek_to_newest_visible(&mut self, id: &[u8]) -> Result<()> {
+        // Versions of an id are ordered from the newest to the oldest.
+        let key = Key::encode_to_vec(id, self.snapshot_ts, ValueType::Put);
+        self.iter.seek(key.as_slice().into()).await
+    }
+
+    // Reads the value of the next id that is not deleted, and leaves the
+    // merging iterator at the id after it.
+    async fn read_next_visible(&mut self) -> Result<()> {
+        self.current = None;
+        while self.iter.valid() {
+            let id = self.iter.key().id().to_owned();
+            let value = self.read_value().await?;
+            // Skips the versions shadowed by the put or the tombstone.
+            while self.iter.valid() && self.iter.key().id() == id {
+                self.iter.next().await?;
+            }
+            if let Some(value) = value {
+                self.current = Some((id, value));
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the versions of the id at the merging iterator, from the newest
+    // to the oldest, until a put or a tombstone. Merge operands on the way
+    // are applied to the value of the put, if any.
+    async fn read_value(&mut self) -> Result<Option<Vec<u8>>> {
+        let id = self.iter.key().id().to_owned();
+        let mut operands = Vec::new();
+        let mut base = None;
+        while self.iter.valid() && self.iter.key().id() == id {
+            match self.iter.key().tp() {
+                ValueType::Put => {
+                    base = Some(self.iter.value().to_owned());
+                    break;
+                }
+                ValueType::Delete => break,
+                ValueType::Merge => operands.push(self.iter.value().to_owned()),
+            }
+            self.iter.next().await?;
+        }
+        if operands.is_empty() {
+            return Ok(base);
+        }
+        let merge_operator = self.merge_operator.as_ref().ok_or_else(|| {
+            Error::invalid_argument(format!("{:?} has merge operands but no merge operator", id))
+        })?;
+        operands.reverse();
+        let operands: Vec<_> = operands.iter().map(Vec::as_slice).collect();
+        let value = merge_operator.full_merge(&id, base.as_deref(), &operands)?;
+        Ok(Some(value))
+    }
+}
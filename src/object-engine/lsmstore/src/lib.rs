@@ -14,7 +14,10 @@
 
 #![feature(map_first_last)]
 
+mod cache;
+mod compaction;
 mod iterator;
+mod merge;
 mod store;
 mod table;
 mod versions;
@@ -22,11 +25,14 @@ mod versions;
 use object_engine_common::{Error, Result};
 
 pub use self::{
-    iterator::MergingIterator,
-    store::{Bucket, Store, Tenant},
+    cache::CacheStats,
+    compaction::CompactionOptions,
+    iterator::{MergingIterator, SnapshotIter},
+    merge::{AppendOperator, I64AddOperator, MergeOperator, MergeOperators},
+    store::{Bucket, Store, StoreOptions, Tenant},
     table::{
-        Key, TableBuilder, TableBuilderOptions, TableDesc, TableIter, TableReader, Timestamp,
-        ValueType,
+        CompressionType, Key, TableBuilder, TableBuilderOptions, TableDesc, TableIter, TableReader,
+        Timestamp, ValueType,
     },
     versions::{
         Bucket as VersionEditBucket, File as VersionEditFile, VersionEditBuilder, VersionSet,
@@ -0,0 +1,152 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{Error, Result};
+
+/// Combines the merge operands of an id with its value.
+///
+/// Operands are written with `ValueType::Merge`, and folded onto the older
+/// value of the id by reads and compactions.
+pub trait MergeOperator: Send + Sync {
+    /// The name that buckets refer to this operator by.
+    fn name(&self) -> &str;
+
+    /// Applies the operands, from the oldest to the newest, to the base
+    /// value, which is `None` if the id has no value.
+    fn full_merge(&self, id: &[u8], base: Option<&[u8]>, operands: &[&[u8]]) -> Result<Vec<u8>>;
+
+    /// Combines two operands into one that has the same effect, or returns
+    /// `None` if they can't be combined without the base value.
+    fn partial_merge(&self, id: &[u8], older: &[u8], newer: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Adds up little-endian i64 values, with missing values treated as zero.
+pub struct I64AddOperator;
+
+impl I64AddOperator {
+    fn decode(id: &[u8], value: &[u8]) -> Result<i64> {
+        let bytes = value
+            .try_into()
+            .map_err(|_| Error::invalid_argument(format!("{:?} has an invalid i64 value", id)))?;
+        Ok(i64::from_le_bytes(bytes))
+    }
+}
+
+impl MergeOperator for I64AddOperator {
+    fn name(&self) -> &str {
+        "i64_add"
+    }
+
+    fn full_merge(&self, id: &[u8], base: Option<&[u8]>, operands: &[&[u8]]) -> Result<Vec<u8>> {
+        let mut sum = match base {
+            Some(base) => Self::decode(id, base)?,
+            None => 0,
+        };
+        for operand in operands {
+            sum = sum.wrapping_add(Self::decode(id, operand)?);
+        }
+        Ok(sum.to_le_bytes().to_vec())
+    }
+
+    fn partial_merge(&self, id: &[u8], older: &[u8], newer: &[u8]) -> Option<Vec<u8>> {
+        let sum = Self::decode(id, older)
+            .ok()?
+            .wrapping_add(Self::decode(id, newer).ok()?);
+        Some(sum.to_le_bytes().to_vec())
+    }
+}
+
+/// Appends the operands to the value, with a missing value treated as empty.
+pub struct AppendOperator;
+
+impl MergeOperator for AppendOperator {
+    fn name(&self) -> &str {
+        "append"
+    }
+
+    fn full_merge(&self, _: &[u8], base: Option<&[u8]>, operands: &[&[u8]]) -> Result<Vec<u8>> {
+        let mut value = base.unwrap_or_default().to_owned();
+        for operand in operands {
+            value.extend_from_slice(operand);
+        }
+        Ok(value)
+    }
+
+    fn partial_merge(&self, _: &[u8], older: &[u8], newer: &[u8]) -> Option<Vec<u8>> {
+        Some([older, newer].concat())
+    }
+}
+
+/// Merge operators by their names, which include the built-in ones.
+#[derive(Clone)]
+pub struct MergeOperators {
+    operators: HashMap<String, Arc<dyn MergeOperator>>,
+}
+
+impl MergeOperators {
+    /// Registers the operator, which replaces the one with the same name.
+    pub fn register(&mut self, operator: Arc<dyn MergeOperator>) {
+        self.operators.insert(operator.name().to_owned(), operator);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn MergeOperator>> {
+        self.operators.get(name).cloned()
+    }
+}
+
+impl Default for MergeOperators {
+    fn default() -> Self {
+        let mut operators = Self {
+            operators: HashMap::new(),
+        };
+        operators.register(Arc::new(I64AddOperator));
+        operators.register(Arc::new(AppendOperator));
+        operators
+    }
+}
+
+impl fmt::Debug for MergeOperators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.operators.keys().collect();
+        names.sort();
+        f.debug_list().entries(names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i64_add() -> Result<()> {
+        let op = I64AddOperator;
+        let v = |x: i64| x.to_le_bytes().to_vec();
+        assert_eq!(op.full_merge(b"a", None, &[&v(1), &v(2)])?, v(3));
+        assert_eq!(op.full_merge(b"a", Some(&v(10)), &[&v(-1)])?, v(9));
+        assert_eq!(op.partial_merge(b"a", &v(1), &v(2)), Some(v(3)));
+        assert!(op.full_merge(b"a", Some(b"x"), &[]).is_err());
+        assert_eq!(op.partial_merge(b"a", b"x", &v(2)), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append() -> Result<()> {
+        let op = AppendOperator;
+        assert_eq!(op.full_merge(b"a", None, &[b"1", b"2"])?, b"12");
+        assert_eq!(op.full_merge(b"a", Some(b"0"), &[b"1"])?, b"01");
+        assert_eq!(op.partial_merge(b"a", b"1", b"2"), Some(b"12".to_vec()));
+        Ok(())
+    }
+}
@@ -13,21 +13,42 @@
 // limitations under the License.
 
 use std::{
-    collections::{hash_map, BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use object_engine_filestore::{SequentialWrite, Store as FileStore};
-use tokio::sync::Mutex;
+use tokio::{fs, sync::Mutex};
 
 use crate::{
-    iterator::{LevelIter, ManifestIter, MergingIterator},
-    versions::{proto::*, BucketVersion, OrdByUpperBound, VersionSet},
-    Error, Key, Result, TableReader, ValueType, VersionEditBuilder, VersionEditFile,
+    cache::{CacheStats, TableCache},
+    compaction::{self, EntryFilter, MergeFolder},
+    iterator::{LevelIter, ManifestIter, MergingIterator, SnapshotIter},
+    versions::{proto::*, OrdByUpperBound, Version, VersionSet},
+    CompactionOptions, Error, Key, MergeOperator, Result, TableBuilder, TableBuilderOptions,
+    TableDesc, TableReader, Timestamp, ValueType, VersionEditBuilder, VersionEditFile,
 };
 
+const LIST_BATCH_SIZE: usize = 256;
+
+pub struct StoreOptions {
+    /// The number of tables to keep open, or 0 to disable the table cache.
+    pub table_cache_size: usize,
+    /// The capacity of the block cache in bytes, or 0 to disable it.
+    pub block_cache_size: usize,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            table_cache_size: 1000,
+            block_cache_size: 64 << 20,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Store {
     inner: Arc<Mutex<Inner>>,
@@ -38,6 +59,8 @@ struct Inner {
     version_sets: HashMap<String, VersionSet>, // tenant -> VersionSet
     local_store: Option<Arc<dyn FileStore>>,
     external_store: Arc<dyn FileStore>,
+    // Reads tables from the local store if any, and shared by all tenants.
+    table_cache: Arc<TableCache>,
 }
 
 impl Store {
@@ -45,26 +68,38 @@ impl Store {
         base_dir: impl Into<PathBuf>,
         local_store: Option<Arc<dyn FileStore>>,
         external_store: Arc<dyn FileStore>,
+        options: StoreOptions,
     ) -> Result<Self> {
+        let base_dir = base_dir.into();
+        let version_sets = recover_version_sets(&base_dir).await?;
+        let table_cache = TableCache::new(
+            local_store
+                .clone()
+                .unwrap_or_else(|| external_store.clone()),
+            options.table_cache_size,
+            options.block_cache_size,
+        );
         let inner = Inner {
-            base_dir: base_dir.into(),
-            version_sets: HashMap::new(),
+            base_dir,
+            version_sets,
             local_store,
             external_store,
+            table_cache: Arc::new(table_cache),
         };
         Ok(Self {
             inner: Arc::new(Mutex::new(inner)),
         })
     }
 
+    pub async fn tenants(&self) -> Vec<String> {
+        let inner = self.inner.lock().await;
+        inner.version_sets.keys().cloned().collect()
+    }
+
     pub async fn tenant(&self, name: &str) -> Result<Tenant> {
-        let mut inner = self.inner.lock().await;
-        let base_dir = inner.base_dir.to_owned();
-        match inner.version_sets.entry(name.to_owned()) {
-            hash_map::Entry::Occupied(_) => {}
-            hash_map::Entry::Vacant(ent) => {
-                ent.insert(VersionSet::open(base_dir.join(name)).await?);
-            }
+        let inner = self.inner.lock().await;
+        if !inner.version_sets.contains_key(name) {
+            return Err(Error::NotFound(format!("tenant {}", name)));
         }
         Ok(Tenant {
             tenant: name.to_owned(),
@@ -72,10 +107,39 @@ impl Store {
         })
     }
 
-    pub async fn create_tenant(&self, _name: &str) -> Result<()> {
-        // TODO: save tenant info in some place.
+    pub async fn create_tenant(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        if inner.version_sets.contains_key(name) {
+            return Err(Error::AlreadyExists(format!("tenant {}", name)));
+        }
+        // The version set is created at last, so that a tenant is recovered
+        // only if it has been created completely.
+        inner.external_store.create_tenant(name).await?;
+        let version_set = VersionSet::open(inner.base_dir.join(name)).await?;
+        inner.version_sets.insert(name.to_owned(), version_set);
+        Ok(())
+    }
+
+    pub async fn delete_tenant(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner.version_sets.remove(name);
+        inner.table_cache.evict(|f| f.tenant == name);
+        // The manifest files live in the tenant directory of the external
+        // store, so they are removed along with the tenant data.
+        inner.external_store.delete_tenant(name).await?;
+        if let Some(local_store) = &inner.local_store {
+            ignore_not_found(local_store.delete_tenant(name).await)?;
+        }
         Ok(())
     }
+
+    pub async fn table_cache_stats(&self) -> CacheStats {
+        self.inner.lock().await.table_cache.table_stats()
+    }
+
+    pub async fn block_cache_stats(&self) -> CacheStats {
+        self.inner.lock().await.table_cache.block_stats()
+    }
 }
 
 pub struct Tenant {
@@ -92,7 +156,7 @@ impl Tenant {
         })
     }
 
-    pub async fn create_bucket(&self, name: &str) -> Result<()> {
+    pub async fn create_bucket(&self, name: &str, options: Vec<u8>) -> Result<()> {
         let inner = self.inner.lock().await;
 
         inner
@@ -110,6 +174,7 @@ impl Tenant {
                 VersionEditBuilder::default()
                     .add_buckets(vec![version_edit::Bucket {
                         name: name.to_owned(),
+                        options,
                     }])
                     .build(),
             )
@@ -117,30 +182,122 @@ impl Tenant {
         Ok(())
     }
 
+    pub async fn update_bucket(&self, name: &str, options: Vec<u8>) -> Result<()> {
+        let inner = self.inner.lock().await;
+        let version_tenant = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        // Makes sure that the bucket exists, since adding it again only
+        // updates the options.
+        version_tenant
+            .current_version()
+            .await
+            .bucket_version(name)
+            .await?;
+        version_tenant
+            .log_and_apply(
+                VersionEditBuilder::default()
+                    .add_buckets(vec![version_edit::Bucket {
+                        name: name.to_owned(),
+                        options,
+                    }])
+                    .build(),
+            )
+            .await
+    }
+
+    pub async fn buckets(&self) -> Result<Vec<String>> {
+        let inner = self.inner.lock().await;
+        let version_tenant = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        Ok(version_tenant.current_version().await.bucket_names().await)
+    }
+
+    pub async fn delete_bucket(&self, name: &str) -> Result<()> {
+        let inner = self.inner.lock().await;
+
+        let version_tenant = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        version_tenant
+            .log_and_apply(
+                VersionEditBuilder::default()
+                    .remove_buckets(vec![name.to_owned()])
+                    .build(),
+            )
+            .await?;
+        inner
+            .table_cache
+            .evict(|f| f.tenant == self.tenant && f.bucket == name);
+
+        inner
+            .external_store
+            .tenant(&self.tenant)
+            .delete_bucket(name)
+            .await?;
+        if let Some(local_store) = &inner.local_store {
+            ignore_not_found(local_store.tenant(&self.tenant).delete_bucket(name).await)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the options set when the tenant was updated.
+    pub async fn options(&self) -> Result<Vec<u8>> {
+        let inner = self.inner.lock().await;
+        let version_tenant = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        Ok(version_tenant
+            .current_version()
+            .await
+            .tenant_options()
+            .await)
+    }
+
+    pub async fn update_options(&self, options: Vec<u8>) -> Result<()> {
+        let inner = self.inner.lock().await;
+        let version_tenant = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        version_tenant
+            .log_and_apply(
+                VersionEditBuilder::default()
+                    .set_tenant_options(options)
+                    .build(),
+            )
+            .await
+    }
+
     pub async fn add_files(&self, files: Vec<VersionEditFile>) -> Result<()> {
         let inner = self.inner.lock().await;
 
         let ve = VersionEditBuilder::default()
             .add_files(files.to_owned())
             .build();
-        let tenant = inner.version_sets.get(&self.tenant).unwrap();
-        tenant.log_and_apply(ve).await?;
-
-        if inner.local_store.is_some() {
-            for f in &files {
-                let bucket = inner
-                    .local_store
-                    .as_ref()
-                    .unwrap()
-                    .tenant(&self.tenant)
-                    .bucket(&f.bucket);
+        inner.install_files(&self.tenant, ve, &files).await
+    }
 
-                let r = bucket.new_sequential_reader(&f.name).await?;
-                let w = bucket.new_sequential_writer(&f.name).await?;
-                object_engine_filestore::copy_all(r, w).await?;
-            }
+    /// Deletes the files that have been removed from all live versions.
+    ///
+    /// Obsolete files are tracked in memory, so the ones left before a restart
+    /// are not deleted here.
+    pub async fn delete_obsolete_files(&self) -> Result<()> {
+        let inner = self.inner.lock().await;
+        let vs = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        for (bucket, name) in vs.deletable_files().await {
+            // The file might have been deleted with its bucket.
+            ignore_not_found(inner.delete_file(&self.tenant, &bucket, &name).await)?;
+            vs.forget_obsolete_file(&bucket, &name).await;
         }
-
         Ok(())
     }
 
@@ -163,39 +320,55 @@ pub struct Bucket {
 }
 
 impl Bucket {
-    pub async fn get(&self, id: &[u8]) -> Result<Option<Vec<u8>>> {
+    /// Returns the value of the id that is visible at `snapshot_ts`, with
+    /// merge operands applied by `merge_operator`.
+    pub async fn get(
+        &self,
+        id: &[u8],
+        snapshot_ts: Timestamp,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+    ) -> Result<Option<Vec<u8>>> {
         let inner = self.inner.lock().await;
         let vs = inner
             .version_sets
             .get(&self.tenant)
             .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
-        let current = vs
-            .current_version()
-            .await
-            .bucket_version(&self.bucket)
-            .await?;
+        let current = vs.current_version().await;
         inner
-            .get(current, &self.tenant, &self.bucket, id, u64::MAX)
+            .get(
+                current,
+                &self.tenant,
+                &self.bucket,
+                id,
+                snapshot_ts,
+                merge_operator,
+            )
             .await
     }
 
-    pub async fn iter(&self) -> Result<MergingIterator> {
+    /// Returns an iterator over the values that are visible at `snapshot_ts`,
+    /// with merge operands applied by `merge_operator`.
+    pub async fn iter(
+        &self,
+        snapshot_ts: Timestamp,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+    ) -> Result<SnapshotIter> {
         let inner = self.inner.lock().await;
         let vs = inner
             .version_sets
             .get(&self.tenant)
             .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
-        let current = vs
-            .current_version()
-            .await
-            .bucket_version(&self.bucket)
-            .await?;
-
-        let merge_iter = inner
-            .get_merge_iter(&self.tenant, &self.bucket, current)
-            .await?;
+        let current = vs.current_version().await;
 
-        Ok(merge_iter)
+        inner
+            .iter(
+                &self.tenant,
+                &self.bucket,
+                current,
+                snapshot_ts,
+                merge_operator,
+            )
+            .await
     }
 
     pub async fn new_sequential_writer(&self, name: &str) -> Result<Box<dyn SequentialWrite>> {
@@ -207,108 +380,697 @@ impl Bucket {
             .new_sequential_writer(name)
             .await
     }
+
+    /// Returns the names of the files in the external store.
+    pub async fn list_files(&self) -> Result<Vec<String>> {
+        let inner = self.inner.lock().await;
+        let mut lister = inner
+            .external_store
+            .tenant(&self.tenant)
+            .bucket(&self.bucket)
+            .list_files()
+            .await?;
+        let mut names = Vec::new();
+        loop {
+            let files = lister.next(LIST_BATCH_SIZE).await?;
+            if files.is_empty() {
+                break;
+            }
+            names.extend(files.into_iter().map(|f| f.name));
+        }
+        Ok(names)
+    }
+
+    /// Returns the options set when the bucket was created or updated.
+    pub async fn options(&self) -> Result<Vec<u8>> {
+        let inner = self.inner.lock().await;
+        let vs = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        let bucket = vs
+            .current_version()
+            .await
+            .bucket_version(&self.bucket)
+            .await?;
+        Ok(bucket.options)
+    }
+
+    /// Returns the names of the files referenced by live versions.
+    pub async fn live_files(&self) -> Result<HashSet<String>> {
+        let inner = self.inner.lock().await;
+        let vs = inner
+            .version_sets
+            .get(&self.tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+        Ok(vs.live_files(&self.bucket).await)
+    }
+
+    /// Reads the descriptor of a table in the external store.
+    pub async fn table_desc(&self, name: &str) -> Result<TableDesc> {
+        let inner = self.inner.lock().await;
+        let bucket = inner
+            .external_store
+            .tenant(&self.tenant)
+            .bucket(&self.bucket);
+        let table_size = bucket.file_size(name).await?;
+        let reader = bucket.new_random_reader(name).await?;
+        let table = TableReader::open(name, reader.into(), table_size).await?;
+        table.desc().await
+    }
+
+    /// Compacts the level that exceeds its target the most, if any.
+    ///
+    /// Versions that are invisible to snapshots since `oldest_snapshot` are
+    /// dropped, and merge operands below it are folded with `merge_operator`.
+    /// Returns false if there is nothing to compact.
+    pub async fn compact(
+        &self,
+        options: &CompactionOptions,
+        table_options: &TableBuilderOptions,
+        merge_operator: Option<&dyn MergeOperator>,
+        oldest_snapshot: Timestamp,
+    ) -> Result<bool> {
+        // Doesn't hold the lock during the compaction, which might take long.
+        let (vs, tables, external_store) = {
+            let inner = self.inner.lock().await;
+            let vs = inner
+                .version_sets
+                .get(&self.tenant)
+                .cloned()
+                .ok_or_else(|| Error::NotFound(format!("tenant {}", &self.tenant)))?;
+            (vs, inner.table_cache.clone(), inner.external_store.clone())
+        };
+        let version = vs.current_version().await;
+        let current = version.bucket_version(&self.bucket).await?;
+        let compaction = match compaction::pick(&current, options) {
+            Some(compaction) => compaction,
+            None => return Ok(false),
+        };
+
+        let mut level_iters = Vec::new();
+        for files in &compaction.inputs {
+            let files = files.iter().cloned().map(OrdByUpperBound).collect();
+            let iter = LevelIter::new(
+                &self.tenant,
+                &self.bucket,
+                version.clone(),
+                ManifestIter::new(files),
+                tables.clone(),
+            )
+            .await?;
+            level_iters.push(iter);
+        }
+        let mut iter = MergingIterator::new(level_iters, None);
+        iter.seek_to_first().await?;
+
+        let bucket = external_store.tenant(&self.tenant).bucket(&self.bucket);
+        let mut filter = EntryFilter::new(oldest_snapshot, compaction.bottommost);
+        let mut folder = MergeFolder::new(merge_operator, oldest_snapshot, compaction.bottommost);
+        let mut entries = Vec::new();
+        let mut outputs = Vec::new();
+        let mut output: Option<(String, TableBuilder)> = None;
+        let mut last_id = Vec::new();
+        let mut done = false;
+        while !done {
+            entries.clear();
+            if iter.valid() {
+                let key = iter.key();
+                if filter.keep(key) {
+                    folder.add(key, iter.value(), &mut entries)?;
+                }
+                iter.next().await?;
+            } else {
+                folder.finish(&mut entries)?;
+                done = true;
+            }
+            for (key, value) in &entries {
+                let key = Key::from(key.as_slice());
+                // Splits outputs only between ids, so that all versions of an
+                // id stay in the same file.
+                if let Some((_, builder)) = &output {
+                    if builder.estimated_size() as u64 >= options.target_file_size
+                        && key.id() != last_id
+                    {
+                        let (name, builder) = output.take().unwrap();
+                        outputs.push((name, builder.finish().await?));
+                    }
+                }
+                if output.is_none() {
+                    let num = vs.get_next_file_num(1).await?[0];
+                    let name = format!("{:0>6}.sst", num);
+                    let writer = bucket.new_sequential_writer(&name).await?;
+                    let builder = TableBuilder::new(writer, table_options.clone());
+                    output = Some((name, builder));
+                }
+                let (_, builder) = output.as_mut().unwrap();
+                builder.add(key, value).await?;
+                last_id = key.id().to_owned();
+            }
+        }
+        if let Some((name, builder)) = output {
+            outputs.push((name, builder.finish().await?));
+        }
+
+        let removed = compaction
+            .input_files()
+            .map(|f| version_edit::FileId {
+                bucket: self.bucket.to_owned(),
+                range_id: 0,
+                name: f.name.to_owned(),
+            })
+            .collect();
+        let added: Vec<_> = outputs
+            .into_iter()
+            .map(|(name, desc)| VersionEditFile {
+                tenant: self.tenant.to_owned(),
+                bucket: self.bucket.to_owned(),
+                range_id: 0,
+                name,
+                level: compaction.output_level,
+                lower_bound: desc.lower_bound,
+                upper_bound: desc.upper_bound,
+                file_size: desc.table_size as u64,
+            })
+            .collect();
+        let ve = VersionEditBuilder::default()
+            .remove_files(removed)
+            .add_files(added.clone())
+            .build();
+        let inner = self.inner.lock().await;
+        inner.install_files(&self.tenant, ve, &added).await?;
+        Ok(true)
+    }
+
+    pub async fn delete_file(&self, name: &str) -> Result<()> {
+        let inner = self.inner.lock().await;
+        inner.delete_file(&self.tenant, &self.bucket, name).await
+    }
 }
 
 impl Inner {
-    fn get_store(&self) -> Arc<dyn FileStore> {
-        if self.local_store.is_none() {
-            self.external_store.clone()
-        } else {
-            self.local_store.as_ref().unwrap().clone()
+    // Applies an edit that adds the files, and copies them to the local store.
+    async fn install_files(
+        &self,
+        tenant: &str,
+        ve: VersionEdit,
+        files: &[VersionEditFile],
+    ) -> Result<()> {
+        let vs = self
+            .version_sets
+            .get(tenant)
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", tenant)))?;
+        vs.log_and_apply(ve).await?;
+
+        if let Some(local_store) = &self.local_store {
+            for f in files {
+                let bucket = local_store.tenant(tenant).bucket(&f.bucket);
+                let r = bucket.new_sequential_reader(&f.name).await?;
+                let w = bucket.new_sequential_writer(&f.name).await?;
+                object_engine_filestore::copy_all(r, w).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_file(&self, tenant: &str, bucket: &str, name: &str) -> Result<()> {
+        self.table_cache
+            .evict(|f| f.tenant == tenant && f.bucket == bucket && f.name == name);
+        self.external_store
+            .tenant(tenant)
+            .bucket(bucket)
+            .delete_file(name)
+            .await?;
+        if let Some(local_store) = &self.local_store {
+            let bucket = local_store.tenant(tenant).bucket(bucket);
+            ignore_not_found(bucket.delete_file(name).await)?;
         }
+        Ok(())
     }
 
     async fn get(
         &self,
-        ver: BucketVersion,
+        version: Version,
         tenant: &str,
         bucket: &str,
         id: &[u8],
-        snapshot_ts: u64,
+        snapshot_ts: Timestamp,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
     ) -> Result<Option<Vec<u8>>> {
-        let key = Key::encode_to_vec(id, u64::MAX, ValueType::Put);
-        for l in ver.l0_level.iter().rev() {
-            let r = self
-                .get_store()
-                .tenant(tenant)
-                .bucket(bucket)
-                .new_random_reader(&l.name)
-                .await?;
-
-            let mut iter = TableReader::open(r.into(), l.file_size as usize)
-                .await?
-                .iter();
-            iter.seek(key.as_slice().into()).await?;
-            while iter.valid() {
-                let k = iter.key();
-                if k.id() != id {
-                    break;
-                }
-                if k.ts() > snapshot_ts {
-                    continue;
-                }
-                match k.tp() {
-                    ValueType::Put => return Ok(Some(iter.value().to_owned())),
-                    ValueType::Delete => return Ok(None),
-                    ValueType::Merge => todo!(),
-                }
+        let ver = version.bucket_version(bucket).await?;
+        let key = Key::encode_to_vec(id, snapshot_ts, ValueType::Put);
+        // Only reads the files that might contain the id.
+        let mut files: Vec<_> = ver.l0_level.iter().rev().cloned().collect();
+        for l in ver.non_l0_levels {
+            // Compactions don't split the versions of an id into different
+            // files in a level, so only one file needs to be read.
+            let mut level_files = l.iter();
+            level_files.seek(key.as_slice().into());
+            if level_files.valid() {
+                files.push(level_files.value());
             }
         }
-
-        for l in ver.non_l0_levels {
-            let level_file = l.iter();
-            let local_store = self.get_store();
-            let mut iter = LevelIter::new(tenant, bucket, level_file, local_store).await?;
-            iter.seek(key.as_slice().into()).await?;
-            while iter.valid() {
-                let k = iter.key();
-                if k.is_none() {
-                    break;
-                }
-                let k = k.unwrap();
-                if k.id() != key {
-                    break;
-                }
-                if k.ts() > snapshot_ts {
-                    continue;
-                }
-                match k.tp() {
-                    ValueType::Put => return Ok(Some(iter.value().to_owned())),
-                    ValueType::Delete => return Ok(None),
-                    ValueType::Merge => todo!(),
-                }
+        let mut level_iters = Vec::new();
+        for f in files {
+            let table = self
+                .table_cache
+                .open(tenant, bucket, &f.name, f.file_size as usize)
+                .await?;
+            if !table.may_contain(id) {
+                continue;
             }
+            let iter = LevelIter::new(
+                tenant,
+                bucket,
+                version.clone(),
+                ManifestIter::new([OrdByUpperBound(f)].into()),
+                self.table_cache.clone(),
+            )
+            .await?;
+            level_iters.push(iter);
         }
-
-        Ok(None)
+        let mut iter = SnapshotIter::new(level_iters, snapshot_ts, merge_operator);
+        iter.get(id).await
     }
 
-    async fn get_merge_iter(
+    async fn iter(
         &self,
         tenant: &str,
         bucket: &str,
-        version: BucketVersion,
-    ) -> Result<MergingIterator> {
+        version: Version,
+        snapshot_ts: Timestamp,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+    ) -> Result<SnapshotIter> {
+        let bucket_version = version.bucket_version(bucket).await?;
         let mut level_iters = Vec::new();
 
-        for l0_file in version.l0_level.iter().rev() {
+        for l0_file in bucket_version.l0_level.iter().rev() {
             let mut fs = BTreeSet::new();
             fs.insert(OrdByUpperBound(l0_file.deref().to_owned()));
             let manifest_file_iter = ManifestIter::new(fs);
-            let local_store = self.get_store();
-            let merge_iter =
-                LevelIter::new(tenant, bucket, manifest_file_iter, local_store).await?;
+            let merge_iter = LevelIter::new(
+                tenant,
+                bucket,
+                version.clone(),
+                manifest_file_iter,
+                self.table_cache.clone(),
+            )
+            .await?;
             level_iters.push(merge_iter);
         }
 
-        for level_files in version.non_l0_levels {
+        for level_files in bucket_version.non_l0_levels {
             let manifest_file_iter = level_files.iter();
-            let local_store = self.get_store();
-            let merge_iter =
-                LevelIter::new(tenant, bucket, manifest_file_iter, local_store).await?;
+            let merge_iter = LevelIter::new(
+                tenant,
+                bucket,
+                version.clone(),
+                manifest_file_iter,
+                self.table_cache.clone(),
+            )
+            .await?;
             level_iters.push(merge_iter);
         }
 
-        Ok(MergingIterator::new(level_iters, None))
+        Ok(SnapshotIter::new(level_iters, snapshot_ts, merge_operator))
+    }
+}
+
+async fn recover_version_sets(base_dir: &Path) -> Result<HashMap<String, VersionSet>> {
+    let mut version_sets = HashMap::new();
+    let mut dir = match fs::read_dir(base_dir).await {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(version_sets),
+        Err(err) => return Err(err.into()),
+    };
+    while let Some(ent) = dir.next_entry().await? {
+        let path = ent.path();
+        if !VersionSet::exists(&path) {
+            continue;
+        }
+        let name = ent
+            .file_name()
+            .into_string()
+            .map_err(|s| Error::Corrupted(format!("invalid tenant name {:?}", s)))?;
+        version_sets.insert(name, VersionSet::open(path).await?);
+    }
+    Ok(version_sets)
+}
+
+// Files are copied to the local store only when they are added, so the
+// directories to delete might not exist there.
+fn ignore_not_found(res: Result<()>) -> Result<()> {
+    match res {
+        Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use object_engine_filestore::FsFileStore;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{versions::NUM_LEVELS, AppendOperator};
+
+    async fn write_file(
+        tenant: &Tenant,
+        bucket: &Bucket,
+        level: u32,
+        entries: &[(&[u8], Timestamp, &[u8])],
+    ) -> Result<()> {
+        let name = format!("{}.sst", tenant.get_next_file_nums(1).await?[0]);
+        let writer = bucket.new_sequential_writer(&name).await?;
+        let mut builder = TableBuilder::new(writer, TableBuilderOptions::default());
+        for (id, ts, value) in entries {
+            let key = Key::encode_to_vec(id, *ts, ValueType::Put);
+            builder.add(key.as_slice().into(), value).await?;
+        }
+        let desc = builder.finish().await?;
+        let file = VersionEditFile {
+            tenant: "t1".to_owned(),
+            bucket: "b1".to_owned(),
+            range_id: 0,
+            name,
+            level,
+            lower_bound: desc.lower_bound,
+            upper_bound: desc.upper_bound,
+            file_size: desc.table_size as u64,
+        };
+        tenant.add_files(vec![file]).await
+    }
+
+    #[tokio::test]
+    async fn test_compact_same_upper_bound() -> Result<()> {
+        let tmp = tempdir::TempDir::new("test_store")?;
+        let file_store = FsFileStore::open(tmp.path().join("files")).await?;
+        let store = Store::new(
+            tmp.path().join("versions"),
+            None,
+            Arc::new(file_store),
+            StoreOptions::default(),
+        )
+        .await?;
+        store.create_tenant("t1").await?;
+        let tenant = store.tenant("t1").await?;
+        tenant.create_bucket("b1", Vec::new()).await?;
+        let bucket = tenant.bucket("b1").await?;
+
+        // The file in level 1 lies within the range of the file in level 2,
+        // so the output has the same upper bound as the file in level 2.
+        write_file(&tenant, &bucket, 2, &[(b"k1", 1, b"1"), (b"k5", 1, b"5")]).await?;
+        write_file(&tenant, &bucket, 1, &[(b"k3", 2, b"3")]).await?;
+
+        let options = CompactionOptions {
+            level1_target_size: 1,
+            ..Default::default()
+        };
+        let table_options = TableBuilderOptions::default();
+        assert!(
+            bucket
+                .compact(&options, &table_options, None, Timestamp::MAX)
+                .await?
+        );
+        let current = store
+            .inner
+            .lock()
+            .await
+            .version_sets
+            .get("t1")
+            .unwrap()
+            .current_version()
+            .await
+            .bucket_version("b1")
+            .await?;
+        assert!(current.non_l0_levels[0].files.is_empty());
+        assert_eq!(current.non_l0_levels[1].files.len(), 1);
+        assert_eq!(
+            bucket.get(b"k1", Timestamp::MAX, None).await?.unwrap(),
+            b"1"
+        );
+        assert_eq!(
+            bucket.get(b"k3", Timestamp::MAX, None).await?.unwrap(),
+            b"3"
+        );
+        assert_eq!(
+            bucket.get(b"k5", Timestamp::MAX, None).await?.unwrap(),
+            b"5"
+        );
+        Ok(())
+    }
+
+    enum Op {
+        Put(Vec<u8>),
+        Delete,
+        Merge(Vec<u8>),
+    }
+
+    type Entry = (Timestamp, ValueType, Vec<u8>);
+
+    // The versions of each id, from the newest to the oldest.
+    #[derive(Default)]
+    struct Model {
+        ids: BTreeMap<Vec<u8>, Vec<(Timestamp, Op)>>,
+    }
+
+    impl Model {
+        fn get(&self, id: &[u8], snapshot_ts: Timestamp) -> Option<Vec<u8>> {
+            let mut base = None;
+            let mut operands = Vec::new();
+            for (ts, op) in self.ids.get(id)? {
+                if *ts > snapshot_ts {
+                    continue;
+                }
+                match op {
+                    Op::Put(value) => {
+                        base = Some(value.as_slice());
+                        break;
+                    }
+                    Op::Delete => break,
+                    Op::Merge(value) => operands.push(value.as_slice()),
+                }
+            }
+            if base.is_none() && operands.is_empty() {
+                return None;
+            }
+            let mut value = base.unwrap_or_default().to_owned();
+            for operand in operands.iter().rev() {
+                value.extend_from_slice(operand);
+            }
+            Some(value)
+        }
+
+        fn scan(&self, target: &[u8], snapshot_ts: Timestamp) -> Vec<(Vec<u8>, Vec<u8>)> {
+            self.ids
+                .keys()
+                .filter(|id| id.as_slice() >= target)
+                .filter_map(|id| self.get(id, snapshot_ts).map(|v| (id.clone(), v)))
+                .collect()
+        }
+    }
+
+    async fn scan(
+        bucket: &Bucket,
+        target: &[u8],
+        snapshot_ts: Timestamp,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut iter = bucket
+            .iter(snapshot_ts, Some(Arc::new(AppendOperator)))
+            .await?;
+        iter.seek(target).await?;
+        let mut entries = Vec::new();
+        while iter.valid() {
+            entries.push((iter.id().to_owned(), iter.value().to_owned()));
+            iter.next().await?;
+        }
+        Ok(entries)
+    }
+
+    // Writes the entries into files split between random ids.
+    async fn write_files(
+        rng: &mut StdRng,
+        tenant: &Tenant,
+        bucket: &Bucket,
+        level: u32,
+        entries: BTreeMap<Vec<u8>, Vec<Entry>>,
+    ) -> Result<()> {
+        let mut files: Vec<Vec<(Vec<u8>, Vec<u8>)>> = vec![Vec::new()];
+        for (id, versions) in entries {
+            if !files.last().unwrap().is_empty() && level > 0 && rng.gen_bool(0.3) {
+                files.push(Vec::new());
+            }
+            for (ts, tp, value) in versions {
+                let key = Key::encode_to_vec(&id, ts, tp);
+                files.last_mut().unwrap().push((key, value));
+            }
+        }
+        for entries in files {
+            let name = format!("{}.sst", tenant.get_next_file_nums(1).await?[0]);
+            let writer = bucket.new_sequential_writer(&name).await?;
+            let mut builder = TableBuilder::new(writer, TableBuilderOptions::default());
+            for (key, value) in &entries {
+                builder.add(key.as_slice().into(), value).await?;
+            }
+            let desc = builder.finish().await?;
+            let file = VersionEditFile {
+                tenant: "t1".to_owned(),
+                bucket: "b1".to_owned(),
+                range_id: 0,
+                name,
+                level,
+                lower_bound: desc.lower_bound,
+                upper_bound: desc.upper_bound,
+                file_size: desc.table_size as u64,
+            };
+            tenant.add_files(vec![file]).await?;
+        }
+        Ok(())
+    }
+
+    async fn check(
+        rng: &mut StdRng,
+        bucket: &Bucket,
+        model: &Model,
+        max_ts: Timestamp,
+        oldest_snapshot: Timestamp,
+    ) -> Result<()> {
+        for _ in 0..8 {
+            let snapshot_ts = match rng.gen_range(0..4) {
+                0 => Timestamp::MAX,
+                _ => rng.gen_range(oldest_snapshot..=max_ts + 1),
+            };
+            for i in 0..=20 {
+                let id = format!("{:02}", i).into_bytes();
+                let value = bucket
+                    .get(&id, snapshot_ts, Some(Arc::new(AppendOperator)))
+                    .await?;
+                assert_eq!(
+                    value,
+                    model.get(&id, snapshot_ts),
+                    "{:?}@{}",
+                    id,
+                    snapshot_ts
+                );
+            }
+            let target = format!("{:02}", rng.gen_range(0..=20)).into_bytes();
+            for target in [b"".as_slice(), &target] {
+                assert_eq!(
+                    scan(bucket, target, snapshot_ts).await?,
+                    model.scan(target, snapshot_ts)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(seed: u64, ordered: bool) -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let tmp = tempdir::TempDir::new("test_store")?;
+        let file_store = FsFileStore::open(tmp.path().join("files")).await?;
+        let store = Store::new(
+            tmp.path().join("versions"),
+            None,
+            Arc::new(file_store),
+            StoreOptions::default(),
+        )
+        .await?;
+        store.create_tenant("t1").await?;
+        let tenant = store.tenant("t1").await?;
+        tenant.create_bucket("b1", Vec::new()).await?;
+        let bucket = tenant.bucket("b1").await?;
+
+        // Batches of operations from the oldest to the newest. If `ordered`,
+        // they are put into levels from the bottom to the top like flushes,
+        // otherwise into any level like bulk loads with older timestamps.
+        let mut model = Model::default();
+        let mut ts = 0;
+        let mut level = NUM_LEVELS as u32 - 1;
+        let mut level_entries = BTreeMap::new();
+        // Levels other than level 0 are written once, so their files don't
+        // overlap.
+        let mut written = HashSet::new();
+        for _ in 0..rng.gen_range(1..8) {
+            let batch_level = if ordered {
+                rng.gen_range(0..=level)
+            } else {
+                loop {
+                    let l = rng.gen_range(0..NUM_LEVELS as u32);
+                    if l == 0 || l == level || !written.contains(&l) {
+                        break l;
+                    }
+                }
+            };
+            if batch_level != level && !level_entries.is_empty() {
+                let entries = std::mem::take(&mut level_entries);
+                write_files(&mut rng, &tenant, &bucket, level, entries).await?;
+                written.insert(level);
+            }
+            level = batch_level;
+            for _ in 0..rng.gen_range(1..20) {
+                let id = format!("{:02}", rng.gen_range(0..20)).into_bytes();
+                ts += 1;
+                let value = format!("{}", ts).into_bytes();
+                let (tp, op) = match rng.gen_range(0..4) {
+                    0 | 1 => (ValueType::Put, Op::Put(value.clone())),
+                    2 => (ValueType::Delete, Op::Delete),
+                    _ => (ValueType::Merge, Op::Merge(value.clone())),
+                };
+                let value = if tp == ValueType::Delete {
+                    Vec::new()
+                } else {
+                    value
+                };
+                level_entries
+                    .entry(id.clone())
+                    .or_insert_with(Vec::new)
+                    .insert(0, (ts, tp, value));
+                model.ids.entry(id).or_default().insert(0, (ts, op));
+            }
+            if level == 0 {
+                let entries = std::mem::take(&mut level_entries);
+                write_files(&mut rng, &tenant, &bucket, level, entries).await?;
+            }
+        }
+        if !level_entries.is_empty() {
+            write_files(&mut rng, &tenant, &bucket, level, level_entries).await?;
+        }
+        check(&mut rng, &bucket, &model, ts, 0).await?;
+
+        // Compactions keep what is visible to snapshots since the oldest one.
+        let options = CompactionOptions {
+            l0_compaction_trigger: 1,
+            level1_target_size: 1,
+            level_size_multiplier: 2,
+            target_file_size: 128,
+        };
+        let table_options = TableBuilderOptions::default();
+        let oldest_snapshot = rng.gen_range(0..=ts + 1);
+        for _ in 0..100 {
+            let compacted = bucket
+                .compact(
+                    &options,
+                    &table_options,
+                    Some(&AppendOperator),
+                    oldest_snapshot,
+                )
+                .await?;
+            if !compacted {
+                break;
+            }
+        }
+        check(&mut rng, &bucket, &model, ts, oldest_snapshot).await
+    }
+
+    #[tokio::test]
+    async fn test_random_layouts() -> Result<()> {
+        for seed in 0..32 {
+            run(seed, true).await?;
+            run(seed, false).await?;
+        }
+        Ok(())
     }
 }
@@ -20,15 +20,28 @@ use bytes::BufMut;
 //
 // Block = { Entry } Footer
 // Entry = {
-//     key size   : fixed32
-//     value size : fixed32
-//     key        : key size bytes
-//     value      : value size bytes
+//     shared size   : fixed32
+//     unshared size : fixed32
+//     value size    : fixed32
+//     unshared key  : unshared size bytes
+//     value         : value size bytes
 // }
 // Footer = {
 //     restarts     : fixed32 * num_restarts
 //     num_restarts : fixed32
 // }
+//
+// An entry shares a prefix with the key of the previous entry, except for the
+// entries at restart points, which store the full keys.
+//
+// Tables before format version 2 store full keys in all entries:
+//
+// Entry = {
+//     key size   : fixed32
+//     value size : fixed32
+//     key        : key size bytes
+//     value      : value size bytes
+// }
 
 #[allow(dead_code)]
 pub struct BlockBuilder {
@@ -36,6 +49,7 @@ pub struct BlockBuilder {
     restarts: Vec<u32>,
     num_entries: usize,
     restart_interval: usize,
+    last_key: Vec<u8>,
 }
 
 impl Default for BlockBuilder {
@@ -45,6 +59,7 @@ impl Default for BlockBuilder {
             restarts: Vec::new(),
             num_entries: 0,
             restart_interval: 8,
+            last_key: Vec::new(),
         }
     }
 }
@@ -60,18 +75,28 @@ impl BlockBuilder {
         self.buf.clear();
         self.restarts.clear();
         self.num_entries = 0;
+        self.last_key.clear();
     }
 
     pub fn add(&mut self, key: &[u8], value: &[u8]) {
-        if self.num_entries % self.restart_interval == 0 {
+        let shared = if self.num_entries % self.restart_interval == 0 {
             self.restarts.push(self.buf.len() as u32);
-        }
+            0
+        } else {
+            self.last_key
+                .iter()
+                .zip(key)
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
 
-        // TODO: add prefix compression
-        self.buf.put_u32(key.len() as u32);
+        self.buf.put_u32(shared as u32);
+        self.buf.put_u32((key.len() - shared) as u32);
         self.buf.put_u32(value.len() as u32);
-        self.buf.put_slice(key);
+        self.buf.put_slice(&key[shared..]);
         self.buf.put_slice(value);
+        self.last_key.truncate(shared);
+        self.last_key.extend_from_slice(&key[shared..]);
         self.num_entries += 1;
     }
 
@@ -15,7 +15,9 @@
 use std::mem::size_of;
 
 use bytes::{Buf, BufMut};
+use crc::{Crc, CRC_32_ISCSI};
 
+#[derive(Default, Clone, Copy)]
 pub struct BlockHandle {
     pub offset: usize,
     pub length: usize,
@@ -23,6 +25,27 @@ pub struct BlockHandle {
 
 pub const ENCODED_SIZE: usize = size_of::<u64>() * 2;
 
+// Since format version 3, every block is followed by a trailer:
+//
+// BlockTrailer = {
+//     compression : u8
+//     checksum    : fixed32
+// }
+//
+// The checksum is the CRC32C of the stored block and the compression type.
+// Block handles don't include the trailer.
+
+pub const TRAILER_SIZE: usize = 1 + size_of::<u32>();
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+pub fn block_checksum(block: &[u8], compression: u8) -> u32 {
+    let mut digest = CRC.digest();
+    digest.update(block);
+    digest.update(&[compression]);
+    digest.finalize()
+}
+
 impl BlockHandle {
     pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
         buf.put_u64(self.offset as u64);
@@ -17,32 +17,50 @@ use std::{mem::size_of, ops::Range, sync::Arc};
 use bytes::Buf;
 
 use super::Key;
+use crate::{Error, Result};
+
+// Describes the corruption in a block.
+type DecodeResult<T> = std::result::Result<T, String>;
 
 pub struct BlockIter {
     block: Arc<[u8]>,
+    // Whether the keys share prefixes, see `BlockBuilder` for the formats.
+    prefix_compressed: bool,
     num_restarts: usize,
     restarts_offset: usize,
-    key_range: Range<usize>,
+    key: Vec<u8>,
     value_range: Range<usize>,
+    valid: bool,
+    // The first corruption found in the block.
+    corruption: Option<String>,
 }
 
 impl BlockIter {
-    pub fn new(block: Arc<[u8]>) -> Self {
-        let offset = block.len() - size_of::<u32>();
-        let num_restarts = (&block[offset..]).get_u32() as usize;
-        let restarts_offset = offset - num_restarts * size_of::<u32>();
-        Self {
+    /// Creates an iterator over a block of a table of the format version.
+    pub fn new(block: Arc<[u8]>, version: u32) -> Self {
+        let mut iter = Self {
             block,
-            num_restarts,
-            restarts_offset,
-            key_range: Range::default(),
+            prefix_compressed: version >= 2,
+            num_restarts: 0,
+            restarts_offset: 0,
+            key: Vec::new(),
             value_range: Range::default(),
+            valid: false,
+            corruption: None,
+        };
+        match decode_restarts(&iter.block) {
+            Ok((num_restarts, restarts_offset)) => {
+                iter.num_restarts = num_restarts;
+                iter.restarts_offset = restarts_offset;
+            }
+            Err(msg) => iter.set_corruption(msg),
         }
+        iter
     }
 
     pub fn key(&self) -> Key<'_> {
         debug_assert!(self.valid());
-        self.block[self.key_range.clone()].into()
+        self.key.as_slice().into()
     }
 
     pub fn value(&self) -> &[u8] {
@@ -51,27 +69,48 @@ impl BlockIter {
     }
 
     pub fn valid(&self) -> bool {
-        !self.key_range.is_empty()
+        self.valid
+    }
+
+    /// Returns an error if the iterator has found a corruption, after which
+    /// it is invalid.
+    pub fn status(&self) -> Result<()> {
+        match &self.corruption {
+            Some(msg) => Err(Error::corrupted(msg.as_str())),
+            None => Ok(()),
+        }
     }
 
     pub fn seek_to_first(&mut self) {
+        if self.corruption.is_some() {
+            return;
+        }
+        self.key.clear();
         self.decode_from(0);
     }
 
     pub fn seek(&mut self, target: Key<'_>) {
+        if self.corruption.is_some() {
+            return;
+        }
+
+        // Finds the last restart point with a key not greater than the target.
         let mut l = 0;
         let mut r = self.num_restarts - 1;
         while l < r {
             let m = (l + r + 1) / 2;
-            let k = self.restart_key(m);
-            if k <= target {
-                l = m;
-            } else {
-                r = m - 1;
+            match self.restart_key(m) {
+                Ok(k) if k <= target => l = m,
+                Ok(_) => r = m - 1,
+                Err(msg) => return self.set_corruption(msg),
             }
         }
 
-        self.decode_from(self.restart_offset(l));
+        self.key.clear();
+        match self.restart_offset(l) {
+            Ok(offset) => self.decode_from(offset),
+            Err(msg) => return self.set_corruption(msg),
+        }
         while self.valid() {
             if self.key() >= target {
                 break;
@@ -85,33 +124,99 @@ impl BlockIter {
         self.decode_from(self.value_range.end)
     }
 
+    fn set_corruption(&mut self, msg: String) {
+        self.valid = false;
+        self.corruption.get_or_insert(msg);
+    }
+
     fn decode_from(&mut self, offset: usize) {
-        if offset < self.restarts_offset {
-            (self.key_range, self.value_range) = decode_entry(&self.block, offset);
-        } else {
-            (self.key_range, self.value_range) = (Range::default(), Range::default());
+        if offset >= self.restarts_offset {
+            self.valid = false;
+            return;
         }
+        let entry = match self.decode_entry(offset) {
+            Ok(entry) => entry,
+            Err(msg) => return self.set_corruption(msg),
+        };
+        if entry.shared > self.key.len() {
+            return self.set_corruption(format!("entry at {} shares too many bytes", offset));
+        }
+        self.key.truncate(entry.shared);
+        self.key
+            .extend_from_slice(&self.block[entry.unshared_key_range]);
+        if !Key::is_valid(&self.key) {
+            return self.set_corruption(format!("entry at {} has an invalid key", offset));
+        }
+        self.value_range = entry.value_range;
+        self.valid = true;
     }
 
-    fn restart_key(&self, index: usize) -> Key<'_> {
-        let offset = self.restart_offset(index);
-        let (key_range, _) = decode_entry(&self.block, offset);
-        self.block[key_range].into()
+    fn restart_key(&self, index: usize) -> DecodeResult<Key<'_>> {
+        // Entries at restart points store the full keys.
+        let offset = self.restart_offset(index)?;
+        let entry = self.decode_entry(offset)?;
+        let key = &self.block[entry.unshared_key_range];
+        if entry.shared != 0 || !Key::is_valid(key) {
+            return Err(format!("entry at {} has an invalid restart key", offset));
+        }
+        Ok(key.into())
     }
 
-    fn restart_offset(&self, index: usize) -> usize {
+    fn restart_offset(&self, index: usize) -> DecodeResult<usize> {
         let offset = self.restarts_offset + index * size_of::<u32>();
-        (&self.block[offset..]).get_u32() as usize
+        let restart = (&self.block[offset..]).get_u32() as usize;
+        if restart >= self.restarts_offset {
+            return Err(format!("restart point {} is out of range", index));
+        }
+        Ok(restart)
+    }
+
+    // Decodes the entry at the offset, which must be before the restarts.
+    fn decode_entry(&self, offset: usize) -> DecodeResult<Entry> {
+        let truncated = || format!("entry at {} is truncated", offset);
+        let mut buf = &self.block[offset..self.restarts_offset];
+        let num_fields = if self.prefix_compressed { 3 } else { 2 };
+        if buf.len() < size_of::<u32>() * num_fields {
+            return Err(truncated());
+        }
+        let shared = if self.prefix_compressed {
+            buf.get_u32() as usize
+        } else {
+            0
+        };
+        let key_size = buf.get_u32() as usize;
+        let value_size = buf.get_u32() as usize;
+        let key_offset = offset + size_of::<u32>() * num_fields;
+        let value_offset = key_offset.checked_add(key_size).ok_or_else(truncated)?;
+        let end = value_offset
+            .checked_add(value_size)
+            .filter(|&end| end <= self.restarts_offset)
+            .ok_or_else(truncated)?;
+        Ok(Entry {
+            shared,
+            unshared_key_range: key_offset..value_offset,
+            value_range: value_offset..end,
+        })
     }
 }
 
-fn decode_entry(buf: &[u8], mut offset: usize) -> (Range<usize>, Range<usize>) {
-    let mut buf = &buf[offset..];
-    let key_size = buf.get_u32() as usize;
-    let value_size = buf.get_u32() as usize;
-    offset += size_of::<u32>() * 2;
-    let key_range = offset..(offset + key_size);
-    offset += key_size;
-    let value_range = offset..(offset + value_size);
-    (key_range, value_range)
+// Returns the number of restarts and the offset of the restarts.
+fn decode_restarts(block: &[u8]) -> DecodeResult<(usize, usize)> {
+    let offset = block
+        .len()
+        .checked_sub(size_of::<u32>())
+        .ok_or_else(|| format!("block of {} bytes is too small", block.len()))?;
+    let num_restarts = (&block[offset..]).get_u32() as usize;
+    let restarts_offset = num_restarts
+        .checked_mul(size_of::<u32>())
+        .and_then(|size| offset.checked_sub(size))
+        .filter(|_| num_restarts > 0)
+        .ok_or_else(|| format!("block has an invalid number of restarts {}", num_restarts))?;
+    Ok((num_restarts, restarts_offset))
+}
+
+struct Entry {
+    shared: usize,
+    unshared_key_range: Range<usize>,
+    value_range: Range<usize>,
 }
@@ -0,0 +1,77 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::{Error, Result};
+
+/// The compression algorithm of blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionType {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl Default for CompressionType {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl From<CompressionType> for u8 {
+    fn from(v: CompressionType) -> Self {
+        match v {
+            CompressionType::None => 0,
+            CompressionType::Lz4 => 1,
+            CompressionType::Zstd => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for CompressionType {
+    type Error = u8;
+
+    fn try_from(v: u8) -> std::result::Result<Self, Self::Error> {
+        match v {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            2 => Ok(Self::Zstd),
+            _ => Err(v),
+        }
+    }
+}
+
+/// Compresses the block, or returns `None` if it isn't worth it.
+pub fn compress(tp: CompressionType, block: &[u8]) -> Result<Option<Vec<u8>>> {
+    let compressed = match tp {
+        CompressionType::None => return Ok(None),
+        CompressionType::Lz4 => lz4_flex::compress_prepend_size(block),
+        CompressionType::Zstd => zstd::encode_all(block, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+    };
+    // Keeps the block raw unless compression saves at least 1/8 of it.
+    if compressed.len() < block.len() - block.len() / 8 {
+        Ok(Some(compressed))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn decompress(tp: CompressionType, block: &[u8]) -> Result<Vec<u8>> {
+    match tp {
+        CompressionType::None => Ok(block.to_owned()),
+        CompressionType::Lz4 => lz4_flex::decompress_size_prepended(block)
+            .map_err(|err| Error::corrupted(format!("lz4 block: {}", err))),
+        CompressionType::Zstd => {
+            zstd::decode_all(block).map_err(|err| Error::corrupted(format!("zstd block: {}", err)))
+        }
+    }
+}
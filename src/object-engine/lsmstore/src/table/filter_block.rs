@@ -0,0 +1,152 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::BufMut;
+
+// Filter block format:
+//
+// Filter = {
+//     bits       : bytes
+//     num_probes : u8
+// }
+//
+// The filter is a bloom filter over the ids of a table, with the same layout
+// as LevelDB's.
+
+pub struct FilterBuilder {
+    bits_per_key: usize,
+    hashes: Vec<u32>,
+    last_id: Option<Vec<u8>>,
+}
+
+impl FilterBuilder {
+    pub fn new(bits_per_key: usize) -> Self {
+        Self {
+            bits_per_key,
+            hashes: Vec::new(),
+            last_id: None,
+        }
+    }
+
+    pub fn add(&mut self, id: &[u8]) {
+        // All versions of an id are consecutive in a table.
+        if self.last_id.as_deref() != Some(id) {
+            self.hashes.push(bloom_hash(id));
+            self.last_id = Some(id.to_owned());
+        }
+    }
+
+    pub fn finish(&self) -> Vec<u8> {
+        // Rounds down to reduce the cost of probing, ln(2) is about 0.69.
+        let num_probes = (self.bits_per_key * 69 / 100).clamp(1, 30);
+        let num_bits = (self.hashes.len() * self.bits_per_key).max(64);
+        let num_bytes = (num_bits + 7) / 8;
+        let num_bits = num_bytes * 8;
+
+        let mut buf = vec![0u8; num_bytes];
+        for &hash in &self.hashes {
+            let mut h = hash;
+            let delta = h.rotate_left(15);
+            for _ in 0..num_probes {
+                let pos = h as usize % num_bits;
+                buf[pos / 8] |= 1 << (pos % 8);
+                h = h.wrapping_add(delta);
+            }
+        }
+        buf.put_u8(num_probes as u8);
+        buf
+    }
+}
+
+/// Returns false if the id is definitely not in the filter.
+pub fn may_contain(filter: &[u8], id: &[u8]) -> bool {
+    if filter.len() < 2 {
+        return false;
+    }
+    let (bits, num_probes) = filter.split_at(filter.len() - 1);
+    let num_probes = num_probes[0];
+    if num_probes > 30 {
+        // Reserved for other encodings.
+        return true;
+    }
+    let num_bits = bits.len() * 8;
+    let mut h = bloom_hash(id);
+    let delta = h.rotate_left(15);
+    for _ in 0..num_probes {
+        let pos = h as usize % num_bits;
+        if bits[pos / 8] & (1 << (pos % 8)) == 0 {
+            return false;
+        }
+        h = h.wrapping_add(delta);
+    }
+    true
+}
+
+// The hash function of LevelDB.
+fn bloom_hash(data: &[u8]) -> u32 {
+    const SEED: u32 = 0xbc9f1d34;
+    const M: u32 = 0xc6a4a793;
+
+    let mut h = SEED ^ (data.len() as u32).wrapping_mul(M);
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let w = u32::from_le_bytes(chunk.try_into().unwrap());
+        h = h.wrapping_add(w).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate() {
+            h = h.wrapping_add((*b as u32) << (8 * i));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> 24;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(i: usize) -> Vec<u8> {
+        format!("tenant/bucket/object-{}", i).into_bytes()
+    }
+
+    #[test]
+    fn test_empty() {
+        let filter = FilterBuilder::new(10).finish();
+        assert!(!may_contain(&filter, b"a"));
+        assert!(!may_contain(&filter, b""));
+    }
+
+    #[test]
+    fn test_may_contain() {
+        for n in [1, 10, 100, 1000, 10000] {
+            let mut builder = FilterBuilder::new(10);
+            for i in 0..n {
+                builder.add(&id(i));
+            }
+            let filter = builder.finish();
+            assert!(filter.len() <= n * 10 / 8 + 40);
+            for i in 0..n {
+                assert!(may_contain(&filter, &id(i)));
+            }
+            // The false positive rate of 10 bits per key is about 1%.
+            let false_positives = (n..(n + 10000))
+                .filter(|&i| may_contain(&filter, &id(i)))
+                .count();
+            assert!(false_positives < 200, "{} false positives", false_positives);
+        }
+    }
+}
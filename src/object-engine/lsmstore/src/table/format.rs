@@ -22,7 +22,7 @@ pub type Timestamp = u64;
 pub struct Key<'a>(&'a [u8]);
 
 impl<'a> Key<'a> {
-    pub fn id(&self) -> &[u8] {
+    pub fn id(&self) -> &'a [u8] {
         &self.0[..(self.0.len() - 9)]
     }
 
@@ -36,6 +36,11 @@ impl<'a> Key<'a> {
         (*self.0.last().unwrap()).try_into().unwrap()
     }
 
+    /// Returns true if the bytes are a well-formed key.
+    pub fn is_valid(v: &[u8]) -> bool {
+        v.len() > size_of::<Timestamp>() && ValueType::try_from(v[v.len() - 1]).is_ok()
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         self.0
     }
@@ -94,6 +99,7 @@ impl<'a> PartialEq for Key<'a> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ValueType {
     Put = 0,
     Merge = 1,
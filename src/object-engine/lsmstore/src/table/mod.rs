@@ -15,6 +15,8 @@
 mod block_builder;
 mod block_handle;
 mod block_iter;
+mod compression;
+mod filter_block;
 mod format;
 mod table_builder;
 mod table_footer;
@@ -23,7 +25,339 @@ mod table_reader;
 pub(crate) use self::block_iter::BlockIter;
 use self::{block_builder::BlockBuilder, block_handle::BlockHandle, table_footer::TableFooter};
 pub use self::{
+    compression::CompressionType,
     format::{Key, Timestamp, ValueType},
     table_builder::{TableBuilder, TableBuilderOptions, TableDesc},
     table_reader::{TableIter, TableReader},
 };
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::BufMut;
+    use object_engine_filestore::{Bucket, FsFileStore, Store};
+
+    use super::{table_footer::FORMAT_VERSION, *};
+    use crate::{Error, Result};
+
+    // A xorshift generator, so that the tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn random_key(rng: &mut Rng) -> Vec<u8> {
+        // Ids share long prefixes like real ones.
+        let id = format!("tenant/bucket/object-{}", rng.next() % 1000);
+        Key::encode_to_vec(id.as_bytes(), rng.next() % 10, ValueType::Put)
+    }
+
+    // Returns sorted entries with random keys and values.
+    fn random_entries(rng: &mut Rng, n: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut keys: Vec<_> = (0..n).map(|_| random_key(rng)).collect();
+        keys.sort_by(|a, b| Key::from(a.as_slice()).cmp(&Key::from(b.as_slice())));
+        keys.dedup();
+        keys.into_iter()
+            .map(|key| {
+                let value = vec![rng.next() as u8; (rng.next() % 100) as usize];
+                (key, value)
+            })
+            .collect()
+    }
+
+    fn assert_block(block: Arc<[u8]>, version: u32, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let mut iter = BlockIter::new(block, version);
+        iter.seek_to_first();
+        for (key, value) in entries {
+            assert!(iter.valid());
+            assert_eq!(iter.key().as_slice(), key.as_slice());
+            assert_eq!(iter.value(), value.as_slice());
+            iter.next();
+        }
+        assert!(!iter.valid());
+
+        for (key, value) in entries {
+            iter.seek(key.as_slice().into());
+            assert_eq!(iter.key().as_slice(), key.as_slice());
+            assert_eq!(iter.value(), value.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        let mut rng = Rng(42);
+        for restart_interval in [1, 2, 16] {
+            for n in [1, 10, 1000] {
+                let entries = random_entries(&mut rng, n);
+                let mut builder = BlockBuilder::default().restart_interval(restart_interval);
+                for (key, value) in &entries {
+                    builder.add(key, value);
+                }
+                let block: Arc<[u8]> = builder.finish().into();
+                assert_block(block.clone(), FORMAT_VERSION, &entries);
+
+                // Seeks to keys that might not exist.
+                let mut iter = BlockIter::new(block, FORMAT_VERSION);
+                for _ in 0..100 {
+                    let target = random_key(&mut rng);
+                    let target = Key::from(target.as_slice());
+                    iter.seek(target);
+                    match entries
+                        .iter()
+                        .find(|(key, _)| Key::from(key.as_slice()) >= target)
+                    {
+                        Some((key, _)) => assert_eq!(iter.key().as_slice(), key.as_slice()),
+                        None => assert!(!iter.valid()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_legacy_block() {
+        let mut rng = Rng(7);
+        let entries = random_entries(&mut rng, 100);
+        let mut buf = Vec::new();
+        let mut restarts = Vec::new();
+        for (i, (key, value)) in entries.iter().enumerate() {
+            if i % 8 == 0 {
+                restarts.push(buf.len() as u32);
+            }
+            buf.put_u32(key.len() as u32);
+            buf.put_u32(value.len() as u32);
+            buf.put_slice(key);
+            buf.put_slice(value);
+        }
+        for restart in &restarts {
+            buf.put_u32(*restart);
+        }
+        buf.put_u32(restarts.len() as u32);
+        assert_block(buf.into(), 0, &entries);
+    }
+
+    async fn open_bucket(path: &std::path::Path) -> Result<Box<dyn Bucket>> {
+        let store = FsFileStore::open(path).await?;
+        store.create_tenant("t").await?.create_bucket("b").await
+    }
+
+    async fn build_table(
+        bucket: &dyn Bucket,
+        name: &str,
+        options: TableBuilderOptions,
+        entries: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<TableReader> {
+        let writer = bucket.new_sequential_writer(name).await?;
+        let mut builder = TableBuilder::new(writer, options);
+        for (key, value) in entries {
+            builder.add(key.as_slice().into(), value).await?;
+        }
+        let desc = builder.finish().await?;
+        let reader = bucket.new_random_reader(name).await?;
+        TableReader::open(name, reader.into(), desc.table_size).await
+    }
+
+    fn id(i: usize) -> Vec<u8> {
+        format!("k{:06}", i).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_filter() -> Result<()> {
+        let dir = tempdir::TempDir::new("test_filter")?;
+        let bucket = open_bucket(dir.path()).await?;
+        let entries: Vec<_> = (0..1000)
+            .map(|i| (Key::encode_to_vec(&id(i), 1, ValueType::Put), id(i)))
+            .collect();
+
+        let table =
+            build_table(&*bucket, "1.sst", TableBuilderOptions::default(), &entries).await?;
+        for i in 0..1000 {
+            assert!(table.may_contain(&id(i)));
+        }
+        let false_positives = (1000..2000).filter(|&i| table.may_contain(&id(i))).count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+
+        let options = TableBuilderOptions {
+            bits_per_key: 0,
+            ..Default::default()
+        };
+        let table = build_table(&*bucket, "2.sst", options, &entries).await?;
+        assert!(table.may_contain(&id(1000)));
+        let mut iter = table.iter();
+        iter.seek_to_first().await?;
+        for (key, value) in &entries {
+            assert!(iter.valid());
+            assert_eq!(iter.key().as_slice(), key.as_slice());
+            assert_eq!(iter.value(), value.as_slice());
+            iter.next().await?;
+        }
+        assert!(!iter.valid());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_table_round_trip() -> Result<()> {
+        let dir = tempdir::TempDir::new("test_table_round_trip")?;
+        let bucket = open_bucket(dir.path()).await?;
+        let mut rng = Rng(1);
+        let entries = random_entries(&mut rng, 5000);
+        for compression in [
+            CompressionType::None,
+            CompressionType::Lz4,
+            CompressionType::Zstd,
+        ] {
+            let options = TableBuilderOptions {
+                block_size: 1024,
+                compression,
+                ..Default::default()
+            };
+            let name = format!("{:?}.sst", compression);
+            let table = build_table(&*bucket, &name, options, &entries).await?;
+            assert_table(&table, &entries).await?;
+        }
+        Ok(())
+    }
+
+    async fn assert_table(table: &TableReader, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        let desc = table.desc().await?;
+        assert_eq!(desc.lower_bound, entries.first().unwrap().0);
+        assert_eq!(desc.upper_bound, entries.last().unwrap().0);
+
+        let mut iter = table.iter();
+        iter.seek_to_first().await?;
+        for (key, value) in entries {
+            assert!(iter.valid());
+            assert_eq!(iter.key().as_slice(), key.as_slice());
+            assert_eq!(iter.value(), value.as_slice());
+            iter.next().await?;
+        }
+        assert!(!iter.valid());
+        for (key, value) in entries.iter().step_by(7) {
+            iter.seek(key.as_slice().into()).await?;
+            assert_eq!(iter.key().as_slice(), key.as_slice());
+            assert_eq!(iter.value(), value.as_slice());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compression() -> Result<()> {
+        let dir = tempdir::TempDir::new("test_compression")?;
+        let bucket = open_bucket(dir.path()).await?;
+        let mut rng = Rng(3);
+        let compressible: Vec<_> = (0..1000)
+            .map(|i| {
+                (
+                    Key::encode_to_vec(&id(i), 1, ValueType::Put),
+                    vec![b'v'; 100],
+                )
+            })
+            .collect();
+        let random: Vec<_> = (0..1000)
+            .map(|i| {
+                let value: Vec<_> = (0..100).map(|_| rng.next() as u8).collect();
+                (Key::encode_to_vec(&id(i), 1, ValueType::Put), value)
+            })
+            .collect();
+
+        for (i, entries) in [compressible, random].iter().enumerate() {
+            let mut sizes = Vec::new();
+            for compression in [CompressionType::None, CompressionType::Lz4] {
+                let options = TableBuilderOptions {
+                    compression,
+                    ..Default::default()
+                };
+                let name = format!("{}-{:?}.sst", i, compression);
+                let table = build_table(&*bucket, &name, options, entries).await?;
+                assert_table(&table, entries).await?;
+                sizes.push(table.desc().await?.table_size);
+            }
+            if i == 0 {
+                assert!(sizes[1] < sizes[0] / 2, "{:?}", sizes);
+            } else {
+                // Random values don't compress, so most blocks are stored raw.
+                assert!(sizes[1] > sizes[0] / 10 * 9, "{:?}", sizes);
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_block_checksum() -> Result<()> {
+        let dir = tempdir::TempDir::new("test_block_checksum")?;
+        let bucket = open_bucket(dir.path()).await?;
+        let entries: Vec<_> = (0..100)
+            .map(|i| (Key::encode_to_vec(&id(i), 1, ValueType::Put), id(i)))
+            .collect();
+        let table = build_table(&*bucket, "1.sst", Default::default(), &entries).await?;
+        let size = table.desc().await?.table_size;
+
+        let path = dir.path().join("t").join("b").join("1.sst");
+        let mut content = std::fs::read(&path)?;
+        content[0] ^= 1;
+        std::fs::write(&path, content)?;
+        let reader = bucket.new_random_reader("1.sst").await?;
+        let table = TableReader::open("1.sst", reader.into(), size).await?;
+        let mut iter = table.iter();
+        match iter.seek_to_first().await {
+            Err(Error::Corrupted(msg)) => {
+                assert_eq!(msg, "table 1.sst at offset 0: block checksum mismatch")
+            }
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+
+        // Truncated tables are corrupted too.
+        std::fs::write(&path, b"")?;
+        let reader = bucket.new_random_reader("1.sst").await?;
+        let res = TableReader::open("1.sst", reader.into(), size).await;
+        assert!(matches!(res, Err(Error::Corrupted(_))));
+        Ok(())
+    }
+
+    // Iterates over the block and returns the status.
+    fn scan_block(block: Arc<[u8]>, target: &[u8]) -> Result<()> {
+        let mut iter = BlockIter::new(block.clone(), FORMAT_VERSION);
+        iter.seek_to_first();
+        while iter.valid() {
+            iter.key().tp();
+            iter.next();
+        }
+        iter.status()?;
+        let mut iter = BlockIter::new(block, FORMAT_VERSION);
+        iter.seek(target.into());
+        iter.status()
+    }
+
+    #[test]
+    fn test_corrupted_block() {
+        let mut rng = Rng(5);
+        let entries = random_entries(&mut rng, 50);
+        let mut builder = BlockBuilder::default().restart_interval(4);
+        for (key, value) in &entries {
+            builder.add(key, value);
+        }
+        let block = builder.finish().to_owned();
+        let target = &entries[entries.len() / 2].0;
+        assert!(scan_block(block.clone().into(), target).is_ok());
+
+        // Corruptions are reported instead of panicking.
+        for len in 0..block.len() {
+            let _ = scan_block(block[..len].into(), target);
+        }
+        for _ in 0..1000 {
+            let mut block = block.clone();
+            let i = rng.next() as usize % block.len();
+            block[i] = rng.next() as u8;
+            let _ = scan_block(block.into(), target);
+        }
+        assert!(scan_block(vec![0xff; 16].into(), target).is_err());
+    }
+}
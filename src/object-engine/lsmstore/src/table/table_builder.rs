@@ -12,9 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bytes::BufMut;
 use object_engine_filestore::SequentialWrite;
 
-use super::{BlockBuilder, BlockHandle, Key, TableFooter};
+use super::{
+    block_handle,
+    compression::{self, CompressionType},
+    filter_block::FilterBuilder,
+    BlockBuilder, BlockHandle, Key, TableFooter,
+};
 use crate::Result;
 
 #[derive(Default)]
@@ -24,13 +30,26 @@ pub struct TableDesc {
     pub upper_bound: Vec<u8>,
 }
 
+#[derive(Clone)]
 pub struct TableBuilderOptions {
     pub block_size: usize,
+    /// The number of entries between restart points in data blocks.
+    pub block_restart_interval: usize,
+    /// The number of bits per id in the bloom filter, or 0 to build no filter.
+    pub bits_per_key: usize,
+    /// The compression of data and index blocks. Blocks that don't shrink
+    /// enough are stored raw.
+    pub compression: CompressionType,
 }
 
 impl Default for TableBuilderOptions {
     fn default() -> Self {
-        Self { block_size: 8192 }
+        Self {
+            block_size: 8192,
+            block_restart_interval: 16,
+            bits_per_key: 10,
+            compression: CompressionType::None,
+        }
     }
 }
 
@@ -41,18 +60,29 @@ pub struct TableBuilder {
     upper_bound: Vec<u8>,
     data_block_builder: BlockBuilder,
     index_block_builder: BlockBuilder,
+    filter_builder: Option<FilterBuilder>,
 }
 
 #[allow(dead_code)]
 impl TableBuilder {
     pub fn new(writer: SequentialWriter, options: TableBuilderOptions) -> Self {
+        let data_block_builder =
+            BlockBuilder::default().restart_interval(options.block_restart_interval);
+        let filter_builder = if options.bits_per_key > 0 {
+            Some(FilterBuilder::new(options.bits_per_key))
+        } else {
+            None
+        };
         Self {
             writer: FileWriter::new(writer),
             options,
             lower_bound: Vec::new(),
             upper_bound: Vec::new(),
-            data_block_builder: BlockBuilder::default(),
-            index_block_builder: BlockBuilder::default(),
+            data_block_builder,
+            // Index blocks are small, so every entry is a restart point to
+            // make seeks faster.
+            index_block_builder: BlockBuilder::default().restart_interval(1),
+            filter_builder,
         }
     }
 
@@ -61,6 +91,9 @@ impl TableBuilder {
             self.lower_bound = key.to_owned();
         }
         self.upper_bound = key.to_owned();
+        if let Some(filter_builder) = &mut self.filter_builder {
+            filter_builder.add(key.id());
+        }
         self.data_block_builder.add(key.as_slice(), value);
         if self.data_block_builder.encoded_size() >= self.options.block_size as usize {
             self.finish_data_block().await?;
@@ -88,7 +121,10 @@ impl TableBuilder {
     async fn finish_data_block(&mut self) -> Result<()> {
         if self.data_block_builder.num_entries() > 0 {
             let block = self.data_block_builder.finish();
-            let handle = self.writer.write_block(block).await?;
+            let handle = self
+                .writer
+                .write_block(block, self.options.compression)
+                .await?;
             self.data_block_builder.reset();
             let index_value = handle.encode_to_vec();
             self.index_block_builder
@@ -100,11 +136,21 @@ impl TableBuilder {
     async fn finish_index_block(&mut self) -> Result<()> {
         if self.index_block_builder.num_entries() > 0 {
             let block = self.index_block_builder.finish();
-            let handle = self.writer.write_block(block).await?;
+            let index_handle = self
+                .writer
+                .write_block(block, self.options.compression)
+                .await?;
             self.index_block_builder.reset();
-            let footer = TableFooter {
-                index_handle: handle,
+            // Filters are random bits, which don't compress.
+            let filter_handle = match &self.filter_builder {
+                Some(filter_builder) => {
+                    self.writer
+                        .write_block(&filter_builder.finish(), CompressionType::None)
+                        .await?
+                }
+                None => BlockHandle::default(),
             };
+            let footer = TableFooter::new(index_handle, filter_handle);
             self.writer.write_footer(&footer).await?;
         }
         Ok(())
@@ -133,12 +179,25 @@ impl FileWriter {
         Ok(())
     }
 
-    async fn write_block(&mut self, block: &[u8]) -> Result<BlockHandle> {
+    async fn write_block(
+        &mut self,
+        block: &[u8],
+        compression: CompressionType,
+    ) -> Result<BlockHandle> {
+        let compressed = compression::compress(compression, block)?;
+        let (block, compression) = match &compressed {
+            Some(compressed) => (compressed.as_slice(), compression),
+            None => (block, CompressionType::None),
+        };
         let handle = BlockHandle {
             offset: self.offset,
             length: block.len(),
         };
         self.write(block).await?;
+        let mut trailer = Vec::with_capacity(block_handle::TRAILER_SIZE);
+        trailer.put_u8(compression.into());
+        trailer.put_u32(block_handle::block_checksum(block, compression.into()));
+        self.write(&trailer).await?;
         Ok(handle)
     }
 
@@ -17,15 +17,52 @@ use bytes::{Buf, BufMut};
 use super::{block_handle, BlockHandle};
 use crate::{Error, Result};
 
+// Footer format:
+//
+// Footer = {
+//     index_handle  : BlockHandle
+//     filter_handle : BlockHandle
+//     version       : fixed32
+//     magic         : fixed64
+// }
+//
+// Tables without the magic number have a legacy footer with only the
+// index handle, which is read as version 0.
+
+/// The format version of new tables.
+///
+/// 1: Adds the filter block.
+/// 2: Shares key prefixes in blocks.
+/// 3: Compresses blocks and adds a trailer with the checksum.
+pub const FORMAT_VERSION: u32 = 3;
+
+const MAGIC: u64 = 0x656e67756c615353;
+
+const LEGACY_ENCODED_SIZE: usize = block_handle::ENCODED_SIZE;
+
+pub const ENCODED_SIZE: usize = block_handle::ENCODED_SIZE * 2 + 4 + 8;
+
 pub struct TableFooter {
+    pub version: u32,
     pub index_handle: BlockHandle,
+    /// An empty handle if the table has no filter.
+    pub filter_handle: BlockHandle,
 }
 
-pub const ENCODED_SIZE: usize = block_handle::ENCODED_SIZE;
-
 impl TableFooter {
+    pub fn new(index_handle: BlockHandle, filter_handle: BlockHandle) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            index_handle,
+            filter_handle,
+        }
+    }
+
     pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
         self.index_handle.encode_to(buf);
+        self.filter_handle.encode_to(buf);
+        buf.put_u32(self.version);
+        buf.put_u64(MAGIC);
     }
 
     pub fn encode_to_vec(&self) -> Vec<u8> {
@@ -34,12 +71,130 @@ impl TableFooter {
         buf
     }
 
-    pub fn decode_from<B: Buf>(buf: &mut B) -> Result<Self> {
-        if buf.remaining() >= ENCODED_SIZE {
-            let index_handle = BlockHandle::decode_from(buf);
-            Ok(Self { index_handle })
+    /// Decodes the footer from the last bytes of a table of `table_size`
+    /// bytes, which are at most `ENCODED_SIZE` bytes.
+    ///
+    /// Bytes without the magic number are only read as a legacy footer if
+    /// its index handle fits in the table, so that corrupted footers aren't
+    /// mistaken for legacy ones.
+    pub fn decode_from(buf: &[u8], table_size: usize) -> Result<Self> {
+        if buf.len() >= ENCODED_SIZE {
+            let mut buf = &buf[(buf.len() - ENCODED_SIZE)..];
+            let index_handle = BlockHandle::decode_from(&mut buf);
+            let filter_handle = BlockHandle::decode_from(&mut buf);
+            let version = buf.get_u32();
+            if buf.get_u64() == MAGIC {
+                if version == 0 || version > FORMAT_VERSION {
+                    return Err(Error::corrupted(format!(
+                        "unsupported table format version {}",
+                        version
+                    )));
+                }
+                return Ok(Self {
+                    version,
+                    index_handle,
+                    filter_handle,
+                });
+            }
+        }
+        if buf.len() >= LEGACY_ENCODED_SIZE {
+            let mut buf = &buf[(buf.len() - LEGACY_ENCODED_SIZE)..];
+            let index_handle = BlockHandle::decode_from(&mut buf);
+            let fits = index_handle
+                .offset
+                .checked_add(index_handle.length)
+                .and_then(|end| end.checked_add(LEGACY_ENCODED_SIZE))
+                .map_or(false, |end| end <= table_size);
+            if !fits {
+                return Err(Error::corrupted("invalid table footer"));
+            }
+            Ok(Self {
+                version: 0,
+                index_handle,
+                filter_handle: BlockHandle::default(),
+            })
         } else {
             Err(Error::corrupted("table footer is too small"))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_legacy_footer() -> Result<()> {
+        let index_handle = BlockHandle {
+            offset: 123,
+            length: 456,
+        };
+        let buf = index_handle.encode_to_vec();
+        let table_size = 123 + 456 + LEGACY_ENCODED_SIZE;
+        let footer = TableFooter::decode_from(&buf, table_size)?;
+        assert_eq!(footer.version, 0);
+        assert_eq!(footer.index_handle.offset, 123);
+        assert_eq!(footer.index_handle.length, 456);
+        assert_eq!(footer.filter_handle.length, 0);
+
+        // Legacy tables might be large enough to be read with the new size.
+        let mut buf = vec![1u8; ENCODED_SIZE];
+        index_handle.encode_to(&mut buf);
+        let footer = TableFooter::decode_from(&buf[LEGACY_ENCODED_SIZE..], table_size)?;
+        assert_eq!(footer.version, 0);
+        assert_eq!(footer.index_handle.offset, 123);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_corrupted_footer() {
+        let is_corrupted = |buf: &[u8], table_size| {
+            matches!(
+                TableFooter::decode_from(buf, table_size),
+                Err(Error::Corrupted(_))
+            )
+        };
+        assert!(is_corrupted(&[0u8; LEGACY_ENCODED_SIZE - 1], 100));
+
+        // Without the magic number, the index handle must fit in the table.
+        let index_handle = BlockHandle {
+            offset: 10,
+            length: 20,
+        };
+        let buf = index_handle.encode_to_vec();
+        assert!(!is_corrupted(&buf, 46));
+        assert!(is_corrupted(&buf, 45));
+        let mut buf = vec![0xffu8; ENCODED_SIZE];
+        assert!(is_corrupted(&buf, 1 << 20));
+
+        // A new footer with a corrupted magic number isn't read as a legacy
+        // one, whose handle would be the version and the magic number.
+        let footer = TableFooter::new(index_handle, BlockHandle::default());
+        buf.clear();
+        footer.encode_to(&mut buf);
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+        assert!(is_corrupted(&buf, 1 << 20));
+    }
+
+    #[test]
+    fn test_encode_decode() -> Result<()> {
+        let footer = TableFooter::new(
+            BlockHandle {
+                offset: 1,
+                length: 2,
+            },
+            BlockHandle {
+                offset: 3,
+                length: 4,
+            },
+        );
+        let mut buf = vec![0u8; 10];
+        footer.encode_to(&mut buf);
+        let decoded = TableFooter::decode_from(&buf, buf.len())?;
+        assert_eq!(decoded.version, FORMAT_VERSION);
+        assert_eq!(decoded.index_handle.offset, 1);
+        assert_eq!(decoded.filter_handle.length, 4);
+        Ok(())
+    }
+}
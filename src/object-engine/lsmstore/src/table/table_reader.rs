@@ -12,50 +12,121 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{fmt::Display, io::ErrorKind, sync::Arc};
 
+use bytes::Buf;
 use object_engine_filestore::RandomRead;
 
-use super::{table_footer, BlockHandle, BlockIter, Key, TableFooter};
-use crate::Result;
+use super::{
+    block_handle, compression, filter_block, table_footer, BlockHandle, BlockIter, Key, TableDesc,
+    TableFooter,
+};
+use crate::{
+    cache::{BlockCache, FileKey},
+    Error, Result,
+};
 
 #[allow(dead_code)]
 pub struct TableReader {
     reader: FileReader,
+    version: u32,
+    index_handle: BlockHandle,
     index_block: Arc<[u8]>,
+    filter_block: Option<Arc<[u8]>>,
 }
 
 #[allow(dead_code)]
 impl TableReader {
-    pub async fn open(reader: RandomReader, table_size: usize) -> Result<Self> {
-        let reader = FileReader::new(reader);
-        let footer = reader.read_footer(table_size).await?;
+    /// Opens a table, whose name is used to report corruptions.
+    pub async fn open(name: &str, reader: RandomReader, table_size: usize) -> Result<Self> {
+        let mut reader = FileReader::new(name, reader, table_size);
+        let footer = reader.read_footer().await?;
+        reader.version = footer.version;
         let index_block = reader.read_block(&footer.index_handle).await?;
+        let filter_block = if footer.filter_handle.length > 0 {
+            Some(reader.read_block(&footer.filter_handle).await?)
+        } else {
+            None
+        };
         Ok(Self {
             reader,
+            version: footer.version,
+            index_handle: footer.index_handle,
             index_block,
+            filter_block,
         })
     }
 
+    /// Reads data blocks through the cache, where they are keyed by the file.
+    pub(crate) fn set_block_cache(&mut self, cache: Arc<BlockCache>, file: Arc<FileKey>) {
+        self.reader.cache = Some((cache, file));
+    }
+
+    /// Returns false if the table definitely doesn't contain the id.
+    pub fn may_contain(&self, id: &[u8]) -> bool {
+        match &self.filter_block {
+            Some(filter) => filter_block::may_contain(filter, id),
+            None => true,
+        }
+    }
+
     pub fn iter(&self) -> TableIter {
-        let index_iter = BlockIter::new(self.index_block.clone());
-        TableIter::new(self.reader.clone(), index_iter)
+        let index_iter = BlockIter::new(self.index_block.clone(), self.version);
+        TableIter::new(
+            self.reader.clone(),
+            self.version,
+            self.index_handle.offset,
+            index_iter,
+        )
+    }
+
+    /// Reads the size and the key bounds of this table.
+    pub async fn desc(&self) -> Result<TableDesc> {
+        let mut iter = self.iter();
+        iter.seek_to_first().await?;
+        if !iter.valid() {
+            return Err(self.reader.corrupted(0, "table is empty"));
+        }
+        let lower_bound = iter.key().to_owned();
+        // The index keys are the upper bounds of the data blocks.
+        let mut index_iter = BlockIter::new(self.index_block.clone(), self.version);
+        index_iter.seek_to_first();
+        let mut upper_bound = Vec::new();
+        while index_iter.valid() {
+            upper_bound = index_iter.key().to_owned();
+            index_iter.next();
+        }
+        index_iter
+            .status()
+            .map_err(|err| self.reader.corrupted(self.index_handle.offset, err))?;
+        Ok(TableDesc {
+            table_size: self.reader.size,
+            lower_bound,
+            upper_bound,
+        })
     }
 }
 
 #[allow(dead_code)]
 pub struct TableIter {
     reader: FileReader,
+    version: u32,
+    index_offset: usize,
     index_iter: BlockIter,
+    // The offset of the current data block.
+    block_offset: usize,
     block_iter: Option<BlockIter>,
 }
 
 #[allow(dead_code)]
 impl TableIter {
-    fn new(reader: FileReader, index_iter: BlockIter) -> Self {
+    fn new(reader: FileReader, version: u32, index_offset: usize, index_iter: BlockIter) -> Self {
         Self {
             reader,
+            version,
+            index_offset,
             index_iter,
+            block_offset: 0,
             block_iter: None,
         }
     }
@@ -86,7 +157,7 @@ impl TableIter {
         } else {
             None
         };
-        Ok(())
+        self.status()
     }
 
     pub async fn seek(&mut self, key: Key<'_>) -> Result<()> {
@@ -98,13 +169,13 @@ impl TableIter {
         } else {
             None
         };
-        Ok(())
+        self.status()
     }
 
     pub async fn next(&mut self) -> Result<()> {
         if let Some(mut block_iter) = self.block_iter.take() {
             block_iter.next();
-            if block_iter.valid() {
+            if block_iter.valid() || block_iter.status().is_err() {
                 self.block_iter = Some(block_iter);
             } else {
                 self.index_iter.next();
@@ -117,14 +188,34 @@ impl TableIter {
                 }
             }
         }
+        self.status()
+    }
+
+    // Returns the corruption found in the index block or the current data
+    // block, if any.
+    fn status(&self) -> Result<()> {
+        self.index_iter
+            .status()
+            .map_err(|err| self.reader.corrupted(self.index_offset, err))?;
+        if let Some(block_iter) = &self.block_iter {
+            block_iter
+                .status()
+                .map_err(|err| self.reader.corrupted(self.block_offset, err))?;
+        }
         Ok(())
     }
 
     async fn read_block_iter(&mut self) -> Result<BlockIter> {
         let mut index_value = self.index_iter.value();
+        if index_value.len() != block_handle::ENCODED_SIZE {
+            return Err(self
+                .reader
+                .corrupted(self.index_offset, "invalid block handle in the index block"));
+        }
         let handle = BlockHandle::decode_from(&mut index_value);
         let block = self.reader.read_block(&handle).await?;
-        Ok(BlockIter::new(block))
+        self.block_offset = handle.offset;
+        Ok(BlockIter::new(block, self.version))
     }
 }
 
@@ -132,24 +223,92 @@ type RandomReader = Arc<dyn RandomRead>;
 
 #[derive(Clone)]
 struct FileReader {
+    name: Arc<str>,
     reader: RandomReader,
+    size: usize,
+    /// The format version of the table, which is 0 until the footer is read.
+    version: u32,
+    cache: Option<(Arc<BlockCache>, Arc<FileKey>)>,
 }
 
 impl FileReader {
-    fn new(reader: RandomReader) -> Self {
-        Self { reader }
+    fn new(name: &str, reader: RandomReader, size: usize) -> Self {
+        Self {
+            name: name.into(),
+            reader,
+            size,
+            version: 0,
+            cache: None,
+        }
     }
 
     async fn read_block(&self, handle: &BlockHandle) -> Result<Arc<[u8]>> {
-        let mut buf = vec![0u8; handle.length as usize];
-        self.reader.read_exact_at(&mut buf, handle.offset).await?;
-        Ok(buf.into())
+        let (cache, file) = match &self.cache {
+            Some(cache) => cache,
+            None => return self.read_block_uncached(handle).await,
+        };
+        let key = (file.clone(), handle.offset);
+        if let Some(block) = cache.get(&key) {
+            return Ok(block);
+        }
+        let block = self.read_block_uncached(handle).await?;
+        cache.insert(key, block.clone(), block.len());
+        Ok(block)
+    }
+
+    async fn read_block_uncached(&self, handle: &BlockHandle) -> Result<Arc<[u8]>> {
+        let trailer_size = if self.version >= 3 {
+            block_handle::TRAILER_SIZE
+        } else {
+            0
+        };
+        let length = handle.length.checked_add(trailer_size);
+        let end = length.and_then(|length| handle.offset.checked_add(length));
+        if end.map_or(true, |end| end > self.size) {
+            return Err(self.corrupted(handle.offset, "block handle is out of range"));
+        }
+        let mut buf = vec![0u8; handle.length + trailer_size];
+        self.read_exact_at(&mut buf, handle.offset).await?;
+        if trailer_size == 0 {
+            return Ok(buf.into());
+        }
+
+        let mut trailer = &buf[handle.length..];
+        let tp = trailer.get_u8();
+        let checksum = trailer.get_u32();
+        buf.truncate(handle.length);
+        if block_handle::block_checksum(&buf, tp) != checksum {
+            return Err(self.corrupted(handle.offset, "block checksum mismatch"));
+        }
+        let tp = tp.try_into().map_err(|tp| {
+            self.corrupted(
+                handle.offset,
+                format!("unknown block compression type {}", tp),
+            )
+        })?;
+        let block =
+            compression::decompress(tp, &buf).map_err(|err| self.corrupted(handle.offset, err))?;
+        Ok(block.into())
+    }
+
+    async fn read_footer(&self) -> Result<TableFooter> {
+        // Legacy footers are smaller, so small tables are read as a whole.
+        let mut buf = vec![0; self.size.min(table_footer::ENCODED_SIZE)];
+        let offset = self.size - buf.len();
+        self.read_exact_at(&mut buf, offset).await?;
+        TableFooter::decode_from(&buf, self.size).map_err(|err| self.corrupted(offset, err))
+    }
+
+    async fn read_exact_at(&self, buf: &mut [u8], offset: usize) -> Result<()> {
+        match self.reader.read_exact_at(buf, offset).await {
+            Err(Error::Io(err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                Err(self.corrupted(offset, "table is truncated"))
+            }
+            res => res,
+        }
     }
 
-    async fn read_footer(&self, table_size: usize) -> Result<TableFooter> {
-        let mut buf = [0; table_footer::ENCODED_SIZE];
-        let offset = table_size - buf.len();
-        self.reader.read_exact_at(&mut buf, offset).await?;
-        TableFooter::decode_from(&mut buf.as_slice())
+    fn corrupted(&self, offset: usize, msg: impl Display) -> Error {
+        Error::corrupted(format!("table {} at offset {}: {}", self.name, offset, msg))
     }
 }
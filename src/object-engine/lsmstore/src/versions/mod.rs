@@ -22,7 +22,7 @@ pub use self::{
         version_edit::{Bucket, File},
         VersionEditBuilder,
     },
-    version::{BucketVersion, FileMetadata, OrdByUpperBound, Version},
+    version::{BucketVersion, FileMetadata, OrdByUpperBound, Version, NUM_LEVELS},
     version_set::VersionSet,
 };
 
@@ -42,6 +42,7 @@ mod test_version_set {
                 VersionEditBuilder::default()
                     .add_buckets(vec![version_edit::Bucket {
                         name: format!("b{}", i).to_string(),
+                        ..Default::default()
                     }])
                     .build(),
             )
@@ -54,6 +55,7 @@ mod test_version_set {
                 VersionEditBuilder::default()
                     .add_buckets(vec![version_edit::Bucket {
                         name: format!("b{}", i).to_string(),
+                        ..Default::default()
                     }])
                     .build(),
             )
@@ -62,4 +64,133 @@ mod test_version_set {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_reopen() -> Result<()> {
+        let tmp = tempdir::TempDir::new("test_reopen")?;
+        let vs1 = VersionSet::open(tmp.path()).await?;
+        vs1.log_and_apply(
+            VersionEditBuilder::default()
+                .add_buckets(vec![
+                    version_edit::Bucket {
+                        name: "b1".to_owned(),
+                        ..Default::default()
+                    },
+                    version_edit::Bucket {
+                        name: "b2".to_owned(),
+                        options: vec![1],
+                    },
+                ])
+                .build(),
+        )
+        .await?;
+        vs1.log_and_apply(
+            VersionEditBuilder::default()
+                .add_buckets(vec![version_edit::Bucket {
+                    name: "b2".to_owned(),
+                    options: vec![2],
+                }])
+                .remove_buckets(vec!["b1".to_owned()])
+                .set_tenant_options(vec![3])
+                .build(),
+        )
+        .await?;
+        let nums = vs1.get_next_file_num(3).await?;
+        drop(vs1);
+
+        // Opens twice, so that the second one loads from a rolled manifest.
+        for _ in 0..2 {
+            let vs = VersionSet::open(tmp.path()).await?;
+            let names = vs.current_version().await.bucket_names().await;
+            assert_eq!(names, vec!["b2".to_owned()]);
+            let bucket = vs.current_version().await.bucket_version("b2").await?;
+            assert_eq!(bucket.options, vec![2]);
+            let options = vs.current_version().await.tenant_options().await;
+            assert_eq!(options, vec![3]);
+            let next = vs.get_next_file_num(1).await?;
+            assert!(next[0] > *nums.last().unwrap());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_obsolete_files() -> Result<()> {
+        let tmp = tempdir::TempDir::new("test_obsolete_files")?;
+        let vs = VersionSet::open(tmp.path()).await?;
+        let file = version_edit::File {
+            bucket: "b1".to_owned(),
+            name: "f1".to_owned(),
+            lower_bound: Key::encode_to_vec(b"k1", 1, ValueType::Put),
+            upper_bound: Key::encode_to_vec(b"k2", 1, ValueType::Put),
+            ..Default::default()
+        };
+        vs.log_and_apply(
+            VersionEditBuilder::default()
+                .add_buckets(vec![version_edit::Bucket {
+                    name: "b1".to_owned(),
+                    ..Default::default()
+                }])
+                .add_files(vec![file])
+                .build(),
+        )
+        .await?;
+        let version = vs.current_version().await;
+        vs.log_and_apply(
+            VersionEditBuilder::default()
+                .remove_files(vec![version_edit::FileId {
+                    bucket: "b1".to_owned(),
+                    name: "f1".to_owned(),
+                    ..Default::default()
+                }])
+                .build(),
+        )
+        .await?;
+
+        // The removed file is still referenced by the old version.
+        assert!(vs.live_files("b1").await.contains("f1"));
+        assert!(vs.deletable_files().await.is_empty());
+
+        drop(version);
+        assert!(vs.live_files("b1").await.is_empty());
+        let files = vs.deletable_files().await;
+        assert_eq!(files, vec![("b1".to_owned(), "f1".to_owned())]);
+        vs.forget_obsolete_file("b1", "f1").await;
+        assert!(vs.deletable_files().await.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_obsolete_manifests() -> Result<()> {
+        let tmp = tempdir::TempDir::new("test_delete_obsolete_manifests")?;
+        let num_manifests = || {
+            std::fs::read_dir(tmp.path())
+                .unwrap()
+                .filter(|ent| {
+                    let name = ent.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with("MANIFEST-")
+                })
+                .count()
+        };
+        for i in 0..3 {
+            // Each restart rolls a new manifest on the first edit.
+            let vs = VersionSet::open(tmp.path()).await?;
+            vs.log_and_apply(
+                VersionEditBuilder::default()
+                    .add_buckets(vec![version_edit::Bucket {
+                        name: format!("b{}", i),
+                        ..Default::default()
+                    }])
+                    .build(),
+            )
+            .await?;
+            assert_eq!(num_manifests(), 1);
+        }
+        let vs = VersionSet::open(tmp.path()).await?;
+        let names = vs.current_version().await.bucket_names().await;
+        assert_eq!(names, vec!["b0", "b1", "b2"]);
+
+        Ok(())
+    }
 }
@@ -61,6 +61,11 @@ impl VersionEditBuilder {
         self
     }
 
+    pub fn set_tenant_options(&mut self, options: Vec<u8>) -> &mut Self {
+        self.ve.tenant_options = Some(version_edit::TenantOptions { options });
+        self
+    }
+
     pub fn set_next_file_num(&mut self, next_file_num: u64) -> &mut Self {
         self.ve.next_file_num = next_file_num;
         self
@@ -14,16 +14,16 @@
 
 use std::{
     cmp::Ordering,
-    collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap},
+    collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap, HashSet},
     ops::Deref,
-    sync::Arc,
+    sync::{Arc, Weak},
 };
 
 use tokio::sync::Mutex;
 
 use crate::{iterator::ManifestIter, versions::proto::*, *};
 
-const NUM_LEVELS: usize = 7;
+pub const NUM_LEVELS: usize = 7;
 
 #[derive(Clone, Default)]
 pub struct FileMetadata {
@@ -52,6 +52,7 @@ impl LevelFiles<OrdByUpperBound> {
 
 #[derive(Clone)]
 pub struct BucketVersion {
+    pub options: Vec<u8>,
     pub l0_level: Vec<FileMetadata>,
     pub non_l0_levels: Vec<LevelFiles<OrdByUpperBound>>,
     pub files: HashMap<String, FileMetadata>, // filename -> file (for delete)
@@ -64,6 +65,7 @@ impl Default for BucketVersion {
             .map(|_| LevelFiles::default())
             .collect();
         Self {
+            options: Vec::new(),
             non_l0_levels,
             files: HashMap::new(),
             l0_level: Vec::new(),
@@ -76,9 +78,10 @@ pub struct Version {
     pub inner: Arc<Mutex<Inner>>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Inner {
     pub buckets: BTreeMap<String, BucketVersion>, // bucket => levels;
+    pub tenant_options: Vec<u8>,
 }
 
 impl Version {
@@ -88,11 +91,50 @@ impl Version {
         Ok(())
     }
 
+    pub fn downgrade(&self) -> WeakVersion {
+        WeakVersion(Arc::downgrade(&self.inner))
+    }
+
+    /// Returns a copy of this version that can be modified independently.
+    pub async fn fork(&self) -> Version {
+        let inner = self.inner.lock().await;
+        Version {
+            inner: Arc::new(Mutex::new(inner.clone())),
+        }
+    }
+
     pub async fn generate_snapshot(&self, next_file_num: u64) -> VersionEdit {
         let inner = self.inner.lock().await;
         inner.generate_snapshot(next_file_num).await
     }
 
+    pub async fn tenant_options(&self) -> Vec<u8> {
+        let inner = self.inner.lock().await;
+        inner.tenant_options.clone()
+    }
+
+    pub async fn bucket_names(&self) -> Vec<String> {
+        let inner = self.inner.lock().await;
+        inner.buckets.keys().cloned().collect()
+    }
+
+    pub async fn bucket_files(&self, bucket: &str) -> HashSet<String> {
+        let inner = self.inner.lock().await;
+        inner
+            .buckets
+            .get(bucket)
+            .map(|b| b.files.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub async fn contains_file(&self, bucket: &str, name: &str) -> bool {
+        let inner = self.inner.lock().await;
+        inner
+            .buckets
+            .get(bucket)
+            .map_or(false, |b| b.files.contains_key(name))
+    }
+
     pub async fn bucket_version(&self, bucket: &str) -> Result<BucketVersion> {
         let inner = self.inner.lock().await;
         Ok(inner
@@ -103,18 +145,50 @@ impl Version {
     }
 }
 
+/// A version that doesn't keep its files alive.
+pub struct WeakVersion(Weak<Mutex<Inner>>);
+
+impl WeakVersion {
+    pub fn upgrade(&self) -> Option<Version> {
+        self.0.upgrade().map(|inner| Version { inner })
+    }
+}
+
 impl Inner {
     async fn apply(&mut self, ve: &VersionEdit) -> Result<()> {
+        if let Some(tenant_options) = &ve.tenant_options {
+            self.tenant_options = tenant_options.options.clone();
+        }
+        // Adding an existing bucket updates its options.
         for add_bucket in &ve.add_buckets {
-            match self.buckets.entry(add_bucket.name.to_owned()) {
-                btree_map::Entry::Vacant(ent) => Some(ent.insert(BucketVersion::default())),
-                btree_map::Entry::Occupied(_) => None,
+            let bucket = match self.buckets.entry(add_bucket.name.to_owned()) {
+                btree_map::Entry::Vacant(ent) => ent.insert(BucketVersion::default()),
+                btree_map::Entry::Occupied(ent) => ent.into_mut(),
             };
+            bucket.options = add_bucket.options.clone();
         }
         for bucket in &ve.remove_buckets {
             self.buckets.remove(bucket);
         }
 
+        // Files are removed first, since an added file might have the same
+        // upper bound as a removed one in the same level.
+        for file in &ve.remove_files {
+            if let Some(bucket) = self.buckets.get_mut(&file.bucket) {
+                let removed = bucket.files.remove(&file.name);
+                if let Some(f) = removed {
+                    if f.level == 0 {
+                        bucket.l0_level.retain(|e| e.name != f.name)
+                    } else {
+                        // Files in the same level are ordered by their upper bounds.
+                        bucket.non_l0_levels[(f.level - 1) as usize]
+                            .files
+                            .remove(&OrdByUpperBound(f));
+                    }
+                }
+            }
+        }
+
         for add_file in &ve.add_files {
             if let Some(bucket) = self.buckets.get_mut(&add_file.bucket) {
                 let file_meta = FileMetadata {
@@ -159,34 +233,22 @@ impl Inner {
                 return Err(Error::NotFound(format!("bucket {}", &add_file.bucket)));
             }
         }
-        for file in &ve.remove_files {
-            if let Some(bucket) = self.buckets.get_mut(&file.bucket) {
-                let removed = bucket.files.remove(&file.name);
-                if let Some(f) = removed {
-                    if f.level == 0 {
-                        bucket.l0_level.retain(|e| e.lower_bound != f.lower_bound)
-                    } else {
-                        bucket.non_l0_levels[(f.level - 1) as usize].files.remove(
-                            &OrdByUpperBound(FileMetadata {
-                                lower_bound: f.lower_bound,
-                                ..Default::default()
-                            }),
-                        );
-                    }
-                }
-            }
-        }
 
         Ok(())
     }
 
     async fn generate_snapshot(&self, next_file_num: u64) -> VersionEdit {
         let mut b = VersionEditBuilder::default();
+        b.set_tenant_options(self.tenant_options.clone());
         let mut buckets = Vec::new();
         for (name, bucket) in &self.buckets {
             buckets.push(version_edit::Bucket {
                 name: name.to_owned(),
+                options: bucket.options.clone(),
             });
+        }
+        b.add_buckets(buckets);
+        for bucket in self.buckets.values() {
             for file in bucket.files.values() {
                 b.add_files(vec![version_edit::File {
                     range_id: 1,
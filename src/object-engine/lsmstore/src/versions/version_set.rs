@@ -12,13 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::VecDeque, io, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashSet, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use prost::Message;
 use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
 
 use crate::{
-    versions::{proto::*, version::Version, *},
+    versions::{
+        proto::*,
+        version::{Version, WeakVersion},
+        *,
+    },
     *,
 };
 
@@ -30,6 +39,10 @@ pub struct VersionSet {
 struct VersionSetInner {
     path: PathBuf,
     versions: VecDeque<Version>,
+    // Versions replaced by newer ones, which might still be read.
+    old_versions: Vec<WeakVersion>,
+    // Files removed from versions, as (bucket, name).
+    obsolete_files: HashSet<(String, String)>,
     manifest: Option<manifest::Writer>,
     next_file_num: u64,
     current_file_num: u64,
@@ -40,6 +53,8 @@ impl VersionSet {
         let mut inner = VersionSetInner {
             path: path.into(),
             versions: VecDeque::new(),
+            old_versions: Vec::new(),
+            obsolete_files: HashSet::new(),
             manifest: None,
             next_file_num: 0,
             current_file_num: 0,
@@ -59,42 +74,59 @@ impl VersionSet {
 }
 
 impl VersionSet {
+    /// Returns true if there is a version set in `path`.
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        path.as_ref().join("CURRENT").exists()
+    }
+
     pub async fn current_version(&self) -> Version {
         let inner = self.inner.lock().await;
         inner.current_version()
     }
 
-    #[allow(dead_code)]
     pub async fn log_and_apply(&self, ve: VersionEdit) -> Result<()> {
         let mut inner = self.inner.lock().await;
+        inner.log_and_apply(ve).await
+    }
 
-        let rolleded = if match &inner.manifest {
-            Some(manifest) => manifest.accumulated_size().await > manifest::MAX_FILE_SIZE,
-            None => true, // new or restarted
-        } {
-            let new_file_num = inner.get_next_file_num();
-            let next_file_num = inner.next_file_num;
-            inner.create_manifest(new_file_num, next_file_num).await?;
-            inner.current_file_num = new_file_num;
-            true
-        } else {
-            false
-        };
-
-        let mut manifest = inner.manifest.take().unwrap();
-        manifest.append(&ve.encode_to_vec()).await?;
-        manifest.flush_and_sync(rolleded).await?;
-        inner.manifest = Some(manifest);
-        if rolleded {
-            inner.update_current(inner.current_file_num).await?;
+    /// Returns the files of the bucket that are referenced by any live
+    /// version.
+    pub async fn live_files(&self, bucket: &str) -> HashSet<String> {
+        let mut inner = self.inner.lock().await;
+        let mut files = HashSet::new();
+        for version in inner.live_versions() {
+            files.extend(version.bucket_files(bucket).await);
         }
+        files
+    }
 
-        let mut new_version = inner.current_version().to_owned();
-        new_version.apply(&ve).await?;
-
-        inner.versions.push_back(new_version);
+    /// Returns the files that have been removed from the current version and
+    /// are no longer referenced by any live version, as (bucket, name).
+    pub async fn deletable_files(&self) -> Vec<(String, String)> {
+        let mut inner = self.inner.lock().await;
+        let versions = inner.live_versions();
+        let mut files = Vec::new();
+        for (bucket, name) in &inner.obsolete_files {
+            let mut referenced = false;
+            for version in &versions {
+                if version.contains_file(bucket, name).await {
+                    referenced = true;
+                    break;
+                }
+            }
+            if !referenced {
+                files.push((bucket.to_owned(), name.to_owned()));
+            }
+        }
+        files
+    }
 
-        Ok(())
+    /// Stops tracking an obsolete file once it is deleted.
+    pub async fn forget_obsolete_file(&self, bucket: &str, name: &str) {
+        let mut inner = self.inner.lock().await;
+        inner
+            .obsolete_files
+            .remove(&(bucket.to_owned(), name.to_owned()));
     }
 
     pub async fn get_next_file_num(&self, count: u64) -> Result<Vec<u64>> {
@@ -106,17 +138,59 @@ impl VersionSet {
         for _ in 0..count {
             res.push(inner.get_next_file_num());
         }
+        // Logs the next file number so that the allocated numbers are not
+        // reused after restarts.
         let ve = VersionEditBuilder::default()
             .set_next_file_num(inner.next_file_num)
             .build();
-        let mut new_version = inner.current_version().to_owned();
-        new_version.apply(&ve).await?;
-        inner.versions.push_back(new_version);
+        inner.log_and_apply(ve).await?;
         Ok(res)
     }
 }
 
 impl VersionSetInner {
+    async fn log_and_apply(&mut self, ve: VersionEdit) -> Result<()> {
+        // Applies the edit to a copy first, so that an invalid edit is never
+        // logged.
+        let mut new_version = self.current_version().fork().await;
+        new_version.apply(&ve).await?;
+
+        let rolleded = if match &self.manifest {
+            Some(manifest) => manifest.accumulated_size().await > manifest::MAX_FILE_SIZE,
+            None => true, // new or restarted
+        } {
+            let new_file_num = self.get_next_file_num();
+            let next_file_num = self.next_file_num;
+            self.create_manifest(new_file_num, next_file_num).await?;
+            self.current_file_num = new_file_num;
+            true
+        } else {
+            false
+        };
+
+        let mut manifest = self.manifest.take().unwrap();
+        manifest.append(&ve.encode_to_vec()).await?;
+        manifest.flush_and_sync(rolleded).await?;
+        self.manifest = Some(manifest);
+        if rolleded {
+            self.update_current(self.current_file_num).await?;
+            self.delete_obsolete_manifests().await?;
+        }
+
+        self.versions.push_back(new_version);
+        // Only the current version is used by new readers.
+        if self.versions.len() > 1 {
+            let old_version = self.versions.pop_front().unwrap();
+            self.old_versions.push(old_version.downgrade());
+        }
+        for file in &ve.remove_files {
+            self.obsolete_files
+                .insert((file.bucket.to_owned(), file.name.to_owned()));
+        }
+
+        Ok(())
+    }
+
     fn get_next_file_num(&mut self) -> u64 {
         let num = self.next_file_num;
         self.next_file_num += 1;
@@ -127,6 +201,20 @@ impl VersionSetInner {
         self.versions.back().unwrap().to_owned()
     }
 
+    // Returns the current version and the old ones that are still read.
+    fn live_versions(&mut self) -> Vec<Version> {
+        let mut versions = Vec::new();
+        self.old_versions.retain(|v| match v.upgrade() {
+            Some(version) => {
+                versions.push(version);
+                true
+            }
+            None => false,
+        });
+        versions.push(self.current_version());
+        versions
+    }
+
     async fn create_manifest(&mut self, create_file_num: u64, next_file_num: u64) -> Result<()> {
         let file = {
             let filename = self.file_path(create_file_num);
@@ -234,6 +322,20 @@ impl VersionSetInner {
         Ok(Some(num.unwrap()))
     }
 
+    // Deletes the manifests that are superseded by the current one.
+    async fn delete_obsolete_manifests(&self) -> Result<()> {
+        let current = format!("MANIFEST-{:0>6}", self.current_file_num);
+        let mut dir = fs::read_dir(&self.path).await?;
+        while let Some(ent) = dir.next_entry().await? {
+            let name = ent.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("MANIFEST-") && name != current {
+                fs::remove_file(ent.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
     async fn update_current(&self, file_num: u64) -> Result<()> {
         let tmp_path = self.path.join(format!("CURRENT.{}.dbtmp", file_num));
         let curr_path = self.path.join("CURRENT");
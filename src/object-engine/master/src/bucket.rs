@@ -12,12 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use object_engine_filestore::SequentialWrite;
-use object_engine_lsmstore::{Bucket as VersionBucket, MergingIterator};
+use object_engine_lsmstore::{
+    Bucket as VersionBucket, CompactionOptions, MergeOperator, MergeOperators, SnapshotIter,
+    TableBuilderOptions, TableDesc, Timestamp,
+};
+use tokio::sync::Mutex;
 
-use crate::{proto::*, Result};
+use crate::{
+    proto::*,
+    snapshot::{Snapshot, SnapshotList},
+    Error, Result,
+};
 
 #[derive(Clone)]
 pub struct Bucket {
@@ -30,8 +38,9 @@ impl Bucket {
         tenant: String,
         options: BucketOptions,
         version_bucket: VersionBucket,
+        merge_operators: MergeOperators,
     ) -> Self {
-        let inner = BucketInner::new(name, tenant, options, version_bucket);
+        let inner = BucketInner::new(name, tenant, options, version_bucket, merge_operators);
         Self {
             inner: Arc::new(inner),
         }
@@ -49,24 +58,150 @@ impl Bucket {
         self.inner.desc().await
     }
 
+    pub(crate) async fn update(&self, options: BucketOptions) {
+        *self.inner.options.lock().await = options;
+    }
+
+    pub async fn table_options(&self) -> TableBuilderOptions {
+        self.inner.options.lock().await.table_options()
+    }
+
+    /// Returns the merge operator of the bucket, if any.
+    pub async fn merge_operator(&self) -> Result<Option<Arc<dyn MergeOperator>>> {
+        let name = self.inner.options.lock().await.merge_operator.clone();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        self.inner
+            .merge_operators
+            .get(&name)
+            .map(Some)
+            .ok_or_else(|| Error::invalid_argument(format!("unknown merge operator {}", name)))
+    }
+
     pub async fn new_sequential_writer(&self, name: &str) -> Result<Box<dyn SequentialWrite>> {
         self.inner.version_bucket.new_sequential_writer(name).await
     }
 
+    /// Registers a snapshot at the timestamp.
+    ///
+    /// Returns an error if versions visible at the timestamp might have been
+    /// compacted away.
+    pub fn snapshot(&self, ts: Timestamp) -> Result<Snapshot> {
+        self.inner.snapshots.acquire(ts)
+    }
+
     pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        self.inner.version_bucket.get(key).await
+        self.get_at(key, Timestamp::MAX).await
+    }
+
+    /// Returns the value of the key that is visible at the timestamp.
+    pub async fn get_at(&self, key: &[u8], ts: Timestamp) -> Result<Option<Vec<u8>>> {
+        let _snapshot = self.snapshot(ts)?;
+        let merge_operator = self.merge_operator().await?;
+        self.inner.version_bucket.get(key, ts, merge_operator).await
+    }
+
+    pub async fn iter(&self) -> Result<BucketIter> {
+        self.iter_at(Timestamp::MAX).await
+    }
+
+    /// Returns an iterator over the values that are visible at the
+    /// timestamp.
+    pub async fn iter_at(&self, ts: Timestamp) -> Result<BucketIter> {
+        // Acquires the snapshot first, so that no visible versions are
+        // compacted away before the iterator is created.
+        let snapshot = self.snapshot(ts)?;
+        let merge_operator = self.merge_operator().await?;
+        let iter = self.inner.version_bucket.iter(ts, merge_operator).await?;
+        Ok(BucketIter {
+            iter,
+            _snapshot: snapshot,
+        })
+    }
+
+    pub(crate) async fn list_files(&self) -> Result<Vec<String>> {
+        self.inner.version_bucket.list_files().await
+    }
+
+    pub(crate) async fn live_files(&self) -> Result<HashSet<String>> {
+        self.inner.version_bucket.live_files().await
+    }
+
+    pub(crate) async fn table_desc(&self, name: &str) -> Result<TableDesc> {
+        self.inner.version_bucket.table_desc(name).await
+    }
+
+    /// Compacts the bucket, which keeps the versions visible to the live
+    /// snapshots.
+    pub(crate) async fn compact(&self, options: &CompactionOptions) -> Result<bool> {
+        let table_options = self.table_options().await;
+        let merge_operator = self.merge_operator().await?;
+        let oldest_snapshot = self.inner.snapshots.start_compaction();
+        let res = self
+            .inner
+            .version_bucket
+            .compact(
+                options,
+                &table_options,
+                merge_operator.as_deref(),
+                oldest_snapshot,
+            )
+            .await;
+        let compacted = matches!(res, Ok(true));
+        self.inner
+            .snapshots
+            .finish_compaction(oldest_snapshot, compacted);
+        res
+    }
+
+    pub(crate) async fn delete_file(&self, name: &str) -> Result<()> {
+        self.inner.version_bucket.delete_file(name).await
+    }
+}
+
+/// An iterator over the values of a bucket that are visible at a timestamp.
+///
+/// It holds a snapshot at the timestamp, so the versions it reads are kept
+/// from compactions until it is dropped.
+pub struct BucketIter {
+    iter: SnapshotIter,
+    _snapshot: Snapshot,
+}
+
+impl BucketIter {
+    pub fn id(&self) -> &[u8] {
+        self.iter.id()
+    }
+
+    pub fn value(&self) -> &[u8] {
+        self.iter.value()
+    }
+
+    pub fn valid(&self) -> bool {
+        self.iter.valid()
+    }
+
+    pub async fn seek_to_first(&mut self) -> Result<()> {
+        self.iter.seek_to_first().await
+    }
+
+    pub async fn seek(&mut self, target: &[u8]) -> Result<()> {
+        self.iter.seek(target).await
     }
 
-    pub async fn iter(&self) -> Result<MergingIterator> {
-        self.inner.version_bucket.iter().await
+    pub async fn next(&mut self) -> Result<()> {
+        self.iter.next().await
     }
 }
 
 struct BucketInner {
     name: String,
     tenant: String,
-    options: BucketOptions,
+    options: Mutex<BucketOptions>,
     version_bucket: VersionBucket,
+    merge_operators: MergeOperators,
+    snapshots: SnapshotList,
 }
 
 impl BucketInner {
@@ -75,12 +210,15 @@ impl BucketInner {
         tenant: String,
         options: BucketOptions,
         version_bucket: VersionBucket,
+        merge_operators: MergeOperators,
     ) -> Self {
         Self {
             name,
             tenant,
-            options,
+            options: Mutex::new(options),
             version_bucket,
+            merge_operators,
+            snapshots: SnapshotList::default(),
         }
     }
 
@@ -88,7 +226,7 @@ impl BucketInner {
         BucketDesc {
             name: self.name.clone(),
             tenant: self.tenant.clone(),
-            options: Some(self.options.clone()),
+            options: Some(self.options.lock().await.clone()),
             properties: None,
         }
     }
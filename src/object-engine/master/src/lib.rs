@@ -17,8 +17,15 @@ mod fs;
 mod master;
 pub mod proto;
 mod server;
+mod snapshot;
 mod tenant;
 
 use object_engine_common::{Error, Result};
 
-pub use self::{bucket::Bucket, master::Master, server::Server, tenant::Tenant};
+pub use self::{
+    bucket::{Bucket, BucketIter},
+    master::{Config, Master},
+    server::Server,
+    snapshot::Snapshot,
+    tenant::Tenant,
+};
@@ -12,12 +12,121 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
-use object_engine_lsmstore::Store;
+use object_engine_lsmstore::{CompactionOptions, MergeOperators, Store, StoreOptions};
+use prost::Message;
 use tokio::sync::Mutex;
+use tracing::warn;
 
-use crate::{fs, proto::*, Error, Result, Tenant};
+use crate::{fs, proto::*, Bucket, Error, Result, Tenant};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How long in ms a bulk load can be idle before its token expires.
+    ///
+    /// The lease is renewed whenever the bulk load allocates file names.
+    ///
+    /// Default: 10 minutes
+    pub bulkload_lease_ms: u64,
+
+    /// Intervals in ms to expire bulk loads and delete orphaned files.
+    ///
+    /// Default: 1 minute
+    pub sweep_interval_ms: u64,
+
+    /// Intervals in ms to check whether buckets need compactions.
+    ///
+    /// Default: 10 seconds
+    pub compaction_interval_ms: u64,
+
+    /// The number of files in level 0 to trigger a compaction.
+    ///
+    /// Default: 4
+    pub l0_compaction_trigger: usize,
+
+    /// The target size of level 1 in bytes.
+    ///
+    /// Default: 64MB
+    pub level1_target_size: u64,
+
+    /// The ratio of the target size of a level to that of the level above.
+    ///
+    /// Default: 10
+    pub level_size_multiplier: u64,
+
+    /// The target size of compaction outputs in bytes.
+    ///
+    /// Default: 8MB
+    pub target_file_size: u64,
+
+    /// The number of tables to keep open, or 0 to disable the table cache.
+    ///
+    /// Default: 1000
+    pub table_cache_size: usize,
+
+    /// The capacity of the block cache in bytes, or 0 to disable it.
+    ///
+    /// Default: 64MB
+    pub block_cache_size: usize,
+
+    /// Merge operators that buckets can refer to by names.
+    ///
+    /// Default: the built-in operators
+    pub merge_operators: MergeOperators,
+}
+
+impl Config {
+    pub fn bulkload_lease(&self) -> Duration {
+        Duration::from_millis(self.bulkload_lease_ms)
+    }
+
+    pub fn sweep_interval(&self) -> Duration {
+        Duration::from_millis(self.sweep_interval_ms)
+    }
+
+    pub fn compaction_interval(&self) -> Duration {
+        Duration::from_millis(self.compaction_interval_ms)
+    }
+
+    pub fn compaction_options(&self) -> CompactionOptions {
+        CompactionOptions {
+            l0_compaction_trigger: self.l0_compaction_trigger,
+            level1_target_size: self.level1_target_size,
+            level_size_multiplier: self.level_size_multiplier,
+            target_file_size: self.target_file_size,
+        }
+    }
+
+    pub fn store_options(&self) -> StoreOptions {
+        StoreOptions {
+            table_cache_size: self.table_cache_size,
+            block_cache_size: self.block_cache_size,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bulkload_lease_ms: 10 * 60 * 1000,
+            sweep_interval_ms: 60 * 1000,
+            compaction_interval_ms: 10 * 1000,
+            l0_compaction_trigger: 4,
+            level1_target_size: 64 << 20,
+            level_size_multiplier: 10,
+            target_file_size: 8 << 20,
+            table_cache_size: 1000,
+            block_cache_size: 64 << 20,
+            merge_operators: MergeOperators::default(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Master {
@@ -26,18 +135,35 @@ pub struct Master {
 
 impl Master {
     pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with_config(path, Config::default()).await
+    }
+
+    pub async fn open_with_config(path: impl Into<PathBuf>, config: Config) -> Result<Self> {
         let path = path.into();
         let file_store = fs::open(path.to_owned()).await?;
-        let lsm_store = Store::new(path.to_owned(), None, file_store).await?;
-        let inner = MasterInner::new(lsm_store);
-        Ok(Self {
-            inner: Arc::new(inner),
-        })
+        let lsm_store =
+            Store::new(path.to_owned(), None, file_store, config.store_options()).await?;
+        let inner = Arc::new(MasterInner::open(config, lsm_store).await?);
+        tokio::spawn(sweep_periodically(Arc::downgrade(&inner)));
+        tokio::spawn(compact_periodically(Arc::downgrade(&inner)));
+        Ok(Self { inner })
     }
 
     pub async fn tenant(&self, name: &str) -> Result<Tenant> {
         self.inner.tenant(name).await
     }
+
+    /// Expires bulk loads and deletes unused files now, regardless of the
+    /// sweep interval.
+    pub async fn sweep(&self) {
+        self.inner.sweep().await
+    }
+
+    /// Compacts the buckets that need compactions now, regardless of the
+    /// compaction interval.
+    pub async fn compact(&self) {
+        self.inner.compact().await
+    }
 }
 
 impl Master {
@@ -55,35 +181,41 @@ impl Master {
             .request
             .ok_or_else(|| Error::invalid_argument("missing request"))?;
         let res = match req {
-            request_union::Request::ListTenants(_req) => {
-                todo!();
+            request_union::Request::ListTenants(req) => {
+                let res = self.handle_list_tenants(req).await?;
+                response_union::Response::ListTenants(res)
             }
             request_union::Request::CreateTenant(req) => {
                 let res = self.handle_create_tenant(req).await?;
                 response_union::Response::CreateTenant(res)
             }
-            request_union::Request::UpdateTenant(_req) => {
-                todo!();
+            request_union::Request::UpdateTenant(req) => {
+                let res = self.handle_update_tenant(req).await?;
+                response_union::Response::UpdateTenant(res)
             }
-            request_union::Request::DeleteTenant(_req) => {
-                todo!();
+            request_union::Request::DeleteTenant(req) => {
+                let res = self.handle_delete_tenant(req).await?;
+                response_union::Response::DeleteTenant(res)
             }
             request_union::Request::DescribeTenant(req) => {
                 let res = self.handle_describe_tenant(req).await?;
                 response_union::Response::DescribeTenant(res)
             }
-            request_union::Request::ListBuckets(_req) => {
-                todo!();
+            request_union::Request::ListBuckets(req) => {
+                let res = self.handle_list_buckets(req).await?;
+                response_union::Response::ListBuckets(res)
             }
             request_union::Request::CreateBucket(req) => {
                 let res = self.handle_create_bucket(req).await?;
                 response_union::Response::CreateBucket(res)
             }
-            request_union::Request::UpdateBucket(_req) => {
-                todo!();
+            request_union::Request::UpdateBucket(req) => {
+                let res = self.handle_update_bucket(req).await?;
+                response_union::Response::UpdateBucket(res)
             }
-            request_union::Request::DeleteBucket(_req) => {
-                todo!();
+            request_union::Request::DeleteBucket(req) => {
+                let res = self.handle_delete_bucket(req).await?;
+                response_union::Response::DeleteBucket(res)
             }
             request_union::Request::DescribeBucket(req) => {
                 let res = self.handle_describe_bucket(req).await?;
@@ -101,12 +233,29 @@ impl Master {
                 let res = self.handle_allocate_filenames(req).await?;
                 response_union::Response::AllocateFileNames(res)
             }
+            request_union::Request::AbortBulkload(req) => {
+                let res = self.handle_abort_bulk_load(req).await?;
+                response_union::Response::AbortBulkload(res)
+            }
+            request_union::Request::GetCacheStats(req) => {
+                let res = self.handle_get_cache_stats(req).await?;
+                response_union::Response::GetCacheStats(res)
+            }
         };
         Ok(ResponseUnion {
             response: Some(res),
         })
     }
 
+    async fn handle_list_tenants(&self, _: ListTenantsRequest) -> Result<ListTenantsResponse> {
+        let mut descs = Vec::new();
+        for tenant in self.inner.tenants().await {
+            descs.push(tenant.desc().await);
+        }
+        descs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ListTenantsResponse { descs })
+    }
+
     async fn handle_create_tenant(&self, req: CreateTenantRequest) -> Result<CreateTenantResponse> {
         let tenant = self
             .inner
@@ -117,6 +266,22 @@ impl Master {
         })
     }
 
+    async fn handle_update_tenant(&self, req: UpdateTenantRequest) -> Result<UpdateTenantResponse> {
+        let tenant = self.tenant(&req.name).await?;
+        let options = req
+            .options
+            .ok_or_else(|| Error::invalid_argument("missing tenant options"))?;
+        tenant.update(options).await?;
+        Ok(UpdateTenantResponse {
+            desc: Some(tenant.desc().await),
+        })
+    }
+
+    async fn handle_delete_tenant(&self, req: DeleteTenantRequest) -> Result<DeleteTenantResponse> {
+        self.inner.delete_tenant(&req.name).await?;
+        Ok(DeleteTenantResponse {})
+    }
+
     async fn handle_describe_tenant(
         &self,
         req: DescribeTenantRequest,
@@ -127,18 +292,45 @@ impl Master {
         })
     }
 
-    async fn handle_create_bucket(&self, req: CreateBucketRequest) -> Result<CreateBucketResponse> {
+    async fn handle_list_buckets(&self, req: ListBucketsRequest) -> Result<ListBucketsResponse> {
         let tenant = self.tenant(&req.tenant).await?;
+        let mut descs = Vec::new();
+        for bucket in tenant.buckets().await {
+            descs.push(bucket.desc().await);
+        }
+        descs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(ListBucketsResponse { descs })
+    }
 
-        let bucket = tenant
-            .create_bucket(&req.bucket, req.options.unwrap_or_default())
-            .await?;
+    async fn handle_create_bucket(&self, req: CreateBucketRequest) -> Result<CreateBucketResponse> {
+        let tenant = self.tenant(&req.tenant).await?;
+        let options = req.options.unwrap_or_default();
+        validate_bucket_options(&options, &self.inner.config.merge_operators)?;
+        let bucket = tenant.create_bucket(&req.bucket, options).await?;
 
         Ok(CreateBucketResponse {
             desc: Some(bucket.desc().await),
         })
     }
 
+    async fn handle_update_bucket(&self, req: UpdateBucketRequest) -> Result<UpdateBucketResponse> {
+        let tenant = self.tenant(&req.tenant).await?;
+        let options = req
+            .options
+            .ok_or_else(|| Error::invalid_argument("missing bucket options"))?;
+        validate_bucket_options(&options, &self.inner.config.merge_operators)?;
+        let bucket = tenant.update_bucket(&req.bucket, options).await?;
+        Ok(UpdateBucketResponse {
+            desc: Some(bucket.desc().await),
+        })
+    }
+
+    async fn handle_delete_bucket(&self, req: DeleteBucketRequest) -> Result<DeleteBucketResponse> {
+        let tenant = self.tenant(&req.tenant).await?;
+        tenant.delete_bucket(&req.bucket).await?;
+        Ok(DeleteBucketResponse {})
+    }
+
     async fn handle_describe_bucket(
         &self,
         req: DescribeBucketRequest,
@@ -154,11 +346,8 @@ impl Master {
         &self,
         req: BeginBulkLoadRequest,
     ) -> Result<BeginBulkLoadResponse> {
-        let tenant = req.tenant;
-        let uid = uuid::Uuid::new_v4().to_string();
-        let token = tenant.to_owned() + &uid;
-        let mut in_progress = self.inner.in_progress.lock().await;
-        in_progress.insert(token.to_owned(), TokenCtx { tenant });
+        let tenant = self.tenant(&req.tenant).await?;
+        let token = self.inner.begin_bulk_load(tenant.name()).await;
         Ok(BeginBulkLoadResponse { token })
     }
 
@@ -167,78 +356,148 @@ impl Master {
         req: CommitBulkLoadRequest,
     ) -> Result<CommitBulkLoadResponse> {
         let token = req.token;
-        let tenant = self
-            .inner
-            .in_progress
-            .lock()
-            .await
-            .get(&token)
-            .ok_or_else(|| Error::NotFound("bulk load token".to_string()))?
-            .tenant
-            .to_owned();
+        let (tenant, allocated) = self.inner.start_commit(&token).await?;
+        let res = self.commit_bulk_load(&tenant, &allocated, req.files).await;
+        self.inner.finish_commit(&token, res.is_ok()).await;
+        res?;
+        Ok(CommitBulkLoadResponse {})
+    }
 
+    async fn commit_bulk_load(
+        &self,
+        tenant: &str,
+        allocated: &HashSet<String>,
+        descs: Vec<BulkLoadFileDesc>,
+    ) -> Result<()> {
+        let tenant = self.tenant(tenant).await?;
+        let mut names = HashSet::new();
         let mut files = Vec::new();
-        for desc in req.files {
+        // Validates all files before adding any of them, so that an invalid
+        // file rejects the whole commit.
+        for desc in descs {
+            if !allocated.contains(&desc.file_name) {
+                return Err(Error::invalid_argument(format!(
+                    "file {} is not allocated by the bulk load",
+                    desc.file_name
+                )));
+            }
+            if !names.insert(desc.file_name.clone()) {
+                return Err(Error::invalid_argument(format!(
+                    "file {} is committed twice",
+                    desc.file_name
+                )));
+            }
+            let bucket = tenant.bucket(&desc.bucket).await?;
+            validate_file(&bucket, &desc).await?;
             let version_edit = object_engine_lsmstore::VersionEditFile {
                 bucket: desc.bucket.to_owned(),
                 lower_bound: desc.lower_bound.to_owned(),
                 upper_bound: desc.upper_bound.to_owned(),
                 name: desc.file_name.to_owned(),
-                tenant: tenant.to_owned(),
+                tenant: tenant.name().to_owned(),
                 file_size: desc.file_size.to_owned(),
                 range_id: 0,
                 level: 0,
             };
             files.push(version_edit);
         }
-        let tenant = self.tenant(&tenant).await?;
-        tenant.add_files(files).await?;
-        Ok(CommitBulkLoadResponse {})
+        tenant.add_files(files).await
+    }
+
+    async fn handle_abort_bulk_load(
+        &self,
+        req: AbortBulkLoadRequest,
+    ) -> Result<AbortBulkLoadResponse> {
+        self.inner.abort_bulk_load(&req.token).await?;
+        Ok(AbortBulkLoadResponse {})
     }
 
     async fn handle_allocate_filenames(
         &self,
         req: AllocateFileNamesRequest,
     ) -> Result<AllocateFileNamesResponse> {
-        let token = req.token;
-        let count = req.count;
-        let tenant = self
+        let names = self
             .inner
-            .in_progress
-            .lock()
-            .await
-            .get(&token)
-            .ok_or_else(|| Error::NotFound("bulk load token".to_string()))?
-            .tenant
-            .to_owned();
-
-        let mut names = Vec::with_capacity(count as usize);
-        let file_nums = self.tenant(&tenant).await?.get_next_file_num(count).await?;
-        for num in file_nums {
-            names.push(format!("{:0>6}.sst", num));
-        }
-
+            .allocate_file_names(&req.token, req.count)
+            .await?;
         Ok(AllocateFileNamesResponse { names })
     }
+
+    async fn handle_get_cache_stats(
+        &self,
+        _: GetCacheStatsRequest,
+    ) -> Result<GetCacheStatsResponse> {
+        let store = &self.inner.store;
+        Ok(GetCacheStatsResponse {
+            table_cache: Some(store.table_cache_stats().await.into()),
+            block_cache: Some(store.block_cache_stats().await.into()),
+        })
+    }
 }
 
 struct MasterInner {
+    config: Config,
     tenants: Mutex<HashMap<String, Tenant>>,
     in_progress: Mutex<HashMap<String, TokenCtx>>,
+    // Held while compacting or sweeping a bucket, so that the outputs of a
+    // compaction are not swept before they are added to the version.
+    maintenance: Mutex<()>,
     store: Store,
 }
 
 struct TokenCtx {
     tenant: String,
+    deadline: Instant,
+    committing: bool,
+    // The files allocated to this bulk load, which must not be swept before
+    // the bulk load finishes.
+    file_names: HashSet<String>,
+}
+
+impl TokenCtx {
+    fn is_expired(&self, now: Instant) -> bool {
+        !self.committing && self.deadline <= now
+    }
+}
+
+// Returns the context of a bulk load that accepts requests.
+fn active_token<'a>(
+    in_progress: &'a mut HashMap<String, TokenCtx>,
+    token: &str,
+) -> Result<&'a mut TokenCtx> {
+    let ctx = in_progress
+        .get_mut(token)
+        .filter(|ctx| !ctx.is_expired(Instant::now()))
+        .ok_or_else(|| Error::NotFound("bulk load token".to_string()))?;
+    if ctx.committing {
+        return Err(Error::invalid_argument("the bulk load is being committed"));
+    }
+    Ok(ctx)
 }
 
 impl MasterInner {
-    fn new(store: Store) -> Self {
-        Self {
-            tenants: Mutex::new(HashMap::new()),
+    async fn open(config: Config, store: Store) -> Result<Self> {
+        let mut tenants = HashMap::new();
+        for name in store.tenants().await {
+            let versions_tenant = store.tenant(&name).await?;
+            let options = TenantOptions::decode(versions_tenant.options().await?.as_slice())
+                .map_err(|err| Error::corrupted(format!("tenant {} options: {}", name, err)))?;
+            let tenant = Tenant::open(
+                name.clone(),
+                options,
+                versions_tenant,
+                config.merge_operators.clone(),
+            )
+            .await?;
+            tenants.insert(name, tenant);
+        }
+        Ok(Self {
+            config,
+            tenants: Mutex::new(tenants),
             in_progress: Mutex::new(HashMap::new()),
+            maintenance: Mutex::new(()),
             store,
-        }
+        })
     }
 
     async fn tenant(&self, name: &str) -> Result<Tenant> {
@@ -249,6 +508,11 @@ impl MasterInner {
             .ok_or_else(|| Error::NotFound(format!("tenant {}", name)))
     }
 
+    async fn tenants(&self) -> Vec<Tenant> {
+        let tenants = self.tenants.lock().await;
+        tenants.values().cloned().collect()
+    }
+
     async fn create_tenant(&self, name: &str, options: TenantOptions) -> Result<Tenant> {
         let mut tenants = self.tenants.lock().await;
         if tenants.contains_key(name) {
@@ -256,9 +520,238 @@ impl MasterInner {
         }
         self.store.create_tenant(name).await?;
         let versions_tenant = self.store.tenant(name).await?;
+        versions_tenant
+            .update_options(options.encode_to_vec())
+            .await?;
 
-        let tenant = Tenant::new(name.to_owned(), options, versions_tenant);
+        let tenant = Tenant::new(
+            name.to_owned(),
+            options,
+            versions_tenant,
+            self.config.merge_operators.clone(),
+        );
         tenants.insert(name.to_owned(), tenant.clone());
         Ok(tenant)
     }
+
+    async fn delete_tenant(&self, name: &str) -> Result<()> {
+        let mut tenants = self.tenants.lock().await;
+        if !tenants.contains_key(name) {
+            return Err(Error::NotFound(format!("tenant {}", name)));
+        }
+        self.store.delete_tenant(name).await?;
+        tenants.remove(name);
+        // Bulk loads in progress can't be committed to a deleted tenant.
+        let mut in_progress = self.in_progress.lock().await;
+        in_progress.retain(|_, ctx| ctx.tenant != name);
+        Ok(())
+    }
+
+    async fn begin_bulk_load(&self, tenant: &str) -> String {
+        let token = tenant.to_owned() + &uuid::Uuid::new_v4().to_string();
+        let ctx = TokenCtx {
+            tenant: tenant.to_owned(),
+            deadline: Instant::now() + self.config.bulkload_lease(),
+            committing: false,
+            file_names: HashSet::new(),
+        };
+        let mut in_progress = self.in_progress.lock().await;
+        in_progress.insert(token.clone(), ctx);
+        token
+    }
+
+    async fn allocate_file_names(&self, token: &str, count: u64) -> Result<Vec<String>> {
+        let tenant = {
+            let mut in_progress = self.in_progress.lock().await;
+            active_token(&mut in_progress, token)?.tenant.clone()
+        };
+        let file_nums = self.tenant(&tenant).await?.get_next_file_num(count).await?;
+        let names: Vec<String> = file_nums
+            .into_iter()
+            .map(|num| format!("{:0>6}.sst", num))
+            .collect();
+
+        let mut in_progress = self.in_progress.lock().await;
+        let ctx = active_token(&mut in_progress, token)?;
+        ctx.file_names.extend(names.iter().cloned());
+        ctx.deadline = Instant::now() + self.config.bulkload_lease();
+        Ok(names)
+    }
+
+    /// Marks a bulk load as committing and returns its tenant and allocated
+    /// files.
+    async fn start_commit(&self, token: &str) -> Result<(String, HashSet<String>)> {
+        let mut in_progress = self.in_progress.lock().await;
+        let ctx = active_token(&mut in_progress, token)?;
+        ctx.committing = true;
+        Ok((ctx.tenant.clone(), ctx.file_names.clone()))
+    }
+
+    /// Removes a committed bulk load, or allows a failed one to retry.
+    async fn finish_commit(&self, token: &str, committed: bool) {
+        let mut in_progress = self.in_progress.lock().await;
+        if committed {
+            in_progress.remove(token);
+        } else if let Some(ctx) = in_progress.get_mut(token) {
+            ctx.committing = false;
+            ctx.deadline = Instant::now() + self.config.bulkload_lease();
+        }
+    }
+
+    async fn abort_bulk_load(&self, token: &str) -> Result<()> {
+        let mut in_progress = self.in_progress.lock().await;
+        active_token(&mut in_progress, token)?;
+        // The written files are deleted by the sweeper.
+        in_progress.remove(token);
+        Ok(())
+    }
+
+    async fn sweep(&self) {
+        let now = Instant::now();
+        self.in_progress
+            .lock()
+            .await
+            .retain(|_, ctx| !ctx.is_expired(now));
+
+        for tenant in self.tenants().await {
+            if let Err(err) = tenant.delete_obsolete_files().await {
+                warn!(tenant = tenant.name(), "delete obsolete files: {}", err);
+            }
+            for bucket in tenant.buckets().await {
+                if let Err(err) = self.sweep_bucket(&bucket).await {
+                    warn!(
+                        tenant = bucket.tenant(),
+                        bucket = bucket.name(),
+                        "sweep orphaned files: {}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    async fn sweep_bucket(&self, bucket: &Bucket) -> Result<()> {
+        let _guard = self.maintenance.lock().await;
+        // Lists the files before collecting the referenced ones. Since a bulk
+        // load adds its files to the version before it releases the token, a
+        // listed file that is referenced by neither is orphaned.
+        let files = bucket.list_files().await?;
+        let in_progress: HashSet<String> = {
+            let in_progress = self.in_progress.lock().await;
+            in_progress
+                .values()
+                .filter(|ctx| ctx.tenant == bucket.tenant())
+                .flat_map(|ctx| ctx.file_names.iter().cloned())
+                .collect()
+        };
+        let live = bucket.live_files().await?;
+        for name in files {
+            if name.ends_with(".sst") && !in_progress.contains(&name) && !live.contains(&name) {
+                bucket.delete_file(&name).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn compact(&self) {
+        let options = self.config.compaction_options();
+        for tenant in self.tenants().await {
+            for bucket in tenant.buckets().await {
+                if let Err(err) = self.compact_bucket(&bucket, &options).await {
+                    warn!(
+                        tenant = bucket.tenant(),
+                        bucket = bucket.name(),
+                        "compact bucket: {}",
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    async fn compact_bucket(&self, bucket: &Bucket, options: &CompactionOptions) -> Result<()> {
+        loop {
+            let _guard = self.maintenance.lock().await;
+            if !bucket.compact(options).await? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn validate_bucket_options(
+    options: &BucketOptions,
+    merge_operators: &MergeOperators,
+) -> Result<()> {
+    if Compression::from_i32(options.compression).is_none() {
+        return Err(Error::invalid_argument(format!(
+            "unknown compression {}",
+            options.compression
+        )));
+    }
+    if !options.merge_operator.is_empty() && merge_operators.get(&options.merge_operator).is_none()
+    {
+        return Err(Error::invalid_argument(format!(
+            "unknown merge operator {}",
+            options.merge_operator
+        )));
+    }
+    Ok(())
+}
+
+// Checks that a file matches its descriptor.
+async fn validate_file(bucket: &Bucket, desc: &BulkLoadFileDesc) -> Result<()> {
+    let invalid = |msg: String| {
+        Error::invalid_argument(format!("file {} is invalid: {}", desc.file_name, msg))
+    };
+    let table = bucket
+        .table_desc(&desc.file_name)
+        .await
+        .map_err(|err| invalid(err.to_string()))?;
+    if table.table_size as u64 != desc.file_size {
+        return Err(invalid(format!(
+            "the size is {}, but {} is given",
+            table.table_size, desc.file_size
+        )));
+    }
+    if table.lower_bound != desc.lower_bound || table.upper_bound != desc.upper_bound {
+        return Err(invalid("the key bounds mismatch".to_owned()));
+    }
+    Ok(())
+}
+
+async fn sweep_periodically(inner: Weak<MasterInner>) {
+    let interval = match inner.upgrade() {
+        Some(inner) => inner.config.sweep_interval(),
+        None => return,
+    };
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        // Stops when the master is dropped.
+        match inner.upgrade() {
+            Some(inner) => inner.sweep().await,
+            None => break,
+        }
+    }
+}
+
+async fn compact_periodically(inner: Weak<MasterInner>) {
+    let interval = match inner.upgrade() {
+        Some(inner) => inner.config.compaction_interval(),
+        None => return,
+    };
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        // Stops when the master is dropped.
+        match inner.upgrade() {
+            Some(inner) => inner.compact().await,
+            None => break,
+        }
+    }
 }
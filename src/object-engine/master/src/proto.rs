@@ -15,3 +15,31 @@
 #![allow(clippy::all)]
 
 tonic::include_proto!("objectengine.master.v1");
+
+use object_engine_lsmstore::{CompressionType, TableBuilderOptions};
+
+impl From<object_engine_lsmstore::CacheStats> for CacheStats {
+    fn from(stats: object_engine_lsmstore::CacheStats) -> Self {
+        Self {
+            hits: stats.hits,
+            misses: stats.misses,
+            usage: stats.usage as u64,
+            capacity: stats.capacity as u64,
+        }
+    }
+}
+
+impl BucketOptions {
+    /// Returns the options to build tables in the bucket.
+    pub fn table_options(&self) -> TableBuilderOptions {
+        let compression = match self.compression() {
+            Compression::None => CompressionType::None,
+            Compression::Lz4 => CompressionType::Lz4,
+            Compression::Zstd => CompressionType::Zstd,
+        };
+        TableBuilderOptions {
+            compression,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use object_engine_lsmstore::Timestamp;
+
+use crate::{Error, Result};
+
+/// The timestamps of the live snapshots of a bucket.
+///
+/// Compactions keep the versions that are visible to these snapshots.
+#[derive(Clone, Default)]
+pub(crate) struct SnapshotList {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    // Timestamp -> the number of snapshots at it.
+    snapshots: BTreeMap<Timestamp, usize>,
+    // Timestamp -> the number of running compactions that keep the versions
+    // visible at it.
+    compactions: BTreeMap<Timestamp, usize>,
+    // Versions that are invisible at this timestamp might have been
+    // compacted away.
+    compacted: Timestamp,
+}
+
+impl SnapshotList {
+    /// Acquires a snapshot at the timestamp.
+    ///
+    /// Returns an error if versions visible at the timestamp might have been
+    /// compacted away, either by a finished compaction or a running one.
+    pub fn acquire(&self, ts: Timestamp) -> Result<Snapshot> {
+        let mut state = self.state.lock().unwrap();
+        let running = state.compactions.keys().next_back().cloned();
+        let compacted = running.map_or(state.compacted, |x| x.max(state.compacted));
+        if ts < compacted {
+            return Err(Error::invalid_argument(format!(
+                "timestamp {} is below the compacted timestamp {}",
+                ts, compacted
+            )));
+        }
+        *state.snapshots.entry(ts).or_default() += 1;
+        Ok(Snapshot {
+            ts,
+            list: self.clone(),
+        })
+    }
+
+    /// Starts a compaction, which keeps the versions visible to the oldest
+    /// live snapshot, or only the latest versions if there is none.
+    ///
+    /// Returns the timestamp of the oldest live snapshot, or the latest
+    /// timestamp if there is none. Snapshots below it can't be acquired until
+    /// the compaction is finished.
+    pub fn start_compaction(&self) -> Timestamp {
+        let mut state = self.state.lock().unwrap();
+        let ts = state
+            .snapshots
+            .keys()
+            .next()
+            .cloned()
+            .unwrap_or(Timestamp::MAX);
+        *state.compactions.entry(ts).or_default() += 1;
+        ts
+    }
+
+    /// Finishes a compaction started at `ts`.
+    ///
+    /// If the compaction has replaced any files, snapshots below `ts` can't be
+    /// acquired anymore.
+    pub fn finish_compaction(&self, ts: Timestamp, compacted: bool) {
+        let mut state = self.state.lock().unwrap();
+        remove(&mut state.compactions, ts);
+        if compacted {
+            state.compacted = state.compacted.max(ts);
+        }
+    }
+
+    fn release(&self, ts: Timestamp) {
+        let mut state = self.state.lock().unwrap();
+        remove(&mut state.snapshots, ts);
+    }
+}
+
+fn remove(counts: &mut BTreeMap<Timestamp, usize>, ts: Timestamp) {
+    let count = counts.get_mut(&ts).unwrap();
+    *count -= 1;
+    if *count == 0 {
+        counts.remove(&ts);
+    }
+}
+
+/// A snapshot of a bucket at a timestamp, which keeps the versions visible to
+/// it from compactions until it is dropped.
+///
+/// A snapshot can't be acquired below the timestamps that compactions have
+/// dropped versions at.
+pub struct Snapshot {
+    ts: Timestamp,
+    list: SnapshotList,
+}
+
+impl Snapshot {
+    pub fn timestamp(&self) -> Timestamp {
+        self.ts
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.list.release(self.ts);
+    }
+}
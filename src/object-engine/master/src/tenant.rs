@@ -14,7 +14,8 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use object_engine_lsmstore::{Tenant as VersionTenant, VersionEditFile};
+use object_engine_lsmstore::{MergeOperators, Tenant as VersionTenant, VersionEditFile};
+use prost::Message;
 use tokio::sync::Mutex;
 
 use crate::{proto::*, Bucket, Error, Result};
@@ -25,13 +26,44 @@ pub struct Tenant {
 }
 
 impl Tenant {
-    pub fn new(name: String, options: TenantOptions, versions_tenant: VersionTenant) -> Self {
-        let inner = TenantInner::new(name, options, versions_tenant);
+    pub fn new(
+        name: String,
+        options: TenantOptions,
+        versions_tenant: VersionTenant,
+        merge_operators: MergeOperators,
+    ) -> Self {
+        let inner = TenantInner::new(name, options, versions_tenant, merge_operators);
         Self {
             inner: Arc::new(inner),
         }
     }
 
+    /// Opens a tenant with the buckets in the current version.
+    pub async fn open(
+        name: String,
+        options: TenantOptions,
+        versions_tenant: VersionTenant,
+        merge_operators: MergeOperators,
+    ) -> Result<Self> {
+        let mut buckets = HashMap::new();
+        for bucket in versions_tenant.buckets().await? {
+            let version_bucket = versions_tenant.bucket(&bucket).await?;
+            let bucket_options = BucketOptions::decode(version_bucket.options().await?.as_slice())
+                .map_err(|err| Error::corrupted(format!("bucket {} options: {}", bucket, err)))?;
+            let value = Bucket::new(
+                bucket.clone(),
+                name.clone(),
+                bucket_options,
+                version_bucket,
+                merge_operators.clone(),
+            );
+            buckets.insert(bucket, value);
+        }
+        let tenant = Self::new(name, options, versions_tenant, merge_operators);
+        *tenant.inner.buckets.lock().await = buckets;
+        Ok(tenant)
+    }
+
     pub fn name(&self) -> &str {
         &self.inner.name
     }
@@ -44,14 +76,41 @@ impl Tenant {
         self.inner.bucket(name).await
     }
 
+    pub async fn buckets(&self) -> Vec<Bucket> {
+        let buckets = self.inner.buckets.lock().await;
+        buckets.values().cloned().collect()
+    }
+
     pub(crate) async fn create_bucket(&self, name: &str, options: BucketOptions) -> Result<Bucket> {
         self.inner.create_bucket(name, options).await
     }
 
+    pub(crate) async fn update_bucket(&self, name: &str, options: BucketOptions) -> Result<Bucket> {
+        self.inner.update_bucket(name, options).await
+    }
+
+    pub(crate) async fn delete_bucket(&self, name: &str) -> Result<()> {
+        self.inner.delete_bucket(name).await
+    }
+
+    pub(crate) async fn update(&self, options: TenantOptions) -> Result<()> {
+        let mut current = self.inner.options.lock().await;
+        self.inner
+            .versions_tenant
+            .update_options(options.encode_to_vec())
+            .await?;
+        *current = options;
+        Ok(())
+    }
+
     pub async fn add_files(&self, files: Vec<VersionEditFile>) -> Result<()> {
         self.inner.versions_tenant.add_files(files).await
     }
 
+    pub(crate) async fn delete_obsolete_files(&self) -> Result<()> {
+        self.inner.versions_tenant.delete_obsolete_files().await
+    }
+
     pub async fn get_next_file_num(&self, count: u64) -> Result<Vec<u64>> {
         self.inner.versions_tenant.get_next_file_nums(count).await
     }
@@ -59,18 +118,25 @@ impl Tenant {
 
 struct TenantInner {
     name: String,
-    options: TenantOptions,
+    options: Mutex<TenantOptions>,
     buckets: Mutex<HashMap<String, Bucket>>,
     versions_tenant: VersionTenant,
+    merge_operators: MergeOperators,
 }
 
 impl TenantInner {
-    fn new(name: String, options: TenantOptions, versions_tenant: VersionTenant) -> Self {
+    fn new(
+        name: String,
+        options: TenantOptions,
+        versions_tenant: VersionTenant,
+        merge_operators: MergeOperators,
+    ) -> Self {
         Self {
             name,
-            options,
+            options: Mutex::new(options),
             buckets: Mutex::new(HashMap::new()),
             versions_tenant,
+            merge_operators,
         }
     }
 
@@ -81,7 +147,7 @@ impl TenantInner {
         };
         TenantDesc {
             name: self.name.clone(),
-            options: Some(self.options.clone()),
+            options: Some(self.options.lock().await.clone()),
             properties: Some(properties),
         }
     }
@@ -99,10 +165,41 @@ impl TenantInner {
         if buckets.contains_key(name) {
             return Err(Error::AlreadyExists(format!("bucket {}", name)));
         }
-        self.versions_tenant.create_bucket(name).await?;
+        self.versions_tenant
+            .create_bucket(name, options.encode_to_vec())
+            .await?;
         let versioin_bucket = self.versions_tenant.bucket(name).await?;
-        let bucket = Bucket::new(name.to_owned(), self.name.clone(), options, versioin_bucket);
+        let bucket = Bucket::new(
+            name.to_owned(),
+            self.name.clone(),
+            options,
+            versioin_bucket,
+            self.merge_operators.clone(),
+        );
         buckets.insert(name.to_owned(), bucket.clone());
         Ok(bucket)
     }
+
+    async fn update_bucket(&self, name: &str, options: BucketOptions) -> Result<Bucket> {
+        let buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("bucket {}", name)))?;
+        self.versions_tenant
+            .update_bucket(name, options.encode_to_vec())
+            .await?;
+        bucket.update(options).await;
+        Ok(bucket)
+    }
+
+    async fn delete_bucket(&self, name: &str) -> Result<()> {
+        let mut buckets = self.buckets.lock().await;
+        if !buckets.contains_key(name) {
+            return Err(Error::NotFound(format!("bucket {}", name)));
+        }
+        self.versions_tenant.delete_bucket(name).await?;
+        buckets.remove(name);
+        Ok(())
+    }
 }
@@ -68,8 +68,11 @@ enum Command {
     },
 }
 
-async fn list_streams(_tenant: &Tenant) -> Result<()> {
-    todo!();
+async fn list_streams(tenant: &Tenant) -> Result<()> {
+    for desc in tenant.list_streams().await? {
+        println!("{} {}", desc.id, desc.name);
+    }
+    Ok(())
 }
 
 async fn create_stream(tenant: &Tenant, stream_name: String) -> Result<()> {
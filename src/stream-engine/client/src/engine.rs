@@ -21,6 +21,7 @@ use std::{
     thread::{self, JoinHandle},
 };
 
+use stream_engine_proto::TenantDesc;
 use tokio::{
     runtime::Handle as RuntimeHandle,
     sync::{mpsc, Mutex},
@@ -54,6 +55,11 @@ impl Engine {
         Tenant::new(name, self.to_owned())
     }
 
+    #[inline(always)]
+    pub async fn list_tenants(&self) -> Result<Vec<TenantDesc>> {
+        self.master.list_tenants().await
+    }
+
     #[inline(always)]
     pub async fn create_tenant(&self, name: &str) -> Result<Tenant> {
         let tenant_client = self.master.create_tenant(name).await?;
@@ -101,6 +107,8 @@ impl Engine {
         let mut inner = self.inner.lock().await;
         if let Some((join_handle, flag)) = inner.worker_handle.take() {
             flag.store(true, Ordering::Release);
+            // The worker might be waiting for events.
+            inner.active_channel.shutdown();
             join_handle.join().unwrap_or_default();
         }
     }
@@ -138,6 +146,34 @@ impl Drop for EngineInner {
     fn drop(&mut self) {
         if let Some((_, flag)) = self.worker_handle.take() {
             flag.store(true, Ordering::Release);
+            self.active_channel.shutdown();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use stream_engine_master::build_master;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn close_idle_engine() -> Result<()> {
+        let master_addr = build_master(&[]).await?;
+        let engine = Engine::new("1".to_owned(), master_addr).await?;
+
+        // The worker waits for events since there is no stream.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let runtime = RuntimeHandle::current();
+        thread::spawn(move || {
+            runtime.block_on(engine.close());
+            sender.send(()).unwrap_or_default();
+        });
+        receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("close must not block");
+        Ok(())
+    }
+}
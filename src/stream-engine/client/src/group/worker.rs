@@ -65,6 +65,8 @@ enum Action {
         stream_id: u64,
         sender: oneshot::Sender<Result<()>>,
     },
+    /// Wakes up the worker so that it notices the exit flag.
+    Shutdown,
 }
 
 struct ActionChannelState {
@@ -150,6 +152,11 @@ impl ActionChannel {
         self.submit(Action::Remove { stream_id, sender });
         receiver
     }
+
+    #[inline(always)]
+    pub(crate) fn shutdown(&self) {
+        self.submit(Action::Shutdown);
+    }
 }
 
 struct SelectorState {
@@ -290,6 +297,7 @@ impl Worker {
                             .unwrap_or_default();
                     }
                 }
+                Action::Shutdown => {}
             }
         }
     }
@@ -24,7 +24,7 @@ pub use self::{
     stream::{ObserverMeta, Stream},
     tenant::Tenant,
 };
-use crate::Result;
+use crate::{Error, Result};
 
 #[derive(Clone)]
 pub struct Master {
@@ -43,6 +43,17 @@ impl Master {
         Tenant::new(name.to_owned(), self.master_client.clone())
     }
 
+    pub async fn list_tenants(&self) -> Result<Vec<TenantDesc>> {
+        let req = ListTenantsRequest {};
+        let req = tenant_request_union::Request::ListTenants(req);
+        let res = self.master_client.tenant_union(req).await?;
+        if let tenant_response_union::Response::ListTenants(res) = res {
+            Ok(res.descs)
+        } else {
+            Err(Error::InvalidResponse)
+        }
+    }
+
     pub async fn create_tenant(&self, name: &str) -> Result<Tenant> {
         let desc = TenantDesc {
             name: name.to_owned(),
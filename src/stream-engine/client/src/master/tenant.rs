@@ -49,7 +49,18 @@ impl Tenant {
         desc.ok_or(Error::InvalidResponse)
     }
 
-    pub async fn stream(&self, name: &str) -> Result<Stream> {
+    pub async fn list_streams(&self) -> Result<Vec<StreamDesc>> {
+        let req = ListStreamsRequest {};
+        let req = stream_request_union::Request::ListStreams(req);
+        let res = self.inner.stream_union_call(req).await?;
+        if let stream_response_union::Response::ListStreams(res) = res {
+            Ok(res.descs)
+        } else {
+            Err(Error::InvalidResponse)
+        }
+    }
+
+    pub async fn stream_desc(&self, name: &str) -> Result<StreamDesc> {
         let req = DescribeStreamRequest {
             name: name.to_owned(),
         };
@@ -60,7 +71,11 @@ impl Tenant {
         } else {
             None
         };
-        let stream_desc = desc.ok_or(Error::InvalidResponse)?;
+        desc.ok_or(Error::InvalidResponse)
+    }
+
+    pub async fn stream(&self, name: &str) -> Result<Stream> {
+        let stream_desc = self.stream_desc(name).await?;
         Ok(self.inner.new_stream_client(stream_desc))
     }
 
@@ -162,4 +177,46 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn list_and_delete() -> Result<()> {
+        let master_addr = build_master(&[]).await?;
+
+        let master = Master::new(&master_addr).await?;
+        master.create_tenant("tenant").await?;
+        let names: Vec<String> = master
+            .list_tenants()
+            .await?
+            .into_iter()
+            .map(|desc| desc.name)
+            .collect();
+        assert_eq!(names, vec!["tenant".to_owned()]);
+
+        let tenant = master.tenant("tenant");
+        tenant.create_stream("a").await?;
+        tenant.create_stream("b").await?;
+        let mut names: Vec<String> = tenant
+            .list_streams()
+            .await?
+            .into_iter()
+            .map(|desc| desc.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a".to_owned(), "b".to_owned()]);
+
+        tenant.delete_stream("a").await?;
+        match tenant.stream_desc("a").await {
+            Err(Error::NotFound(_)) => {}
+            _ => panic!("stream must be deleted"),
+        }
+        assert_eq!(tenant.list_streams().await?.len(), 1);
+
+        master.delete_tenant("tenant").await?;
+        assert!(master.list_tenants().await?.is_empty());
+        match master.delete_tenant("tenant").await {
+            Err(Error::NotFound(_)) => {}
+            _ => panic!("tenant must be deleted"),
+        }
+        Ok(())
+    }
 }
@@ -44,6 +44,16 @@ impl Tenant {
         self.inner.tenant_client.desc().await
     }
 
+    #[inline(always)]
+    pub async fn list_streams(&self) -> Result<Vec<StreamDesc>> {
+        self.inner.tenant_client.list_streams().await
+    }
+
+    #[inline(always)]
+    pub async fn stream_desc(&self, name: &str) -> Result<StreamDesc> {
+        self.inner.tenant_client.stream_desc(name).await
+    }
+
     #[inline(always)]
     pub async fn stream(&self, name: &str) -> Result<Stream> {
         let stream_client = self.inner.tenant_client.stream(name).await?;
@@ -38,7 +38,7 @@ pub enum Error {
     #[error("{0}")]
     Corruption(String),
     #[error(transparent)]
-    Unknown(Box<dyn std::error::Error + Send>),
+    Unknown(Box<dyn std::error::Error + Send + Sync>),
 }
 
 #[must_use = "this `Result` may be an `Err` variant, which should be handled"]
@@ -23,7 +23,7 @@ pub use stream_engine_common::{
 #[cfg(debug_assertions)]
 pub use tests::build_master;
 
-pub use self::server::Server;
+pub use self::{master::Config, server::Server};
 
 #[cfg(debug_assertions)]
 pub mod tests {
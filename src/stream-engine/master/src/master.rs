@@ -102,6 +102,15 @@ impl Master {
         inner.tenants.insert(desc.name.clone(), db);
         Ok(desc)
     }
+
+    pub async fn delete_tenant(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner
+            .tenants
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::NotFound(format!("tenant {}", name)))
+    }
 }
 
 #[derive(Clone)]
@@ -180,4 +189,16 @@ impl Tenant {
         );
         Ok(desc)
     }
+
+    pub async fn delete_stream(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let stream_id = inner
+            .streams
+            .values()
+            .find(|info| info.stream_name == name)
+            .map(|info| info.stream_id)
+            .ok_or_else(|| Error::NotFound(format!("stream {}", name)))?;
+        inner.streams.remove(&stream_id);
+        Ok(())
+    }
 }
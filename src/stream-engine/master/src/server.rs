@@ -30,8 +30,12 @@ pub struct Server {
 impl Server {
     // FIXME(w41ter) Support address lookup
     pub fn new(stores: Vec<String>) -> Self {
+        Self::with_config(Config::default(), stores)
+    }
+
+    pub fn with_config(config: Config, stores: Vec<String>) -> Self {
         Self {
-            master: Master::new(Config::default(), stores),
+            master: Master::new(config, stores),
         }
     }
 
@@ -124,8 +128,9 @@ impl Server {
             Request::UpdateTenant(_req) => {
                 todo!()
             }
-            Request::DeleteTenant(_req) => {
-                todo!()
+            Request::DeleteTenant(req) => {
+                let res = self.handle_delete_tenant(req).await?;
+                Response::DeleteTenant(res)
             }
             Request::DescribeTenant(req) => {
                 let res = self.handle_describe_tenant(req).await?;
@@ -151,6 +156,11 @@ impl Server {
         Ok(CreateTenantResponse { desc: Some(desc) })
     }
 
+    async fn handle_delete_tenant(&self, req: DeleteTenantRequest) -> Result<DeleteTenantResponse> {
+        self.master.delete_tenant(&req.name).await?;
+        Ok(DeleteTenantResponse {})
+    }
+
     async fn handle_describe_tenant(
         &self,
         req: DescribeTenantRequest,
@@ -195,8 +205,9 @@ impl Server {
             Request::UpdateStream(_req) => {
                 todo!()
             }
-            Request::DeleteStream(_req) => {
-                todo!()
+            Request::DeleteStream(req) => {
+                let res = self.handle_delete_stream(tenant, req).await?;
+                Response::DeleteStream(res)
             }
             Request::DescribeStream(req) => {
                 let res = self.handle_describe_stream(tenant, req).await?;
@@ -229,6 +240,15 @@ impl Server {
         Ok(CreateStreamResponse { desc: Some(desc) })
     }
 
+    async fn handle_delete_stream(
+        &self,
+        tenant: Tenant,
+        req: DeleteStreamRequest,
+    ) -> Result<DeleteStreamResponse> {
+        tenant.delete_stream(&req.name).await?;
+        Ok(DeleteStreamResponse {})
+    }
+
     async fn handle_describe_stream(
         &self,
         tenant: Tenant,
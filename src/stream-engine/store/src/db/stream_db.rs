@@ -182,6 +182,15 @@ impl StreamDb {
         })
     }
 
+    /// Closes the database, the submitted writes are persisted and the log
+    /// and manifest files are flushed.
+    ///
+    /// Writes issued after closing fail.
+    pub fn close(&self) -> Result<()> {
+        self.log_engine.close();
+        self.version_set.flush()
+    }
+
     #[inline(always)]
     fn create<P: AsRef<Path>>(base_dir: P) -> Result<()> {
         VersionSet::create(base_dir)
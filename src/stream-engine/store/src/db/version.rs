@@ -244,6 +244,13 @@ impl VersionSet {
         self.core.lock().unwrap().version.clone()
     }
 
+    /// Flushes the manifest writer.
+    pub fn flush(&self) -> Result<()> {
+        let mut core = self.core.lock().unwrap();
+        core.writer.flush()?;
+        Ok(())
+    }
+
     pub fn set_next_file_number(&self, file_number: u64) {
         let mut core = self.core.lock().unwrap();
         debug_assert!(core.next_file_number < file_number);
@@ -37,6 +37,7 @@ struct Request {
 struct ChannelCore {
     requests: Vec<Request>,
     waitting: bool,
+    closed: bool,
 }
 
 #[derive(Clone)]
@@ -51,6 +52,7 @@ impl Channel {
                 Mutex::new(ChannelCore {
                     requests: Vec::new(),
                     waitting: false,
+                    closed: false,
                 }),
                 Condvar::new(),
             )),
@@ -69,6 +71,13 @@ impl Channel {
     fn append(&self, record: Record) -> oneshot::Receiver<IoKindResult<u64>> {
         let (sender, receiver) = oneshot::channel();
         let mut core = self.core.0.lock().unwrap();
+        if core.closed {
+            // The worker has exited, or will exit without taking this record.
+            sender
+                .send(Err(std::io::ErrorKind::BrokenPipe))
+                .unwrap_or_default();
+            return receiver;
+        }
         core.requests.push(Request {
             sender,
             record: Some(record),
@@ -83,6 +92,7 @@ impl Channel {
     fn shutdown(&self) {
         let (sender, _) = oneshot::channel();
         let mut core = self.core.0.lock().unwrap();
+        core.closed = true;
         core.requests.push(Request {
             sender,
             record: None,
@@ -157,18 +167,24 @@ impl LogEngine {
     pub fn add_record(&self, record: Record) -> oneshot::Receiver<IoKindResult<u64>> {
         self.channel.append(record)
     }
-}
 
-impl Drop for LogEngine {
-    fn drop(&mut self) {
+    /// Stops the log worker after the submitted records are written, and
+    /// flushes the active log file.
+    pub fn close(&self) {
         let mut core = self.core.lock().unwrap();
-        self.channel.shutdown();
         if let Some(handle) = core.worker_handle.take() {
+            self.channel.shutdown();
             handle.join().unwrap_or_default();
         }
     }
 }
 
+impl Drop for LogEngine {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
 struct LogEngineCore {
     worker_handle: Option<JoinHandle<()>>,
 }
@@ -250,6 +266,10 @@ impl LogWorker {
                 self.notify_grouped_requests(Ok(self.writer.log_number()));
             }
         }
+
+        if let Err(err) = self.writer.flush() {
+            tracing::error!("log {} flush: {}", self.writer.log_number(), err);
+        }
     }
 
     fn submit_requests(&mut self, request_group: RecordGroup) -> IoResult<()> {
@@ -369,4 +389,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn log_engine_close() -> Result<()> {
+        let mut opt = DbOption::default();
+        opt.log.sync_data = false;
+        opt.log.log_file_size = 1024 * 1024;
+        let opt = Arc::new(opt);
+        let dir = new_tempdir()?;
+        let log_file_mgr = LogFileManager::new(&dir, 100, opt.clone());
+        let log_engine = LogEngine::recover(&dir, vec![], log_file_mgr, &mut |_, _| Ok(()))?;
+
+        let record = Record {
+            stream_id: 1,
+            epoch: 1,
+            writer_epoch: Some(1),
+            acked_seq: None,
+            first_index: Some(1),
+            entries: vec![Entry {
+                entry_type: EntryType::Event as i32,
+                epoch: 1,
+                event: vec![1, 2, 3],
+            }],
+        };
+        let pending = log_engine.add_record(record.clone());
+        log_engine.close();
+        // Records submitted before closing are written.
+        pending.await??;
+        match log_engine.add_record(record.clone()).await? {
+            Err(std::io::ErrorKind::BrokenPipe) => {}
+            _ => panic!("add record to a closed log engine must fail"),
+        }
+
+        let mut read_contents = vec![];
+        let log_file_mgr = LogFileManager::new(&dir, 100, opt.clone());
+        let reader = &mut |_, record| {
+            read_contents.push(record);
+            Ok(())
+        };
+        LogEngine::recover(&dir, vec![100], log_file_mgr, reader)?;
+        assert_eq!(read_contents, vec![record]);
+
+        Ok(())
+    }
 }